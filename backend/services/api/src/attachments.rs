@@ -0,0 +1,949 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use axum::body::Bytes;
+use base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine};
+use futures_util::{Stream, StreamExt};
+use http::StatusCode;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, DateTime, Document},
+    options::IndexOptions,
+    Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::crypto::{derive_key, hmac_hex, verify_hmac_hex};
+use crate::media::{self, MediaKind};
+
+const ATTACHMENTS_COLLECTION: &str = "attachments";
+
+/// Largest single file accepted by the store.
+pub const MAX_ATTACHMENT_BYTES: usize = 15 * 1024 * 1024;
+
+/// Signed download URLs stay valid for at least this long. Expiry is rounded
+/// up to the hour so list responses stay byte-identical between polls.
+const URL_TTL_SECONDS: i64 = 60 * 60;
+
+const URL_SIGNING_CONTEXT: &[u8] = b"sis-teknik/attachment-url/v1";
+
+const THUMBNAIL_EXTENSION: &str = "jpg";
+
+/// An attachment claimed for a record this recently is never deleted, so the
+/// record update that follows the claim cannot end up pointing at nothing.
+const CLAIM_GRACE_MINUTES: i64 = 30;
+
+/// Bytes kept from the start of a streamed upload to identify its type.
+const SNIFF_BYTES: usize = 1024;
+
+/// Record fields that hold attachment ids (or, before migration, base64 data URLs).
+pub const MUSTERI_FILE_FIELDS: [&str; 4] = ["belge_f", "belge_g", "belge_u", "belge_a"];
+pub const MONTAJ_FILE_FIELDS: [&str; 1] = ["belge_f"];
+pub const MONTAJ_IMAGE_LIST_FIELD: &str = "kurulum_resimleri";
+
+/// Metadata of a stored file. The bytes live on disk under their SHA-256,
+/// so identical uploads share one blob.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Attachment {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub sha256: String,
+    pub size: i64,
+    pub content_type: String,
+    /// Extension of the blob on disk, from the sniffed type ("" for files
+    /// stored before types were checked).
+    #[serde(default)]
+    pub extension: String,
+    pub filename: Option<String>,
+    /// SHA-256 of the JPEG thumbnail blob, if one could be generated.
+    #[serde(default)]
+    pub thumbnail_sha256: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime,
+    /// Last time the file was uploaded or linked to a record; see `claim`.
+    #[serde(default)]
+    pub claimed_at: Option<DateTime>,
+}
+
+impl Attachment {
+    fn new(
+        sha256: String,
+        size: usize,
+        content_type: &str,
+        extension: &str,
+        filename: Option<String>,
+        actor: &str,
+    ) -> Self {
+        let now = DateTime::now();
+        Self {
+            id: ObjectId::new(),
+            sha256,
+            size: size as i64,
+            content_type: content_type.to_string(),
+            extension: extension.to_string(),
+            filename,
+            thumbnail_sha256: None,
+            created_by: actor.to_string(),
+            created_at: now,
+            claimed_at: Some(now),
+        }
+    }
+}
+
+/// Which URL a file field is turned into in API responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileLink {
+    Full,
+    Thumbnail,
+}
+
+#[derive(Clone)]
+pub struct AttachmentStore {
+    root: PathBuf,
+    url_key: Vec<u8>,
+    max_image_dimension: u32,
+}
+
+/// Decodes a `data:<type>;base64,<payload>` URL (or bare base64) to bytes.
+pub fn decode_data_url_image(payload: &str) -> Result<Vec<u8>, String> {
+    let content = payload.trim();
+    if content.is_empty() {
+        return Err("Boş dosya içeriği".to_string());
+    }
+
+    let encoded = if let Some(idx) = content.find(",") {
+        &content[(idx + 1)..]
+    } else {
+        content
+    };
+
+    BASE64_STANDARD
+        .decode(encoded)
+        .map_err(|_| "Base64 görsel decode edilemedi".to_string())
+}
+
+/// MIME type declared in a data URL prefix, e.g. `image/png`.
+fn data_url_content_type(payload: &str) -> Option<String> {
+    let header = payload.trim().strip_prefix("data:")?;
+    let header = &header[..header.find([';', ','])?];
+    if header.is_empty() {
+        None
+    } else {
+        Some(header.to_ascii_lowercase())
+    }
+}
+
+fn is_attachment_id(value: &str) -> bool {
+    value.len() == 24 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Attachment ids the file fields of a `musteri_kabul` or `montaj_kayitlari`
+/// document point at.
+pub fn record_attachment_ids(record: &Document) -> Vec<String> {
+    let mut ids: Vec<String> = MUSTERI_FILE_FIELDS
+        .iter()
+        .filter_map(|field| record.get_str(field).ok())
+        .filter(|value| is_attachment_id(value))
+        .map(str::to_string)
+        .collect();
+    if let Ok(values) = record.get_array(MONTAJ_IMAGE_LIST_FIELD) {
+        ids.extend(
+            values
+                .iter()
+                .filter_map(Bson::as_str)
+                .filter(|value| is_attachment_id(value))
+                .map(str::to_string),
+        );
+    }
+    ids
+}
+
+/// Ids that were on `previous` but are gone once `set` is applied, i.e. the
+/// files an update replaced or cleared.
+pub fn replaced_attachment_ids(previous: &Document, set: &Document) -> Vec<String> {
+    let mut touched = Document::new();
+    for field in MUSTERI_FILE_FIELDS.iter().chain([&MONTAJ_IMAGE_LIST_FIELD]) {
+        if let Some(value) = previous.get(*field).filter(|_| set.contains_key(*field)) {
+            touched.insert(*field, value.clone());
+        }
+    }
+    let kept = record_attachment_ids(set);
+    record_attachment_ids(&touched)
+        .into_iter()
+        .filter(|id| !kept.contains(id))
+        .collect()
+}
+
+/// Attachment id referenced by a field value: a bare id or one of our own
+/// download URLs (clients echo back what the API returned).
+fn referenced_attachment_id(value: &str) -> Option<&str> {
+    let value = value.trim();
+    if is_attachment_id(value) {
+        return Some(value);
+    }
+
+    let rest = &value[value.find("/api/attachments/")? + "/api/attachments/".len()..];
+    let id = &rest[..rest.find(['?', '/']).unwrap_or(rest.len())];
+    is_attachment_id(id).then_some(id)
+}
+
+fn too_large() -> (StatusCode, String) {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Dosya en fazla {} MB olabilir", MAX_ATTACHMENT_BYTES / 1024 / 1024),
+    )
+}
+
+/// The type of an upload from its first bytes, if it is one of `allowed`.
+fn sniff_allowed(head: &[u8], allowed: &[MediaKind]) -> Result<MediaKind, (StatusCode, String)> {
+    media::sniff(head)
+        .filter(|kind| allowed.contains(kind))
+        .ok_or_else(|| {
+            (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                format!("Desteklenmeyen dosya türü; {} yükleyin", media::allowed_label(allowed)),
+            )
+        })
+}
+
+/// Filter for attachments not uploaded or claimed since `cutoff`.
+fn unclaimed_since(cutoff: DateTime) -> Document {
+    doc! {
+        "$or": [
+            { "claimed_at": { "$lt": cutoff } },
+            { "claimed_at": null, "created_at": { "$lt": cutoff } },
+        ]
+    }
+}
+
+fn minutes_ago(minutes: i64) -> DateTime {
+    DateTime::from_millis((chrono::Utc::now() - chrono::Duration::minutes(minutes)).timestamp_millis())
+}
+
+fn sha256_hex(digest: impl AsRef<[u8]>) -> String {
+    digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub async fn ensure_indexes(db: &Database) -> Result<(), String> {
+    db.collection::<Document>(ATTACHMENTS_COLLECTION)
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "sha256": 1 })
+                .options(IndexOptions::builder().name("sha256_1".to_string()).build())
+                .build(),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Attachment index error: {}", e))
+}
+
+impl AttachmentStore {
+    /// `url_secret` signs download URLs; a purpose-specific key is derived from it.
+    /// Uploaded photos are downscaled to `max_image_dimension` pixels.
+    pub fn new(root: impl Into<PathBuf>, url_secret: &str, max_image_dimension: u32) -> Self {
+        Self {
+            root: root.into(),
+            url_key: derive_key(url_secret.as_bytes(), URL_SIGNING_CONTEXT),
+            max_image_dimension,
+        }
+    }
+
+    fn blob_path(&self, sha256: &str, extension: &str) -> PathBuf {
+        let name = if extension.is_empty() {
+            sha256.to_string()
+        } else {
+            format!("{}.{}", sha256, extension)
+        };
+        self.root.join(&sha256[..2]).join(name)
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        self.root.join("tmp").join(uuid::Uuid::new_v4().to_string())
+    }
+
+    /// Moves a finished temp file to its content address (or drops it if the
+    /// same content is already stored).
+    async fn commit_blob(&self, temp: &Path, sha256: &str, extension: &str) -> Result<(), String> {
+        let target = self.blob_path(sha256, extension);
+        if tokio::fs::try_exists(&target).await.unwrap_or(false) {
+            let _ = tokio::fs::remove_file(temp).await;
+            return Ok(());
+        }
+
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Dosya dizini oluşturulamadı: {}", e))?;
+        }
+        tokio::fs::rename(temp, &target)
+            .await
+            .map_err(|e| format!("Dosya kaydedilemedi: {}", e))
+    }
+
+    /// Writes a blob to its content address and returns its SHA-256.
+    async fn write_blob(&self, bytes: &[u8], extension: &str) -> Result<String, String> {
+        let sha256 = sha256_hex(Sha256::digest(bytes));
+        let temp = self.temp_path();
+        if let Some(parent) = temp.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Dosya dizini oluşturulamadı: {}", e))?;
+        }
+        tokio::fs::write(&temp, bytes)
+            .await
+            .map_err(|e| format!("Dosya yazılamadı: {}", e))?;
+        self.commit_blob(&temp, &sha256, extension).await?;
+        Ok(sha256)
+    }
+
+    async fn insert(&self, db: &Database, attachment: &Attachment) -> Result<(), String> {
+        db.collection::<Attachment>(ATTACHMENTS_COLLECTION)
+            .insert_one(attachment)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Stores bytes as they are, without type checks (migrations).
+    async fn put_bytes(
+        &self,
+        db: &Database,
+        bytes: &[u8],
+        content_type: &str,
+        extension: &str,
+        filename: Option<String>,
+        actor: &str,
+    ) -> Result<Attachment, String> {
+        let sha256 = self.write_blob(bytes, extension).await?;
+        let attachment = Attachment::new(sha256, bytes.len(), content_type, extension, filename, actor);
+        self.insert(db, &attachment).await?;
+        Ok(attachment)
+    }
+
+    /// Checks an uploaded file against `allowed` by its magic bytes, then
+    /// normalizes it (see `media::normalize`) and stores the result with the
+    /// sniffed content type and extension, plus a thumbnail when possible.
+    pub async fn put_upload(
+        &self,
+        db: &Database,
+        bytes: Vec<u8>,
+        allowed: &[MediaKind],
+        filename: Option<String>,
+        actor: &str,
+    ) -> Result<Attachment, (StatusCode, String)> {
+        if bytes.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "Boş dosya yüklenemez".to_string()));
+        }
+        if bytes.len() > MAX_ATTACHMENT_BYTES {
+            return Err(too_large());
+        }
+
+        let kind = sniff_allowed(&bytes, allowed)?;
+        self.put_normalized(db, bytes, kind, filename, actor).await
+    }
+
+    async fn put_normalized(
+        &self,
+        db: &Database,
+        bytes: Vec<u8>,
+        kind: MediaKind,
+        filename: Option<String>,
+        actor: &str,
+    ) -> Result<Attachment, (StatusCode, String)> {
+        let max_dimension = self.max_image_dimension;
+        let normalized = tokio::task::spawn_blocking(move || media::normalize(bytes, kind, max_dimension))
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Görsel işlenemedi: {}", e)))?
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+        let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+        let kind = normalized.kind;
+
+        let sha256 = self
+            .write_blob(&normalized.bytes, kind.extension())
+            .await
+            .map_err(internal)?;
+        let mut attachment = Attachment::new(
+            sha256,
+            normalized.bytes.len(),
+            kind.content_type(),
+            kind.extension(),
+            filename,
+            actor,
+        );
+        if let Some(thumbnail) = normalized.thumbnail {
+            attachment.thumbnail_sha256 = Some(
+                self.write_blob(&thumbnail, THUMBNAIL_EXTENSION)
+                    .await
+                    .map_err(internal)?,
+            );
+        }
+
+        self.insert(db, &attachment).await.map_err(internal)?;
+        Ok(attachment)
+    }
+
+    /// Writes a request body to disk chunk by chunk, hashing as it goes, and
+    /// checks its type from the first bytes. Files stored as uploaded (see
+    /// `media::is_stored_as_is`) are committed straight from the temp file;
+    /// only photos are read back into memory to be normalized.
+    pub async fn put_stream<S, E>(
+        &self,
+        db: &Database,
+        mut stream: S,
+        allowed: &[MediaKind],
+        filename: Option<String>,
+        actor: &str,
+    ) -> Result<Attachment, (StatusCode, String)>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+
+        let temp = self.temp_path();
+        if let Some(parent) = temp.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| internal(format!("Dosya dizini oluşturulamadı: {}", e)))?;
+        }
+        let mut file = tokio::fs::File::create(&temp)
+            .await
+            .map_err(|e| internal(format!("Dosya oluşturulamadı: {}", e)))?;
+
+        let mut hasher = Sha256::new();
+        let mut head: Vec<u8> = Vec::with_capacity(SNIFF_BYTES);
+        let mut kind: Option<MediaKind> = None;
+        let mut size = 0usize;
+        let mut failure: Option<(StatusCode, String)> = None;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    failure = Some((StatusCode::BAD_REQUEST, format!("Yükleme okunamadı: {}", e)));
+                    break;
+                }
+            };
+
+            size += chunk.len();
+            if size > MAX_ATTACHMENT_BYTES {
+                failure = Some(too_large());
+                break;
+            }
+
+            // Refuse unsupported types as soon as enough of the file is in
+            if kind.is_none() {
+                let take = (SNIFF_BYTES - head.len()).min(chunk.len());
+                head.extend_from_slice(&chunk[..take]);
+                if head.len() == SNIFF_BYTES {
+                    match sniff_allowed(&head, allowed) {
+                        Ok(sniffed) => kind = Some(sniffed),
+                        Err(e) => {
+                            failure = Some(e);
+                            break;
+                        }
+                    }
+                }
+            }
+
+            hasher.update(&chunk);
+            if let Err(e) = file.write_all(&chunk).await {
+                failure = Some(internal(format!("Dosya yazılamadı: {}", e)));
+                break;
+            }
+        }
+
+        if failure.is_none() && size == 0 {
+            failure = Some((StatusCode::BAD_REQUEST, "Boş dosya yüklenemez".to_string()));
+        }
+        if failure.is_none() && kind.is_none() {
+            match sniff_allowed(&head, allowed) {
+                Ok(sniffed) => kind = Some(sniffed),
+                Err(e) => failure = Some(e),
+            }
+        }
+        if failure.is_none() {
+            if let Err(e) = file.flush().await {
+                failure = Some(internal(format!("Dosya yazılamadı: {}", e)));
+            }
+        }
+        drop(file);
+
+        let kind = match (failure, kind) {
+            (None, Some(kind)) => kind,
+            (failure, _) => {
+                let _ = tokio::fs::remove_file(&temp).await;
+                return Err(failure.unwrap_or_else(|| internal("Dosya türü belirlenemedi".to_string())));
+            }
+        };
+
+        if !media::is_stored_as_is(kind) {
+            let bytes = tokio::fs::read(&temp).await;
+            let _ = tokio::fs::remove_file(&temp).await;
+            let bytes = bytes.map_err(|e| internal(format!("Dosya okunamadı: {}", e)))?;
+            return self.put_normalized(db, bytes, kind, filename, actor).await;
+        }
+
+        let sha256 = sha256_hex(hasher.finalize());
+        if let Err(e) = self.commit_blob(&temp, &sha256, kind.extension()).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(internal(e));
+        }
+
+        let attachment = Attachment::new(sha256, size, kind.content_type(), kind.extension(), filename, actor);
+        self.insert(db, &attachment).await.map_err(internal)?;
+        Ok(attachment)
+    }
+
+    /// Stores a base64 data URL as a new attachment and returns its id.
+    pub async fn store_data_url(
+        &self,
+        db: &Database,
+        payload: &str,
+        allowed: &[MediaKind],
+        actor: &str,
+    ) -> Result<String, (StatusCode, String)> {
+        let bytes = decode_data_url_image(payload).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let attachment = self.put_upload(db, bytes, allowed, None, actor).await?;
+        Ok(attachment.id.to_hex())
+    }
+
+    /// Resolves a file field sent by a staff client to the value to store:
+    /// "" clears it, an existing attachment id/URL is kept and a data URL is
+    /// stored as a new attachment. Either way the file must be of an `allowed` type.
+    /// Existing ids are only accepted if they are in `current` (already on the
+    /// record) or were uploaded by `actor`, so other records' files can't be linked.
+    pub async fn resolve_field_input(
+        &self,
+        db: &Database,
+        value: &str,
+        allowed: &[MediaKind],
+        current: &[String],
+        actor: &str,
+    ) -> Result<String, (StatusCode, String)> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(String::new());
+        }
+
+        if let Some(id) = referenced_attachment_id(value) {
+            let attachment = self
+                .get(db, id)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+                .filter(|attachment| current.iter().any(|own| own == id) || attachment.created_by == actor)
+                .ok_or_else(|| (StatusCode::BAD_REQUEST, format!("Ek bulunamadı: {}", id)))?;
+
+            if !MediaKind::from_content_type(&attachment.content_type).is_some_and(|kind| allowed.contains(&kind)) {
+                return Err((
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    format!("Desteklenmeyen dosya türü; {} yükleyin", media::allowed_label(allowed)),
+                ));
+            }
+            if !self
+                .claim(db, attachment.id)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+            {
+                return Err((StatusCode::BAD_REQUEST, format!("Ek bulunamadı: {}", id)));
+            }
+            return Ok(attachment.id.to_hex());
+        }
+
+        self.store_data_url(db, value, allowed, actor).await
+    }
+
+    pub async fn get(&self, db: &Database, id: &str) -> Result<Option<Attachment>, String> {
+        let Ok(object_id) = ObjectId::parse_str(id) else {
+            return Ok(None);
+        };
+
+        db.collection::<Attachment>(ATTACHMENTS_COLLECTION)
+            .find_one(doc! { "_id": object_id })
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    pub async fn open(&self, attachment: &Attachment) -> Result<tokio::fs::File, String> {
+        tokio::fs::File::open(self.blob_path(&attachment.sha256, &attachment.extension))
+            .await
+            .map_err(|e| format!("Dosya açılamadı: {}", e))
+    }
+
+    /// Thumbnail blob of an attachment. Files stored before thumbnails existed
+    /// get one generated on first request; `None` for HEIC/PDF or unreadable images.
+    pub async fn thumbnail_path(&self, db: &Database, attachment: &Attachment) -> Result<Option<PathBuf>, String> {
+        if let Some(sha256) = &attachment.thumbnail_sha256 {
+            return Ok(Some(self.blob_path(sha256, THUMBNAIL_EXTENSION)));
+        }
+
+        let Some(kind) = MediaKind::from_content_type(&attachment.content_type) else {
+            return Ok(None);
+        };
+        let bytes = tokio::fs::read(self.blob_path(&attachment.sha256, &attachment.extension))
+            .await
+            .map_err(|e| format!("Dosya okunamadı: {}", e))?;
+
+        let generated = tokio::task::spawn_blocking(move || media::thumbnail(&bytes, kind))
+            .await
+            .map_err(|e| format!("Önizleme oluşturulamadı: {}", e))?;
+        let thumbnail = match generated {
+            Ok(Some(thumbnail)) => thumbnail,
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                tracing::error!("Thumbnail generation failed ({}): {}", attachment.id, e);
+                return Ok(None);
+            }
+        };
+
+        let sha256 = self.write_blob(&thumbnail, THUMBNAIL_EXTENSION).await?;
+        db.collection::<Document>(ATTACHMENTS_COLLECTION)
+            .update_one(
+                doc! { "_id": attachment.id },
+                doc! { "$set": { "thumbnail_sha256": &sha256 } },
+            )
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        Ok(Some(self.blob_path(&sha256, THUMBNAIL_EXTENSION)))
+    }
+
+    /// Bytes of a file field value, whether it is an attachment id or a
+    /// not-yet-migrated data URL.
+    pub async fn load_field(&self, db: &Database, value: &str) -> Result<Vec<u8>, String> {
+        match referenced_attachment_id(value) {
+            Some(id) => {
+                let attachment = self
+                    .get(db, id)
+                    .await?
+                    .ok_or_else(|| format!("Ek bulunamadı: {}", id))?;
+                tokio::fs::read(self.blob_path(&attachment.sha256, &attachment.extension))
+                    .await
+                    .map_err(|e| format!("Dosya okunamadı: {}", e))
+            }
+            None => decode_data_url_image(value),
+        }
+    }
+
+    /// Marks an existing attachment as about to be linked to a record, which
+    /// keeps `delete_unreferenced` off it for `CLAIM_GRACE_MINUTES`. Returns
+    /// false if it has been deleted meanwhile.
+    async fn claim(&self, db: &Database, id: ObjectId) -> Result<bool, String> {
+        db.collection::<Document>(ATTACHMENTS_COLLECTION)
+            .update_one(doc! { "_id": id }, doc! { "$set": { "claimed_at": DateTime::now() } })
+            .await
+            .map(|result| result.matched_count > 0)
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Deletes those of `ids` that no record (trashed ones included) refers to
+    /// any more, and their blobs once no other attachment shares them.
+    /// Attachments uploaded or claimed in the last `CLAIM_GRACE_MINUTES` are
+    /// kept, so a record being linked to one concurrently keeps its file; the
+    /// orphan sweep gets them later. Returns how many attachments were deleted.
+    pub async fn delete_unreferenced(&self, db: &Database, ids: &[String]) -> Result<u64, String> {
+        let attachments = db.collection::<Attachment>(ATTACHMENTS_COLLECTION);
+        let mut deleted = 0;
+
+        for id in ids {
+            let Some(attachment) = self.get(db, id).await? else {
+                continue;
+            };
+            if is_referenced(db, id).await? {
+                continue;
+            }
+
+            // A claim made after the reference check lands inside the grace
+            // window and makes this match nothing
+            let mut filter = unclaimed_since(minutes_ago(CLAIM_GRACE_MINUTES));
+            filter.insert("_id", attachment.id);
+            let result = attachments
+                .delete_one(filter)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+            if result.deleted_count == 0 {
+                continue;
+            }
+            deleted += 1;
+            self.remove_blobs(db, &attachment).await?;
+        }
+
+        Ok(deleted)
+    }
+
+    /// Deletes an attachment the caller has just stored and not linked to any
+    /// record, e.g. when the record update it was meant for fails.
+    pub async fn discard(&self, db: &Database, attachment_id: &str) -> Result<(), String> {
+        let Some(attachment) = self.get(db, attachment_id).await? else {
+            return Ok(());
+        };
+        db.collection::<Attachment>(ATTACHMENTS_COLLECTION)
+            .delete_one(doc! { "_id": attachment.id })
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        self.remove_blobs(db, &attachment).await
+    }
+
+    /// Removes a deleted attachment's blobs once no other attachment shares them.
+    async fn remove_blobs(&self, db: &Database, attachment: &Attachment) -> Result<(), String> {
+        let attachments = db.collection::<Attachment>(ATTACHMENTS_COLLECTION);
+        let blobs = [
+            Some((attachment.sha256.as_str(), attachment.extension.as_str())),
+            attachment.thumbnail_sha256.as_deref().map(|sha256| (sha256, THUMBNAIL_EXTENSION)),
+        ];
+        for (sha256, extension) in blobs.into_iter().flatten() {
+            let shared = attachments
+                .count_documents(doc! { "$or": [{ "sha256": sha256 }, { "thumbnail_sha256": sha256 }] })
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+            if shared == 0 {
+                if let Err(e) = tokio::fs::remove_file(self.blob_path(sha256, extension)).await {
+                    tracing::error!("Attachment blob {} could not be removed: {}", sha256, e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes attachments that were uploaded (e.g. via `POST /api/attachments`)
+    /// or last claimed more than `older_than_hours` ago and that no record
+    /// refers to. Returns how many were deleted.
+    pub async fn sweep_unlinked(&self, db: &Database, older_than_hours: i64) -> Result<u64, String> {
+        let mut referenced: HashSet<String> = HashSet::new();
+        for collection_name in ["musteri_kabul", "montaj_kayitlari"] {
+            let projection: Document = MUSTERI_FILE_FIELDS
+                .iter()
+                .chain([&MONTAJ_IMAGE_LIST_FIELD])
+                .map(|field| (field.to_string(), Bson::Int32(1)))
+                .collect();
+            let mut cursor = db
+                .collection::<Document>(collection_name)
+                .find(doc! {})
+                .projection(projection)
+                .await
+                .map_err(|e| format!("Database error: {}", e))?;
+            while cursor.advance().await.map_err(|e| format!("Cursor error: {}", e))? {
+                let record: Document = cursor
+                    .deserialize_current()
+                    .map_err(|e| format!("Deserialize error: {}", e))?;
+                referenced.extend(record_attachment_ids(&record));
+            }
+        }
+
+        let mut cursor = db
+            .collection::<Document>(ATTACHMENTS_COLLECTION)
+            .find(unclaimed_since(minutes_ago(older_than_hours * 60)))
+            .projection(doc! { "_id": 1 })
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        let mut unlinked: Vec<String> = Vec::new();
+        while cursor.advance().await.map_err(|e| format!("Cursor error: {}", e))? {
+            let attachment: Document = cursor
+                .deserialize_current()
+                .map_err(|e| format!("Deserialize error: {}", e))?;
+            if let Ok(id) = attachment.get_object_id("_id") {
+                let id = id.to_hex();
+                if !referenced.contains(&id) {
+                    unlinked.push(id);
+                }
+            }
+        }
+
+        // Rechecked one by one, records may have changed since the scan
+        self.delete_unreferenced(db, &unlinked).await
+    }
+
+    fn expiry(&self) -> i64 {
+        let now = chrono::Utc::now().timestamp();
+        (now / URL_TTL_SECONDS + 2) * URL_TTL_SECONDS
+    }
+
+    /// Signed download URL. The signature covers the id only, so the same
+    /// query string is valid for both the file and its thumbnail.
+    pub fn signed_url(&self, id: &str, link: FileLink) -> String {
+        let exp = self.expiry();
+        let sig = hmac_hex(&self.url_key, format!("{}.{}", id, exp).as_bytes());
+        let suffix = match link {
+            FileLink::Full => "",
+            FileLink::Thumbnail => "/thumbnail",
+        };
+        format!("/api/attachments/{}{}?exp={}&sig={}", id, suffix, exp, sig)
+    }
+
+    pub fn verify_url(&self, id: &str, exp: i64, sig: &str) -> bool {
+        exp >= chrono::Utc::now().timestamp()
+            && verify_hmac_hex(&self.url_key, format!("{}.{}", id, exp).as_bytes(), sig)
+    }
+
+    /// Value of a file field as returned to clients: attachment ids become
+    /// signed URLs, anything else (empty, legacy data URL) is passed through.
+    pub fn public_value(&self, value: Option<String>, link: FileLink) -> Option<String> {
+        value.map(|value| {
+            if is_attachment_id(&value) {
+                self.signed_url(&value, link)
+            } else {
+                value
+            }
+        })
+    }
+}
+
+/// Whether any record's file fields still point at attachment `id`.
+async fn is_referenced(db: &Database, id: &str) -> Result<bool, String> {
+    let sources: [(&str, &[&str], Option<&str>); 2] = [
+        ("musteri_kabul", &MUSTERI_FILE_FIELDS, None),
+        ("montaj_kayitlari", &MONTAJ_FILE_FIELDS, Some(MONTAJ_IMAGE_LIST_FIELD)),
+    ];
+
+    for (collection_name, fields, list_field) in sources {
+        let conditions: Vec<Document> = fields
+            .iter()
+            .copied()
+            .chain(list_field)
+            .map(|field| doc! { field: id })
+            .collect();
+        let found = db
+            .collection::<Document>(collection_name)
+            .find_one(doc! { "$or": conditions })
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+        if found.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Converts a single embedded file value; returns the new id, or `None` if
+/// the value is empty, already an id or undecodable. Existing files are
+/// kept byte for byte, only their real type is detected.
+async fn migrate_value(
+    db: &Database,
+    store: &AttachmentStore,
+    value: &str,
+    failed: &mut i64,
+) -> Result<Option<String>, String> {
+    if value.trim().is_empty() || is_attachment_id(value) {
+        return Ok(None);
+    }
+
+    let Ok(bytes) = decode_data_url_image(value) else {
+        *failed += 1;
+        return Ok(None);
+    };
+    let (content_type, extension) = match media::sniff(&bytes) {
+        Some(kind) => (kind.content_type().to_string(), kind.extension()),
+        None => (
+            data_url_content_type(value).unwrap_or_else(|| "application/octet-stream".to_string()),
+            "",
+        ),
+    };
+    let attachment = store
+        .put_bytes(db, &bytes, &content_type, extension, None, "migration")
+        .await?;
+
+    Ok(Some(attachment.id.to_hex()))
+}
+
+/// Moves base64 payloads embedded in `musteri_kabul` / `montaj_kayitlari`
+/// into the attachment store, leaving attachment ids behind.
+/// Returns (records updated, values that could not be decoded). Safe to rerun.
+pub async fn migrate_embedded_files(db: &Database, store: &AttachmentStore) -> Result<(i64, i64), String> {
+    let mut updated: i64 = 0;
+    let mut failed: i64 = 0;
+
+    let sources: [(&str, &[&str], Option<&str>); 2] = [
+        ("musteri_kabul", &MUSTERI_FILE_FIELDS, None),
+        ("montaj_kayitlari", &MONTAJ_FILE_FIELDS, Some(MONTAJ_IMAGE_LIST_FIELD)),
+    ];
+
+    for (collection_name, fields, list_field) in sources {
+        let collection = db.collection::<Document>(collection_name);
+
+        // Anything longer than an id is an embedded payload
+        let mut conditions: Vec<Document> = fields
+            .iter()
+            .map(|field| doc! { *field: { "$regex": "^.{25,}" } })
+            .collect();
+        if let Some(list_field) = list_field {
+            conditions.push(doc! { list_field: { "$elemMatch": { "$regex": "^.{25,}" } } });
+        }
+
+        let mut cursor = collection
+            .find(doc! { "$or": conditions })
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        while cursor
+            .advance()
+            .await
+            .map_err(|e| format!("Cursor error: {}", e))?
+        {
+            let current: Document = cursor
+                .deserialize_current()
+                .map_err(|e| format!("Deserialize error: {}", e))?;
+
+            let Ok(id) = current.get_object_id("_id") else {
+                continue;
+            };
+
+            // Filtered on the values we converted, so a concurrent edit is not overwritten
+            let mut filter = doc! { "_id": id };
+            let mut set_doc = doc! {};
+            for field in fields.iter() {
+                let Ok(value) = current.get_str(field) else {
+                    continue;
+                };
+                if let Some(new_id) = migrate_value(db, store, value, &mut failed).await? {
+                    filter.insert(*field, value);
+                    set_doc.insert(*field, new_id);
+                }
+            }
+
+            if let Some(list_field) = list_field {
+                if let Ok(values) = current.get_array(list_field) {
+                    let mut changed = false;
+                    let mut next: Vec<Bson> = Vec::with_capacity(values.len());
+                    for value in values {
+                        let converted = match value.as_str() {
+                            Some(text) => migrate_value(db, store, text, &mut failed).await?,
+                            None => None,
+                        };
+                        match converted {
+                            Some(new_id) => {
+                                changed = true;
+                                next.push(Bson::String(new_id));
+                            }
+                            None => next.push(value.clone()),
+                        }
+                    }
+                    if changed {
+                        filter.insert(list_field, values.clone());
+                        set_doc.insert(list_field, next);
+                    }
+                }
+            }
+
+            if set_doc.is_empty() {
+                continue;
+            }
+
+            let new_ids = record_attachment_ids(&set_doc);
+            let result = collection
+                .update_one(filter, doc! { "$set": set_doc })
+                .await
+                .map_err(|e| format!("Update error: {}", e))?;
+
+            if result.modified_count == 1 {
+                updated += 1;
+            } else {
+                // The record changed meanwhile; drop the copies, a rerun picks it up
+                store.delete_unreferenced(db, &new_ids).await?;
+            }
+        }
+    }
+
+    Ok((updated, failed))
+}
@@ -0,0 +1,150 @@
+use mongodb::{
+    bson::{doc, Bson, DateTime, Document},
+    options::IndexOptions,
+    Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::AuthUser;
+
+const AUDIT_COLLECTION: &str = "audit_logs";
+
+/// Fields never copied into the log. `telefon` and the public tracking token
+/// are only reported as changed, base64 documents/photos are reported by
+/// presence instead of content.
+const REDACTED_FIELDS: &[&str] = &["telefon", "takip_token"];
+const BLOB_FIELDS: &[&str] = &["belge_f", "belge_g", "belge_u", "belge_a", "kurulum_resimleri"];
+const IGNORED_FIELDS: &[&str] = &["_id", "updated_at", "telefon_index", "search_terms"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Bson,
+    pub after: Bson,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    /// Hex id of the audited record (same shape as the legacy `entries` log).
+    pub entry_id: String,
+    pub collection: String,
+    pub action: String,
+    pub actor: String,
+    pub actor_role: String,
+    pub changes: Vec<FieldChange>,
+    pub created_at: DateTime,
+}
+
+pub async fn ensure_indexes(db: &Database) -> Result<(), String> {
+    db.collection::<Document>(AUDIT_COLLECTION)
+        .create_indexes(vec![
+            IndexModel::builder()
+                .keys(doc! { "entry_id": 1 })
+                .options(IndexOptions::builder().name("entry_id_1".to_string()).build())
+                .build(),
+            IndexModel::builder()
+                .keys(doc! { "created_at": -1 })
+                .options(IndexOptions::builder().name("created_at_-1".to_string()).build())
+                .build(),
+        ])
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Audit index error: {}", e))
+}
+
+fn loggable_value(field: &str, value: Option<&Bson>) -> Bson {
+    let Some(value) = value else {
+        return Bson::Null;
+    };
+
+    if REDACTED_FIELDS.contains(&field) {
+        return Bson::String("***".to_string());
+    }
+
+    if BLOB_FIELDS.contains(&field) {
+        let present = match value {
+            Bson::String(s) => !s.is_empty(),
+            Bson::Array(items) => !items.is_empty(),
+            Bson::Null => false,
+            _ => true,
+        };
+        return Bson::Boolean(present);
+    }
+
+    value.clone()
+}
+
+/// Field-level diff between the stored document and the `$set` applied to it.
+pub fn diff(before: &Document, set: &Document) -> Vec<FieldChange> {
+    set.iter()
+        .filter(|(field, _)| !IGNORED_FIELDS.contains(&field.as_str()))
+        .filter(|(field, after)| before.get(field.as_str()) != Some(*after))
+        .map(|(field, after)| FieldChange {
+            field: field.clone(),
+            before: loggable_value(field, before.get(field.as_str())),
+            after: loggable_value(field, Some(after)),
+        })
+        .collect()
+}
+
+/// Every field of a newly created document, as changes from nothing.
+pub fn snapshot(created: &Document) -> Vec<FieldChange> {
+    diff(&Document::new(), created)
+}
+
+/// Actor for changes made through public (unauthenticated) endpoints.
+pub fn public_actor() -> AuthUser {
+    AuthUser {
+        username: "public".to_string(),
+        role: "public".to_string(),
+    }
+}
+
+/// Appends an entry to `audit_logs`. Failures are logged, never returned:
+/// the audited change has already been written at this point.
+pub async fn record(
+    db: &Database,
+    collection: &str,
+    entry_id: &str,
+    action: &str,
+    actor: &AuthUser,
+    changes: Vec<FieldChange>,
+) {
+    let entry = AuditEntry {
+        entry_id: entry_id.to_string(),
+        collection: collection.to_string(),
+        action: action.to_string(),
+        actor: actor.username.clone(),
+        actor_role: actor.role.clone(),
+        changes,
+        created_at: DateTime::now(),
+    };
+
+    if let Err(e) = db.collection::<AuditEntry>(AUDIT_COLLECTION).insert_one(&entry).await {
+        tracing::error!("Audit log write failed ({} {} {}): {}", collection, entry_id, action, e);
+    }
+}
+
+pub async fn history(db: &Database, collection: &str, entry_id: &str) -> Result<Vec<AuditEntry>, String> {
+    let mut cursor = db
+        .collection::<AuditEntry>(AUDIT_COLLECTION)
+        .find(doc! { "entry_id": entry_id, "collection": collection })
+        .sort(doc! { "created_at": 1 })
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let mut entries = Vec::new();
+    while cursor
+        .advance()
+        .await
+        .map_err(|e| format!("Cursor error: {}", e))?
+    {
+        entries.push(
+            cursor
+                .deserialize_current()
+                .map_err(|e| format!("Deserialize error: {}", e))?,
+        );
+    }
+
+    Ok(entries)
+}
@@ -1,7 +1,20 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, MatchedPath, Request, State},
+    http::{header, request::Parts, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use chrono::{Duration, Utc};
 
+use crate::handlers::AppState;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
@@ -10,10 +23,7 @@ pub struct Claims {
     pub role: String,
 }
 
-const JWT_SECRET: &str = "sis_teknik_jwt_secret_key_2026";
-const ADMIN_PASSWORD: &str = "123456";
-
-pub fn generate_token(username: &str, role: &str) -> Result<String, String> {
+pub fn generate_token(secret: &str, username: &str, role: &str) -> Result<String, String> {
     let now = Utc::now();
     let expiration = now + Duration::hours(24);
     
@@ -27,23 +37,206 @@ pub fn generate_token(username: &str, role: &str) -> Result<String, String> {
     encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_ref()),
+        &EncodingKey::from_secret(secret.as_ref()),
     )
     .map_err(|e| format!("Token generation failed: {}", e))
 }
 
-pub fn verify_token(token: &str) -> Result<Claims, String> {
+pub fn verify_token(secret: &str, token: &str) -> Result<Claims, String> {
     decode::<Claims>(
         token,
-        &DecodingKey::from_secret(JWT_SECRET.as_ref()),
+        &DecodingKey::from_secret(secret.as_ref()),
         &Validation::default(),
     )
     .map(|data| data.claims)
     .map_err(|e| format!("Token verification failed: {}", e))
 }
 
-pub fn verify_admin_password(password: &str) -> bool {
-    password == ADMIN_PASSWORD
+pub fn verify_admin_password(password: &str, admin_password: &str) -> bool {
+    constant_time_eq(password.as_bytes(), admin_password.as_bytes())
+}
+
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Password hashing failed: {}", e))
+}
+
+/// Stored value is a PHC string (`$argon2id$...`) rather than a legacy plaintext password.
+pub fn is_password_hash(stored: &str) -> bool {
+    stored.starts_with("$argon2")
+}
+
+/// Checks `password` against the stored value. Legacy plaintext rows are
+/// still accepted so they can be rehashed on the next successful login.
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    if stored.is_empty() {
+        return false;
+    }
+
+    if !is_password_hash(stored) {
+        return constant_time_eq(password.as_bytes(), stored.as_bytes());
+    }
+
+    PasswordHash::new(stored)
+        .map(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false)
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub const ROLE_ADMIN: &str = "admin";
+pub const ROLE_TEKNISYEN: &str = "teknisyen";
+pub const ROLE_MONTAJ_EKIBI: &str = "montaj_ekibi";
+
+const ALL_ROLES: &[&str] = &[ROLE_ADMIN, ROLE_TEKNISYEN, ROLE_MONTAJ_EKIBI];
+const STAFF_ROLES: &[&str] = &[ROLE_ADMIN, ROLE_TEKNISYEN];
+const ADMIN_ONLY: &[&str] = &[ROLE_ADMIN];
+
+/// (method, route pattern, roles allowed). Routes missing here are denied.
+const ROUTE_PERMISSIONS: &[(&str, &str, &[&str])] = &[
+    ("GET", "/api/users", ALL_ROLES),
+    ("POST", "/api/users", ADMIN_ONLY),
+    // Non-admin users may only edit their own record, checked in the handler
+    ("PUT", "/api/users/:id", ALL_ROLES),
+    ("DELETE", "/api/users/:id", ADMIN_ONLY),
+    ("POST", "/api/migrations/fix-teknisyen-notes", ADMIN_ONLY),
+    ("POST", "/api/migrations/hash-passwords", ADMIN_ONLY),
+    ("POST", "/api/migrations/telefon-index", ADMIN_ONLY),
+    ("POST", "/api/migrations/attachments", ADMIN_ONLY),
+    ("POST", "/api/attachments", ALL_ROLES),
+    ("GET", "/api/bing/daily-image", ALL_ROLES),
+    ("GET", "/api/montaj", ALL_ROLES),
+    ("POST", "/api/montaj", STAFF_ROLES),
+    ("GET", "/api/montaj/:id", ALL_ROLES),
+    ("PUT", "/api/montaj/:id", STAFF_ROLES),
+    ("DELETE", "/api/montaj/:id", ADMIN_ONLY),
+    ("POST", "/api/montaj/:id/kapat", ALL_ROLES),
+    ("GET", "/api/montaj/:id/download-zip", ALL_ROLES),
+    ("POST", "/api/musteri-kabul", STAFF_ROLES),
+    ("GET", "/api/musteri-kabul", STAFF_ROLES),
+    ("GET", "/api/musteri-kabul/stats", STAFF_ROLES),
+    ("GET", "/api/musteri-kabul/search", STAFF_ROLES),
+    ("GET", "/api/musteri-kabul/awaiting-pickup", STAFF_ROLES),
+    ("POST", "/api/delete-otp/request", ADMIN_ONLY),
+    ("GET", "/api/system/sync", ALL_ROLES),
+    ("GET", "/api/dev/sms-outbox", ADMIN_ONLY),
+    ("GET", "/api/sms/providers", ADMIN_ONLY),
+    ("GET", "/api/sms/messages", ADMIN_ONLY),
+    ("GET", "/api/sms/queue", ADMIN_ONLY),
+    ("GET", "/api/sms/schedule", ADMIN_ONLY),
+    ("GET", "/api/surveys/report", ADMIN_ONLY),
+    ("GET", "/api/sms/consent", ADMIN_ONLY),
+    ("PUT", "/api/sms/consent", ADMIN_ONLY),
+    ("GET", "/api/sms/templates", ADMIN_ONLY),
+    ("PUT", "/api/sms/templates/:key", ADMIN_ONLY),
+    ("DELETE", "/api/sms/templates/:key", ADMIN_ONLY),
+    ("POST", "/api/sms/templates/:key/preview", ADMIN_ONLY),
+    ("POST", "/api/sms/queue/:id/retry", ADMIN_ONLY),
+    ("POST", "/api/sms/queue/:id/cancel", ADMIN_ONLY),
+    ("GET", "/api/musteri-kabul/by-status/:status", STAFF_ROLES),
+    ("POST", "/api/musteri-kabul/:id/resend-sms", STAFF_ROLES),
+    ("GET", "/api/musteri-kabul/:id", STAFF_ROLES),
+    ("PUT", "/api/musteri-kabul/:id", STAFF_ROLES),
+    ("DELETE", "/api/musteri-kabul/:id", ADMIN_ONLY),
+    ("POST", "/api/print-label", STAFF_ROLES),
+    ("GET", "/api/trash", ADMIN_ONLY),
+    ("POST", "/api/musteri-kabul/:id/restore", ADMIN_ONLY),
+    ("POST", "/api/montaj/:id/restore", ADMIN_ONLY),
+    ("GET", "/api/musteri-kabul/:id/history", STAFF_ROLES),
+    ("GET", "/api/montaj/:id/history", STAFF_ROLES),
+    ("POST", "/api/montaj/:id/fatura-link", STAFF_ROLES),
+    ("DELETE", "/api/montaj/:id/fatura-link", STAFF_ROLES),
+    ("POST", "/api/musteri-kabul/:id/fatura-link", STAFF_ROLES),
+    ("DELETE", "/api/musteri-kabul/:id/fatura-link", STAFF_ROLES),
+];
+
+fn allowed_roles(method: &Method, route: &str) -> Option<&'static [&'static str]> {
+    ROUTE_PERMISSIONS
+        .iter()
+        .find(|(m, path, _)| *m == method.as_str() && *path == route)
+        .map(|(_, _, roles)| *roles)
+}
+
+/// Authenticated caller, inserted into request extensions by `require_auth`.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub username: String,
+    pub role: String,
+}
+
+impl AuthUser {
+    pub fn is_admin(&self) -> bool {
+        self.role == ROLE_ADMIN
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<AuthUser>()
+            .cloned()
+            .ok_or((StatusCode::UNAUTHORIZED, "Oturum bulunamadı".to_string()))
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer ").or_else(|| v.strip_prefix("bearer ")))
+        .map(|v| v.trim())
+        .filter(|v| !v.is_empty())
+}
+
+/// Route layer for every protected `/api` route: validates the bearer token
+/// issued by `login` and checks the caller's role against `ROUTE_PERMISSIONS`.
+pub async fn require_auth(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, String)> {
+    let token = bearer_token(req.headers())
+        .ok_or((StatusCode::UNAUTHORIZED, "Yetkilendirme gerekli".to_string()))?;
+
+    let claims = verify_token(&state.config.jwt_secret, token)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Oturum geçersiz veya süresi dolmuş".to_string()))?;
+
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+
+    let permitted = allowed_roles(req.method(), &route)
+        .map(|roles| roles.contains(&claims.role.as_str()))
+        .unwrap_or(false);
+
+    if !permitted {
+        println!("⛔ Yetkisiz erişim: {} {} {} ({})", claims.sub, req.method(), route, claims.role);
+        return Err((StatusCode::FORBIDDEN, "Bu işlem için yetkiniz yok".to_string()));
+    }
+
+    req.extensions_mut().insert(AuthUser {
+        username: claims.sub,
+        role: claims.role,
+    });
+
+    Ok(next.run(req).await)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -0,0 +1,610 @@
+use chrono::{NaiveDate, NaiveTime};
+use serde::Deserialize;
+use std::env;
+use std::net::IpAddr;
+
+/// Runtime configuration, loaded once at startup.
+///
+/// Values come from an optional TOML file (`CONFIG_FILE`, default `config.toml`)
+/// and are overridden by environment variables. Secrets have no defaults.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub api_port: u16,
+    pub frontend_url: Option<String>,
+    pub mongodb_url: String,
+    pub mongodb_database: String,
+    pub jwt_secret: String,
+    pub admin_password: String,
+    pub encryption_key: String,
+    pub sms: SmsConfig,
+    pub delete_otp_phone: String,
+    /// Service phone number filled into `{servis_telefonu}` in SMS templates.
+    pub shop_phone: String,
+    pub printer_path: String,
+    pub turnstile_secret_key: Option<String>,
+    /// Days a soft-deleted record stays in the trash before it is purged.
+    pub trash_retention_days: i64,
+    /// Root of the content-addressed attachment store.
+    pub attachment_dir: String,
+    /// Uploaded photos are downscaled so neither side exceeds this many pixels.
+    pub image_max_dimension: u32,
+    /// Hours an uploaded attachment may stay unlinked to any record before it is deleted.
+    pub attachment_orphan_hours: i64,
+    /// Days a public fatura upload link stays valid.
+    pub fatura_link_ttl_days: i64,
+    /// Reverse proxies whose `cf-connecting-ip` / `x-forwarded-for` headers
+    /// are believed; other peers are identified by their socket address.
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmsConfig {
+    /// Gateways in priority order: `voicetelekom`, `netgsm` or `mock`, see
+    /// `sms_provider::build_provider`.
+    pub providers: Vec<String>,
+    /// Consecutive failures after which a gateway is skipped for a while.
+    pub failure_threshold: u32,
+    /// Seconds a failing gateway is skipped before it is tried again.
+    pub circuit_cooldown_seconds: u64,
+    /// Failed sends before a queued status SMS is dead-lettered.
+    pub queue_max_attempts: i32,
+    /// Days after a device became ready (status 7 or 9) on which a pickup
+    /// reminder is sent; empty turns reminders off.
+    pub pickup_reminder_days: Vec<i64>,
+    /// Send a satisfaction survey SMS after a device is delivered (status 8).
+    pub survey: bool,
+    /// Hours after delivery the survey SMS is due.
+    pub survey_delay_hours: i64,
+    /// Transliterate Turkish letters when that fits a multi-part message in
+    /// one segment, see `sms_encoding::prepare`.
+    pub transliterate: bool,
+    // VoiceTelekom
+    pub username: String,
+    pub password: String,
+    pub sender: String,
+    pub api_url: String,
+    /// Extra PEM root certificate to trust for the gateway (e.g. a private CA).
+    pub ca_cert: Option<String>,
+    /// Price per segment, recorded with every sent message.
+    pub segment_cost: f64,
+    pub netgsm: NetgsmConfig,
+    pub schedule: SmsScheduleConfig,
+    /// JSON-lines file the mock provider appends sent messages to.
+    pub mock_file: Option<String>,
+}
+
+/// Sending window for queued SMS, see `sms_schedule::SendWindow`.
+#[derive(Debug, Clone)]
+pub struct SmsScheduleConfig {
+    /// Turkish local time.
+    pub window_start: NaiveTime,
+    pub window_end: NaiveTime,
+    pub sundays: bool,
+    /// Days off besides the built-in public holidays.
+    pub holidays: Vec<NaiveDate>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NetgsmConfig {
+    pub usercode: String,
+    pub password: String,
+    /// Approved sender title (msgheader).
+    pub header: String,
+    pub api_url: String,
+    pub segment_cost: f64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    api_port: Option<u16>,
+    frontend_url: Option<String>,
+    mongodb: FileMongoConfig,
+    auth: FileAuthConfig,
+    encryption_key: Option<String>,
+    sms: FileSmsConfig,
+    delete_otp_phone: Option<String>,
+    shop_phone: Option<String>,
+    printer_path: Option<String>,
+    turnstile_secret_key: Option<String>,
+    trash_retention_days: Option<i64>,
+    attachment_dir: Option<String>,
+    image_max_dimension: Option<u32>,
+    attachment_orphan_hours: Option<i64>,
+    fatura_link_ttl_days: Option<i64>,
+    trusted_proxies: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileMongoConfig {
+    url: Option<String>,
+    database: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileAuthConfig {
+    jwt_secret: Option<String>,
+    admin_password: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileSmsConfig {
+    /// Legacy single provider; `providers` wins when both are set.
+    provider: Option<String>,
+    providers: Option<Vec<String>>,
+    failure_threshold: Option<u32>,
+    circuit_cooldown_seconds: Option<u64>,
+    queue_max_attempts: Option<i32>,
+    pickup_reminder_days: Option<Vec<i64>>,
+    survey: Option<bool>,
+    survey_delay_hours: Option<i64>,
+    transliterate: Option<bool>,
+    username: Option<String>,
+    password: Option<String>,
+    sender: Option<String>,
+    api_url: Option<String>,
+    ca_cert: Option<String>,
+    segment_cost: Option<f64>,
+    mock_file: Option<String>,
+    netgsm: FileNetgsmConfig,
+    schedule: FileSmsScheduleConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileSmsScheduleConfig {
+    window_start: Option<String>,
+    window_end: Option<String>,
+    sundays: Option<bool>,
+    holidays: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileNetgsmConfig {
+    usercode: Option<String>,
+    password: Option<String>,
+    header: Option<String>,
+    api_url: Option<String>,
+    segment_cost: Option<f64>,
+}
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const DEFAULT_SMS_PROVIDER: &str = "voicetelekom";
+const DEFAULT_SMS_API_URL: &str = "https://smsvt.voicetelekom.com:9588/sms/create";
+const DEFAULT_NETGSM_API_URL: &str = "https://api.netgsm.com.tr/sms/rest/v2/send";
+const DEFAULT_SMS_FAILURE_THRESHOLD: u32 = 3;
+const DEFAULT_SMS_CIRCUIT_COOLDOWN_SECONDS: u64 = 300;
+const DEFAULT_SMS_QUEUE_MAX_ATTEMPTS: i32 = 6;
+const SMS_PROVIDER_NAMES: &[&str] = &["voicetelekom", "netgsm", "mock"];
+const DEFAULT_PICKUP_REMINDER_DAYS: &[i64] = &[7, 15, 19];
+const DEFAULT_SMS_SURVEY_DELAY_HOURS: i64 = 24;
+const DEFAULT_SMS_WINDOW_START: &str = "09:00";
+const DEFAULT_SMS_WINDOW_END: &str = "21:00";
+const DEFAULT_SHOP_PHONE: &str = "04162161262";
+const DEFAULT_PRINTER_PATH: &str = "/dev/usb/lp0";
+const DEFAULT_ATTACHMENT_DIR: &str = "data/attachments";
+const DEFAULT_IMAGE_MAX_DIMENSION: u32 = 2048;
+const DEFAULT_ATTACHMENT_ORPHAN_HOURS: i64 = 24;
+const DEFAULT_FATURA_LINK_TTL_DAYS: i64 = 30;
+
+/// First non-empty environment variable among `keys`.
+fn env_value(keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| env::var(key).ok())
+        .map(|v| v.trim().to_string())
+        .find(|v| !v.is_empty())
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+fn parse_time(value: &str, name: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| format!("{} geçersiz (SS:DD): {}", name, value))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self, String> {
+        let explicit_path = env_value(&["CONFIG_FILE"]);
+        let path = explicit_path.clone().unwrap_or_else(|| DEFAULT_CONFIG_FILE.to_string());
+
+        let file = match std::fs::read_to_string(&path) {
+            Ok(content) => toml::from_str::<FileConfig>(&content)
+                .map_err(|e| format!("{} okunamadı: {}", path, e))?,
+            Err(e) if explicit_path.is_some() => {
+                return Err(format!("{} açılamadı: {}", path, e));
+            }
+            Err(_) => FileConfig::default(),
+        };
+
+        Self::from_sources(file)
+    }
+
+    fn from_sources(file: FileConfig) -> Result<Self, String> {
+        let mut missing: Vec<&str> = Vec::new();
+        let mut required = |value: Option<String>, name: &'static str| {
+            value.unwrap_or_else(|| {
+                missing.push(name);
+                String::new()
+            })
+        };
+
+        let jwt_secret = required(
+            env_value(&["JWT_SECRET"]).or(non_empty(file.auth.jwt_secret)),
+            "JWT_SECRET",
+        );
+        let admin_password = required(
+            env_value(&["ADMIN_PASSWORD"]).or(non_empty(file.auth.admin_password)),
+            "ADMIN_PASSWORD",
+        );
+        let encryption_key = required(
+            env_value(&["ENCRYPTION_KEY"]).or(non_empty(file.encryption_key)),
+            "ENCRYPTION_KEY",
+        );
+        let sms_providers: Vec<String> = match env_value(&["SMS_PROVIDERS", "SMS_PROVIDER"]) {
+            Some(value) => value.split(',').map(str::to_string).collect(),
+            None => file
+                .sms
+                .providers
+                .or(non_empty(file.sms.provider).map(|p| vec![p]))
+                .unwrap_or_else(|| vec![DEFAULT_SMS_PROVIDER.to_string()]),
+        };
+        let sms_providers: Vec<String> = sms_providers
+            .into_iter()
+            .map(|p| p.trim().to_lowercase())
+            .filter(|p| !p.is_empty())
+            .collect();
+
+        // Gateway credentials are only required for the gateways in use
+        let mut credential = |value: Option<String>, name: &'static str, provider: &str| {
+            if sms_providers.iter().any(|p| p == provider) {
+                required(value, name)
+            } else {
+                value.unwrap_or_default()
+            }
+        };
+        let sms_username = credential(
+            env_value(&["SMS_USERNAME"]).or(non_empty(file.sms.username)),
+            "SMS_USERNAME",
+            "voicetelekom",
+        );
+        let sms_password = credential(
+            env_value(&["SMS_PASSWORD"]).or(non_empty(file.sms.password)),
+            "SMS_PASSWORD",
+            "voicetelekom",
+        );
+        let sms_sender = credential(
+            env_value(&["SMS_SENDER"]).or(non_empty(file.sms.sender)),
+            "SMS_SENDER",
+            "voicetelekom",
+        );
+        let netgsm_usercode = credential(
+            env_value(&["NETGSM_USERCODE"]).or(non_empty(file.sms.netgsm.usercode)),
+            "NETGSM_USERCODE",
+            "netgsm",
+        );
+        let netgsm_password = credential(
+            env_value(&["NETGSM_PASSWORD"]).or(non_empty(file.sms.netgsm.password)),
+            "NETGSM_PASSWORD",
+            "netgsm",
+        );
+        let netgsm_header = credential(
+            env_value(&["NETGSM_HEADER"]).or(non_empty(file.sms.netgsm.header)),
+            "NETGSM_HEADER",
+            "netgsm",
+        );
+        let delete_otp_phone = required(
+            env_value(&["DELETE_OTP_PHONE"]).or(non_empty(file.delete_otp_phone)),
+            "DELETE_OTP_PHONE",
+        );
+
+        if !missing.is_empty() {
+            return Err(format!("Eksik ayarlar: {}", missing.join(", ")));
+        }
+
+        let api_port = match env_value(&["API_PORT"]) {
+            Some(value) => value
+                .parse::<u16>()
+                .map_err(|_| format!("API_PORT geçersiz: {}", value))?,
+            None => file.api_port.unwrap_or(3000),
+        };
+
+        let trash_retention_days = match env_value(&["TRASH_RETENTION_DAYS"]) {
+            Some(value) => value
+                .parse::<i64>()
+                .map_err(|_| format!("TRASH_RETENTION_DAYS geçersiz: {}", value))?,
+            None => file.trash_retention_days.unwrap_or(30),
+        };
+
+        let image_max_dimension = match env_value(&["IMAGE_MAX_DIMENSION"]) {
+            Some(value) => value
+                .parse::<u32>()
+                .map_err(|_| format!("IMAGE_MAX_DIMENSION geçersiz: {}", value))?,
+            None => file.image_max_dimension.unwrap_or(DEFAULT_IMAGE_MAX_DIMENSION),
+        };
+
+        let attachment_orphan_hours = match env_value(&["ATTACHMENT_ORPHAN_HOURS"]) {
+            Some(value) => value
+                .parse::<i64>()
+                .map_err(|_| format!("ATTACHMENT_ORPHAN_HOURS geçersiz: {}", value))?,
+            None => file.attachment_orphan_hours.unwrap_or(DEFAULT_ATTACHMENT_ORPHAN_HOURS),
+        };
+
+        let sms_failure_threshold = match env_value(&["SMS_FAILURE_THRESHOLD"]) {
+            Some(value) => value
+                .parse::<u32>()
+                .map_err(|_| format!("SMS_FAILURE_THRESHOLD geçersiz: {}", value))?,
+            None => file.sms.failure_threshold.unwrap_or(DEFAULT_SMS_FAILURE_THRESHOLD),
+        };
+
+        let sms_circuit_cooldown_seconds = match env_value(&["SMS_CIRCUIT_COOLDOWN_SECONDS"]) {
+            Some(value) => value
+                .parse::<u64>()
+                .map_err(|_| format!("SMS_CIRCUIT_COOLDOWN_SECONDS geçersiz: {}", value))?,
+            None => file
+                .sms
+                .circuit_cooldown_seconds
+                .unwrap_or(DEFAULT_SMS_CIRCUIT_COOLDOWN_SECONDS),
+        };
+
+        let sms_queue_max_attempts = match env_value(&["SMS_QUEUE_MAX_ATTEMPTS"]) {
+            Some(value) => value
+                .parse::<i32>()
+                .map_err(|_| format!("SMS_QUEUE_MAX_ATTEMPTS geçersiz: {}", value))?,
+            None => file.sms.queue_max_attempts.unwrap_or(DEFAULT_SMS_QUEUE_MAX_ATTEMPTS),
+        };
+
+        let mut pickup_reminder_days = match env_value(&["PICKUP_REMINDER_DAYS"]) {
+            Some(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|day| !day.is_empty())
+                .map(|day| {
+                    day.parse::<i64>()
+                        .map_err(|_| format!("PICKUP_REMINDER_DAYS geçersiz: {}", value))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => file
+                .sms
+                .pickup_reminder_days
+                .unwrap_or_else(|| DEFAULT_PICKUP_REMINDER_DAYS.to_vec()),
+        };
+        pickup_reminder_days.sort_unstable();
+        pickup_reminder_days.dedup();
+
+        let sms_survey = match env_value(&["SMS_SURVEY"]) {
+            Some(value) => parse_bool(&value).ok_or_else(|| format!("SMS_SURVEY geçersiz: {}", value))?,
+            None => file.sms.survey.unwrap_or(false),
+        };
+
+        let sms_survey_delay_hours = match env_value(&["SMS_SURVEY_DELAY_HOURS"]) {
+            Some(value) => value
+                .parse::<i64>()
+                .map_err(|_| format!("SMS_SURVEY_DELAY_HOURS geçersiz: {}", value))?,
+            None => file.sms.survey_delay_hours.unwrap_or(DEFAULT_SMS_SURVEY_DELAY_HOURS),
+        };
+
+        let sms_transliterate = match env_value(&["SMS_TRANSLITERATE"]) {
+            Some(value) => parse_bool(&value).ok_or_else(|| format!("SMS_TRANSLITERATE geçersiz: {}", value))?,
+            None => file.sms.transliterate.unwrap_or(false),
+        };
+
+        let sms_segment_cost = match env_value(&["SMS_SEGMENT_COST"]) {
+            Some(value) => value
+                .parse::<f64>()
+                .map_err(|_| format!("SMS_SEGMENT_COST geçersiz: {}", value))?,
+            None => file.sms.segment_cost.unwrap_or(0.0),
+        };
+
+        let netgsm_segment_cost = match env_value(&["NETGSM_SEGMENT_COST"]) {
+            Some(value) => value
+                .parse::<f64>()
+                .map_err(|_| format!("NETGSM_SEGMENT_COST geçersiz: {}", value))?,
+            None => file.sms.netgsm.segment_cost.unwrap_or(0.0),
+        };
+
+        let sms_window_start = parse_time(
+            &env_value(&["SMS_WINDOW_START"])
+                .or(non_empty(file.sms.schedule.window_start))
+                .unwrap_or_else(|| DEFAULT_SMS_WINDOW_START.to_string()),
+            "SMS_WINDOW_START",
+        )?;
+        let sms_window_end = parse_time(
+            &env_value(&["SMS_WINDOW_END"])
+                .or(non_empty(file.sms.schedule.window_end))
+                .unwrap_or_else(|| DEFAULT_SMS_WINDOW_END.to_string()),
+            "SMS_WINDOW_END",
+        )?;
+        let sms_sundays = match env_value(&["SMS_SEND_ON_SUNDAYS"]) {
+            Some(value) => parse_bool(&value).ok_or_else(|| format!("SMS_SEND_ON_SUNDAYS geçersiz: {}", value))?,
+            None => file.sms.schedule.sundays.unwrap_or(false),
+        };
+        let sms_holidays = match env_value(&["SMS_HOLIDAYS"]) {
+            Some(value) => value.split(',').map(str::to_string).collect(),
+            None => file.sms.schedule.holidays.unwrap_or_default(),
+        };
+        let sms_holidays = sms_holidays
+            .iter()
+            .map(|day| day.trim())
+            .filter(|day| !day.is_empty())
+            .map(|day| {
+                NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                    .map_err(|_| format!("SMS_HOLIDAYS geçersiz tarih (YYYY-AA-GG): {}", day))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let fatura_link_ttl_days = match env_value(&["FATURA_LINK_TTL_DAYS"]) {
+            Some(value) => value
+                .parse::<i64>()
+                .map_err(|_| format!("FATURA_LINK_TTL_DAYS geçersiz: {}", value))?,
+            None => file.fatura_link_ttl_days.unwrap_or(DEFAULT_FATURA_LINK_TTL_DAYS),
+        };
+
+        let trusted_proxies = match env_value(&["TRUSTED_PROXIES"]) {
+            Some(value) => value.split(',').map(str::to_string).collect(),
+            None => file.trusted_proxies.unwrap_or_default(),
+        };
+        let trusted_proxies = trusted_proxies
+            .iter()
+            .map(|proxy| proxy.trim())
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| {
+                proxy
+                    .parse::<IpAddr>()
+                    .map_err(|_| format!("TRUSTED_PROXIES geçersiz IP adresi: {}", proxy))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let config = Config {
+            api_port,
+            frontend_url: env_value(&["FRONTEND_URL"]).or(non_empty(file.frontend_url)),
+            mongodb_url: env_value(&["MONGODB_URI", "MONGODB_URL"])
+                .or(non_empty(file.mongodb.url))
+                .unwrap_or_else(|| "mongodb://localhost:27017".to_string()),
+            mongodb_database: env_value(&["MONGODB_DATABASE", "MONGODB_DB"])
+                .or(non_empty(file.mongodb.database))
+                .unwrap_or_else(|| "sis_teknik".to_string()),
+            jwt_secret,
+            admin_password,
+            encryption_key,
+            sms: SmsConfig {
+                providers: sms_providers,
+                failure_threshold: sms_failure_threshold,
+                circuit_cooldown_seconds: sms_circuit_cooldown_seconds,
+                queue_max_attempts: sms_queue_max_attempts,
+                pickup_reminder_days,
+                survey: sms_survey,
+                survey_delay_hours: sms_survey_delay_hours,
+                transliterate: sms_transliterate,
+                username: sms_username,
+                password: sms_password,
+                sender: sms_sender,
+                api_url: env_value(&["SMS_API_URL"])
+                    .or(non_empty(file.sms.api_url))
+                    .unwrap_or_else(|| DEFAULT_SMS_API_URL.to_string()),
+                ca_cert: env_value(&["SMS_CA_CERT"]).or(non_empty(file.sms.ca_cert)),
+                segment_cost: sms_segment_cost,
+                netgsm: NetgsmConfig {
+                    usercode: netgsm_usercode,
+                    password: netgsm_password,
+                    header: netgsm_header,
+                    api_url: env_value(&["NETGSM_API_URL"])
+                        .or(non_empty(file.sms.netgsm.api_url))
+                        .unwrap_or_else(|| DEFAULT_NETGSM_API_URL.to_string()),
+                    segment_cost: netgsm_segment_cost,
+                },
+                schedule: SmsScheduleConfig {
+                    window_start: sms_window_start,
+                    window_end: sms_window_end,
+                    sundays: sms_sundays,
+                    holidays: sms_holidays,
+                },
+                mock_file: env_value(&["SMS_MOCK_FILE"]).or(non_empty(file.sms.mock_file)),
+            },
+            delete_otp_phone,
+            shop_phone: env_value(&["SHOP_PHONE"])
+                .or(non_empty(file.shop_phone))
+                .unwrap_or_else(|| DEFAULT_SHOP_PHONE.to_string()),
+            printer_path: env_value(&["PRINTER_PATH"])
+                .or(non_empty(file.printer_path))
+                .unwrap_or_else(|| DEFAULT_PRINTER_PATH.to_string()),
+            turnstile_secret_key: env_value(&["TURNSTILE_SECRET_KEY", "CLOUDFLARE_TURNSTILE_SECRET_KEY"])
+                .or(non_empty(file.turnstile_secret_key)),
+            trash_retention_days,
+            attachment_dir: env_value(&["ATTACHMENT_DIR"])
+                .or(non_empty(file.attachment_dir))
+                .unwrap_or_else(|| DEFAULT_ATTACHMENT_DIR.to_string()),
+            image_max_dimension,
+            attachment_orphan_hours,
+            fatura_link_ttl_days,
+            trusted_proxies,
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.trash_retention_days < 1 {
+            return Err("TRASH_RETENTION_DAYS en az 1 olmalı".to_string());
+        }
+
+        if self.sms.providers.is_empty() {
+            return Err("SMS_PROVIDERS boş olamaz".to_string());
+        }
+        for provider in &self.sms.providers {
+            if !SMS_PROVIDER_NAMES.contains(&provider.as_str()) {
+                return Err(format!(
+                    "SMS_PROVIDERS geçersiz: {} ({})",
+                    provider,
+                    SMS_PROVIDER_NAMES.join(", ")
+                ));
+            }
+            if self.sms.providers.iter().filter(|p| *p == provider).count() > 1 {
+                return Err(format!("SMS_PROVIDERS içinde {} birden fazla kez geçiyor", provider));
+            }
+        }
+
+        if self.sms.failure_threshold < 1 {
+            return Err("SMS_FAILURE_THRESHOLD en az 1 olmalı".to_string());
+        }
+
+        if self.sms.queue_max_attempts < 1 {
+            return Err("SMS_QUEUE_MAX_ATTEMPTS en az 1 olmalı".to_string());
+        }
+
+        for (name, cost) in [
+            ("SMS_SEGMENT_COST", self.sms.segment_cost),
+            ("NETGSM_SEGMENT_COST", self.sms.netgsm.segment_cost),
+        ] {
+            if !cost.is_finite() || cost < 0.0 {
+                return Err(format!("{} negatif olamaz", name));
+            }
+        }
+
+        if self.sms.pickup_reminder_days.iter().any(|day| *day < 1) {
+            return Err("PICKUP_REMINDER_DAYS en az 1 olmalı".to_string());
+        }
+
+        if self.sms.survey_delay_hours < 1 {
+            return Err("SMS_SURVEY_DELAY_HOURS en az 1 olmalı".to_string());
+        }
+
+        if self.sms.schedule.window_start >= self.sms.schedule.window_end {
+            return Err("SMS_WINDOW_START, SMS_WINDOW_END'den önce olmalı".to_string());
+        }
+
+        if self.fatura_link_ttl_days < 1 {
+            return Err("FATURA_LINK_TTL_DAYS en az 1 olmalı".to_string());
+        }
+
+        if self.image_max_dimension < 320 {
+            return Err("IMAGE_MAX_DIMENSION en az 320 olmalı".to_string());
+        }
+
+        if self.attachment_orphan_hours < 1 {
+            return Err("ATTACHMENT_ORPHAN_HOURS en az 1 olmalı".to_string());
+        }
+
+        if self.jwt_secret.len() < 32 {
+            return Err("JWT_SECRET en az 32 karakter olmalı".to_string());
+        }
+
+        crate::crypto::FieldCipher::new(&self.encryption_key)
+            .map_err(|e| format!("ENCRYPTION_KEY geçersiz: {}", e))?;
+
+        Ok(())
+    }
+}
@@ -1,57 +1,138 @@
 use aes_gcm::{
-    aead::{Aead, KeyInit, Payload},
+    aead::{Aead, KeyInit},
     Aes256Gcm, Nonce,
 };
-use base64::{engine::general_purpose::STANDARD, Engine};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use hmac::{Hmac, Mac};
 use rand::Rng;
-use std::env;
-
-pub fn encrypt_value(value: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let encryption_key_b64 = env::var("ENCRYPTION_KEY")
-        .unwrap_or_else(|_| "EEdmGx2ZTjokTQ8XYm1Dfb1ZiqglEGAAoxguSq+dHW4=".to_string());
-    
-    let key_bytes = STANDARD.decode(&encryption_key_b64)?;
-    let cipher = Aes256Gcm::new_from_slice(&key_bytes)
-        .map_err(|e| format!("Invalid key: {}", e))?;
-
-    // Generate random nonce (12 bytes for GCM)
-    let mut rng = rand::thread_rng();
-    let nonce_bytes: [u8; 12] = rng.gen();
-    let nonce = Nonce::from_slice(&nonce_bytes);
-
-    // Encrypt
-    let ciphertext = cipher.encrypt(nonce, value.as_bytes())
-        .map_err(|e| format!("Encryption failed: {}", e))?;
-
-    // Combine nonce + ciphertext and encode to base64
-    let mut combined = nonce_bytes.to_vec();
-    combined.extend_from_slice(&ciphertext);
-    
-    Ok(STANDARD.encode(&combined))
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Context used to derive the blind index key from `ENCRYPTION_KEY`, so the
+/// AES key itself is never used as an HMAC key.
+const BLIND_INDEX_CONTEXT: &[u8] = b"sis-teknik/blind-index/v1";
+
+/// Bytes kept by `hmac_tag`; 128 bits is plenty for a signature that also expires.
+const SHORT_TAG_BYTES: usize = 16;
+
+/// Purpose-specific key derived from a master secret (HMAC-SHA256(secret, context)).
+pub fn derive_key(secret: &[u8], context: &[u8]) -> Vec<u8> {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(context);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Hex encoded HMAC-SHA256 of `message`.
+pub fn hmac_hex(key: &[u8], message: &[u8]) -> String {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Constant-time check of a hex HMAC produced by `hmac_hex`.
+pub fn verify_hmac_hex(key: &[u8], message: &[u8], expected_hex: &str) -> bool {
+    let expected: Option<Vec<u8>> = (0..expected_hex.len())
+        .step_by(2)
+        .map(|i| expected_hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect();
+    let Some(expected) = expected else {
+        return false;
+    };
+
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Short URL-safe HMAC-SHA256 tag (truncated), for links sent by SMS.
+pub fn hmac_tag(key: &[u8], message: &[u8]) -> String {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    URL_SAFE_NO_PAD.encode(&mac.finalize().into_bytes()[..SHORT_TAG_BYTES])
+}
+
+/// Constant-time check of a tag produced by `hmac_tag`.
+pub fn verify_hmac_tag(key: &[u8], message: &[u8], tag: &str) -> bool {
+    let Ok(expected) = URL_SAFE_NO_PAD.decode(tag) else {
+        return false;
+    };
+    if expected.len() != SHORT_TAG_BYTES {
+        return false;
+    }
+
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.verify_truncated_left(&expected).is_ok()
+}
+
+/// AES-256-GCM cipher for encrypted record fields (e.g. `telefon`).
+#[derive(Clone)]
+pub struct FieldCipher {
+    cipher: Aes256Gcm,
+    index_key: Vec<u8>,
 }
 
-pub fn decrypt_value(encrypted: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let encryption_key_b64 = env::var("ENCRYPTION_KEY")
-        .unwrap_or_else(|_| "EEdmGx2ZTjokTQ8XYm1Dfb1ZiqglEGAAoxguSq+dHW4=".to_string());
-    
-    let key_bytes = STANDARD.decode(&encryption_key_b64)?;
-    let cipher = Aes256Gcm::new_from_slice(&key_bytes)
-        .map_err(|e| format!("Invalid key: {}", e))?;
+impl FieldCipher {
+    /// `key_b64` is the base64 encoded 32-byte key from `ENCRYPTION_KEY`.
+    pub fn new(key_b64: &str) -> Result<Self, String> {
+        let key_bytes = STANDARD
+            .decode(key_b64)
+            .map_err(|e| format!("Invalid key encoding: {}", e))?;
+        let cipher = Aes256Gcm::new_from_slice(&key_bytes)
+            .map_err(|e| format!("Invalid key: {}", e))?;
+
+        let index_key = derive_key(&key_bytes, BLIND_INDEX_CONTEXT);
+
+        Ok(Self { cipher, index_key })
+    }
+
+    /// Deterministic keyed hash (hex HMAC-SHA256) of an already normalized
+    /// value. Lets encrypted fields be matched exactly without decrypting.
+    pub fn blind_index(&self, value: &str) -> String {
+        hmac_hex(&self.index_key, value.as_bytes())
+    }
+
+    pub fn encrypt_value(&self, value: &str) -> Result<String, Box<dyn std::error::Error>> {
+        // Generate random nonce (12 bytes for GCM)
+        let mut rng = rand::thread_rng();
+        let nonce_bytes: [u8; 12] = rng.gen();
+        let nonce = Nonce::from_slice(&nonce_bytes);
+
+        // Encrypt
+        let ciphertext = self.cipher.encrypt(nonce, value.as_bytes())
+            .map_err(|e| format!("Encryption failed: {}", e))?;
 
-    // Decode from base64
-    let combined = STANDARD.decode(encrypted)?;
+        // Combine nonce + ciphertext and encode to base64
+        let mut combined = nonce_bytes.to_vec();
+        combined.extend_from_slice(&ciphertext);
 
-    if combined.len() < 12 {
-        return Err("Invalid encrypted data".into());
+        Ok(STANDARD.encode(&combined))
     }
 
-    // Extract nonce and ciphertext
-    let (nonce_bytes, ciphertext) = combined.split_at(12);
-    let nonce = Nonce::from_slice(nonce_bytes);
+    pub fn decrypt_value(&self, encrypted: &str) -> Result<String, Box<dyn std::error::Error>> {
+        // Decode from base64
+        let combined = STANDARD.decode(encrypted)?;
 
-    // Decrypt
-    let plaintext = cipher.decrypt(nonce, ciphertext)
-        .map_err(|e| format!("Decryption failed: {}", e))?;
+        if combined.len() < 12 {
+            return Err("Invalid encrypted data".into());
+        }
 
-    Ok(String::from_utf8(plaintext)?)
+        // Extract nonce and ciphertext
+        let (nonce_bytes, ciphertext) = combined.split_at(12);
+        let nonce = Nonce::from_slice(nonce_bytes);
+
+        // Decrypt
+        let plaintext = self.cipher.decrypt(nonce, ciphertext)
+            .map_err(|e| format!("Decryption failed: {}", e))?;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
 }
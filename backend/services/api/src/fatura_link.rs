@@ -0,0 +1,233 @@
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document};
+
+use crate::crypto::{derive_key, hmac_tag, verify_hmac_tag};
+
+const FATURA_LINK_CONTEXT: &[u8] = b"sis-teknik/fatura-upload/v1";
+
+/// Record field bumped on revoke/reissue; tokens carry the version they were
+/// issued for, so bumping it invalidates every earlier link at once.
+pub const VERSION_FIELD: &str = "fatura_link_version";
+
+/// Record a fatura upload link points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaturaTarget {
+    Montaj,
+    Musteri,
+}
+
+impl FaturaTarget {
+    pub fn collection(self) -> &'static str {
+        match self {
+            FaturaTarget::Montaj => "montaj_kayitlari",
+            FaturaTarget::Musteri => "musteri_kabul",
+        }
+    }
+
+    /// Field holding the device name shown on the public page.
+    pub fn device_field(self) -> &'static str {
+        match self {
+            FaturaTarget::Montaj => "model",
+            FaturaTarget::Musteri => "marka_model",
+        }
+    }
+
+    fn code(self) -> char {
+        match self {
+            FaturaTarget::Montaj => 'm',
+            FaturaTarget::Musteri => 'k',
+        }
+    }
+
+    fn from_code(code: char) -> Option<Self> {
+        match code {
+            'm' => Some(FaturaTarget::Montaj),
+            'k' => Some(FaturaTarget::Musteri),
+            _ => None,
+        }
+    }
+}
+
+/// What a valid token grants: uploading a fatura to one record until `expires_at`.
+#[derive(Debug, Clone)]
+pub struct FaturaClaims {
+    pub target: FaturaTarget,
+    pub id: ObjectId,
+    pub version: i64,
+    pub expires_at: i64,
+}
+
+/// Issues and verifies the tokens in `/fatura/{token}` links.
+///
+/// Token layout: `{target}{object_id}.{expires_at}.{version}.{tag}`, where the
+/// tag is an HMAC over everything before it under a key used for nothing else.
+#[derive(Clone)]
+pub struct FaturaLinks {
+    key: Vec<u8>,
+    ttl_seconds: i64,
+}
+
+impl FaturaLinks {
+    pub fn new(secret: &str, ttl_days: i64) -> Self {
+        Self {
+            key: derive_key(secret.as_bytes(), FATURA_LINK_CONTEXT),
+            ttl_seconds: ttl_days * 24 * 60 * 60,
+        }
+    }
+
+    /// New token and its expiry (unix seconds).
+    pub fn issue(&self, target: FaturaTarget, id: &ObjectId, version: i64) -> (String, i64) {
+        let expires_at = chrono::Utc::now().timestamp() + self.ttl_seconds;
+        let payload = format!("{}{}.{}.{}", target.code(), id.to_hex(), expires_at, version);
+        let tag = hmac_tag(&self.key, payload.as_bytes());
+        (format!("{}.{}", payload, tag), expires_at)
+    }
+
+    /// Public upload page URL for a record.
+    pub fn url(&self, frontend_url: &str, target: FaturaTarget, id: &ObjectId, version: i64) -> String {
+        let (token, _) = self.issue(target, id, version);
+        format!("{}/fatura/{}", frontend_url.trim_end_matches('/'), token)
+    }
+
+    /// Checks the signature and expiry. Whether the version is still current
+    /// is up to the caller (see `version_filter`).
+    pub fn verify(&self, token: &str) -> Result<FaturaClaims, String> {
+        let invalid = || "Fatura bağlantısı geçersiz".to_string();
+
+        let token = token.trim();
+        let (payload, tag) = token.rsplit_once('.').ok_or_else(invalid)?;
+        if !verify_hmac_tag(&self.key, payload.as_bytes(), tag) {
+            return Err(invalid());
+        }
+
+        let mut parts = payload.split('.');
+        let (Some(record), Some(expires_at), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+
+        let mut chars = record.chars();
+        let target = chars.next().and_then(FaturaTarget::from_code).ok_or_else(invalid)?;
+        let id = ObjectId::parse_str(chars.as_str()).map_err(|_| invalid())?;
+        let expires_at = expires_at.parse::<i64>().map_err(|_| invalid())?;
+        let version = version.parse::<i64>().map_err(|_| invalid())?;
+
+        if expires_at < chrono::Utc::now().timestamp() {
+            return Err("Fatura bağlantısının süresi dolmuş, lütfen servisle iletişime geçin".to_string());
+        }
+
+        Ok(FaturaClaims {
+            target,
+            id,
+            version,
+            expires_at,
+        })
+    }
+}
+
+/// Filter matching records whose link version is still `version`. Records
+/// created before versions existed count as version 0.
+pub fn version_filter(version: i64) -> Document {
+    if version == 0 {
+        doc! { VERSION_FIELD: { "$in": [0_i64, Bson::Null] } }
+    } else {
+        doc! { VERSION_FIELD: version }
+    }
+}
+
+/// Current link version of a stored record.
+pub fn current_version(record: &Document) -> i64 {
+    match record.get(VERSION_FIELD) {
+        Some(Bson::Int64(v)) => *v,
+        Some(Bson::Int32(v)) => i64::from(*v),
+        _ => 0,
+    }
+}
+
+/// "AHMET YILMAZ" -> "AH*** YI***", enough for the customer to recognise
+/// themselves without exposing the name to whoever holds the link.
+pub fn mask_name(name: &str) -> String {
+    name.split_whitespace()
+        .map(|word| {
+            let visible: String = word.chars().take(2).collect();
+            format!("{}***", visible)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+
+    fn signed(links: &FaturaLinks, payload: &str) -> String {
+        format!("{}.{}", payload, hmac_tag(&links.key, payload.as_bytes()))
+    }
+
+    #[test]
+    fn issued_tokens_verify() {
+        let links = FaturaLinks::new(SECRET, 7);
+        let id = ObjectId::new();
+        let (token, expires_at) = links.issue(FaturaTarget::Musteri, &id, 3);
+
+        let claims = links.verify(&token).unwrap();
+        assert_eq!(claims.target, FaturaTarget::Musteri);
+        assert_eq!(claims.id, id);
+        assert_eq!(claims.version, 3);
+        assert_eq!(claims.expires_at, expires_at);
+        assert!(expires_at > chrono::Utc::now().timestamp() + 6 * 24 * 60 * 60);
+    }
+
+    #[test]
+    fn url_carries_a_verifiable_token() {
+        let links = FaturaLinks::new(SECRET, 7);
+        let id = ObjectId::new();
+        let url = links.url("https://servis.example/", FaturaTarget::Montaj, &id, 0);
+        let token = url.strip_prefix("https://servis.example/fatura/").unwrap();
+        assert_eq!(links.verify(token).unwrap().target, FaturaTarget::Montaj);
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let links = FaturaLinks::new(SECRET, -1);
+        let (token, _) = links.issue(FaturaTarget::Montaj, &ObjectId::new(), 0);
+        let error = links.verify(&token).unwrap_err();
+        assert!(error.contains("süresi dolmuş"), "{error}");
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let links = FaturaLinks::new(SECRET, 7);
+        let (token, _) = links.issue(FaturaTarget::Musteri, &ObjectId::new(), 1);
+
+        let retargeted = format!("m{}", &token[1..]);
+        let (payload, tag) = token.rsplit_once('.').unwrap();
+        let bumped = format!("{}.{}", payload.replace(".1", ".2"), tag);
+        let other_key = FaturaLinks::new("other-secret", 7).issue(FaturaTarget::Musteri, &ObjectId::new(), 1).0;
+
+        for token in [retargeted.as_str(), bumped.as_str(), other_key.as_str(), payload, "", "..."] {
+            assert!(links.verify(token).is_err(), "{token:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn malformed_payloads_are_rejected_even_when_signed() {
+        let links = FaturaLinks::new(SECRET, 7);
+        let id = ObjectId::new().to_hex();
+        let future = chrono::Utc::now().timestamp() + 60;
+
+        assert!(links.verify(&signed(&links, &format!("k{}.{}.0", id, future))).is_ok());
+        for payload in [
+            format!("x{}.{}.0", id, future),
+            format!("knot-an-id.{}.0", future),
+            format!("k{}.soon.0", id),
+            format!("k{}.{}.v1", id, future),
+            format!("k{}.{}", id, future),
+            format!("k{}.{}.0.extra", id, future),
+        ] {
+            assert!(links.verify(&signed(&links, &payload)).is_err(), "{payload:?} should be rejected");
+        }
+    }
+}
@@ -1,27 +1,69 @@
 use axum::{
-    extract::{Path, State, Json},
+    extract::{ConnectInfo, Path, Query, State, Json},
     http::{HeaderMap, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use rand::Rng;
-use serde::{Deserialize, Serialize, Deserializer};
-use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io::{Cursor, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
 use zip::write::SimpleFileOptions;
-use mongodb::bson::{doc, oid::ObjectId, Document, DateTime};
+use mongodb::bson::{doc, oid::ObjectId, Bson, Document, DateTime};
+use mongodb::options::ReturnDocument;
 
-use crate::models::{MusteriKabul, CreateMusteriKabulRequest, MusteriKabulResponse, MusteriKabulListResponse, status_id_to_string, status_string_to_id, status_id_aliases};
-use crate::crypto::{encrypt_value, decrypt_value};
-use crate::sms::{send_sms, build_sms_message, build_montaj_ariza_sms_message, build_robot_kurulum_sms_message, build_tv_kurulum_sms_message, build_status_sms_message};
-use crate::auth::{LoginRequest, LoginResponse, generate_token, verify_admin_password};
-use crate::sms_queue::enqueue_status_sms;
+use crate::models::{
+    MusteriKabul, CreateMusteriKabulRequest, MusteriKabulResponse, MusteriKabulListResponse, StatusTransition, TransitionRequirement,
+    status_id_to_string, status_id_aliases, status_id_from_any, is_status_change, allowed_next_statuses, transition_requirements,
+};
+use crate::config::Config;
+use crate::crypto::FieldCipher;
+use crate::auth::{AuthUser, ROLE_MONTAJ_EKIBI, LoginRequest, LoginResponse, generate_token, verify_admin_password, hash_password, is_password_hash, verify_password};
+use crate::sms_consent::{self, ConsentChange, SmsConsent};
+use crate::sms_encoding;
+use crate::sms_log::{self, DeliveryReports, OutgoingSms, SendError, SmsMessage, SmsMessageResponse};
+use crate::sms_provider::{ProviderHealth, SmsProvider};
+use crate::sms_templates::{self, SmsTemplates, TemplateDef, TemplateInfo};
+use crate::sms_schedule::SendWindow;
+use crate::sms_queue::{self, enqueue_status_sms, SmsQueueItemResponse};
+use crate::trash;
+use crate::audit;
+use crate::pagination::{self, ListQuery, Page};
+use crate::phone_index::{backfill_telefon_index, telefon_index};
+use crate::search;
+use crate::attachments::{
+    record_attachment_ids, replaced_attachment_ids, AttachmentStore, FileLink, MAX_ATTACHMENT_BYTES,
+    migrate_embedded_files,
+};
+use crate::media::{self, DOCUMENT_KINDS, PHOTO_KINDS};
+use crate::fatura_link::{self, FaturaLinks, FaturaTarget};
+use crate::rate_limit::RateLimiter;
+use crate::tracking::{self, TrackingResponse};
+use crate::pickup_reminders::{self, AwaitingPickup};
+use crate::survey::{self, AnswerError, SurveyReport};
 
-const DELETE_OTP_PHONE_1: &str = "05300735686";
 const DELETE_OTP_EXPIRE_MINUTES: i64 = 10;
+const DELETE_OTP_MAX_ATTEMPTS: i32 = 5;
+const DELETE_OTP_MAX_LOCKED_REQUESTS: u64 = 3;
+const DELETE_OTP_LOCKOUT_MINUTES: i64 = 30;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: mongodb::Database,
+    pub config: Arc<Config>,
+    pub cipher: FieldCipher,
+    pub attachments: AttachmentStore,
+    pub fatura_links: FaturaLinks,
+    /// Per-IP limit for the public tracking endpoint.
+    pub tracking_limiter: RateLimiter,
+    /// Gateways every SMS goes through, see `sms.providers`. Send through
+    /// `sms_log::send` so the message is logged.
+    pub sms: Arc<dyn SmsProvider>,
+    pub sms_reports: DeliveryReports,
+    pub sms_templates: SmsTemplates,
+    /// When queued SMS may go out, see `sms.schedule`.
+    pub sms_window: SendWindow,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -142,6 +184,9 @@ pub struct CreateMontajRequest {
     pub telefon: String,
     pub adres: Option<String>,
     pub servis_tipi: String,
+    /// SMS consent given at the counter; absent leaves the number's record as is.
+    pub sms_izni: Option<bool>,
+    pub pazarlama_izni: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -185,6 +230,486 @@ async fn latest_doc_id_hex(collection: &mongodb::Collection<Document>) -> Result
     Ok(None)
 }
 
+/// Messages recorded by the mock SMS provider; 404 with a real gateway.
+pub async fn get_sms_outbox(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let messages = state.sms.outbox().ok_or((
+        StatusCode::NOT_FOUND,
+        "SMS kutusu yalnızca mock sağlayıcıda kullanılabilir".to_string(),
+    ))?;
+
+    Ok(Json(serde_json::json!({
+        "total": messages.len(),
+        "messages": messages,
+    })))
+}
+
+#[derive(Debug, Serialize)]
+pub struct SmsProviderStatus {
+    #[serde(flatten)]
+    pub health: ProviderHealth,
+    /// Webhook to configure at the gateway for delivery reports.
+    pub delivery_report_path: String,
+    /// Webhook for replies, used for opt-out keywords.
+    pub inbound_path: String,
+}
+
+/// Failover order and circuit state of the configured SMS gateways.
+pub async fn get_sms_provider_health(State(state): State<AppState>) -> Json<Vec<SmsProviderStatus>> {
+    Json(
+        state
+            .sms
+            .health()
+            .into_iter()
+            .map(|health| SmsProviderStatus {
+                delivery_report_path: state.sms_reports.path(health.provider),
+                inbound_path: state.sms_reports.inbound_path(health.provider),
+                health,
+            })
+            .collect(),
+    )
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmsMessageQuery {
+    pub customer_id: Option<String>,
+    pub montaj_id: Option<String>,
+    pub telefon: Option<String>,
+    pub state: Option<String>,
+    pub template: Option<String>,
+    pub provider: Option<String>,
+}
+
+/// Outgoing SMS log, newest first, filtered per customer/montaj record,
+/// phone number, state, template or provider.
+pub async fn list_sms_messages(
+    State(state): State<AppState>,
+    Query(filters): Query<SmsMessageQuery>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Page<SmsMessageResponse>>, (StatusCode, String)> {
+    let non_empty = |value: &Option<String>| value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+    let parse_id = |value: &str| {
+        ObjectId::parse_str(value).map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))
+    };
+
+    let mut filter = doc! {};
+    if let Some(id) = non_empty(&filters.customer_id) {
+        filter.insert("record_collection", "musteri_kabul");
+        filter.insert("record_id", parse_id(&id)?);
+    }
+    if let Some(id) = non_empty(&filters.montaj_id) {
+        filter.insert("record_collection", "montaj_kayitlari");
+        filter.insert("record_id", parse_id(&id)?);
+    }
+    if let Some(telefon) = non_empty(&filters.telefon) {
+        let index = telefon_index(&state.cipher, &telefon).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, "Telefon numarası en az 10 haneli olmalı".to_string())
+        })?;
+        filter.insert("recipient_hash", index);
+    }
+    for (field, value) in [
+        ("state", &filters.state),
+        ("template", &filters.template),
+        ("provider", &filters.provider),
+    ] {
+        if let Some(value) = non_empty(value) {
+            filter.insert(field, value.to_lowercase());
+        }
+    }
+
+    let plan = query.plan(filter).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let page = plan
+        .fetch(&state.db.collection::<Document>(sms_log::COLLECTION))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let mut items = Vec::with_capacity(page.items.len());
+    for item in &page.items {
+        let message: SmsMessage = mongodb::bson::from_document(item.clone())
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Deserialize error: {}", e)))?;
+        items.push(SmsMessageResponse::from(message));
+    }
+
+    Ok(Json(Page {
+        items,
+        total: page.total,
+        limit: page.limit,
+        next_cursor: page.next_cursor,
+    }))
+}
+
+fn sms_template_definition(key: &str) -> Result<&'static TemplateDef, (StatusCode, String)> {
+    sms_templates::definition(key).ok_or_else(|| (StatusCode::NOT_FOUND, format!("SMS şablonu bulunamadı: {}", key)))
+}
+
+/// Every SMS template with its current and built-in text.
+pub async fn list_sms_templates(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TemplateInfo>>, (StatusCode, String)> {
+    state
+        .sms_templates
+        .list()
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmsTemplateRequest {
+    pub body: String,
+}
+
+pub async fn update_sms_template(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(key): Path<String>,
+    Json(req): Json<SmsTemplateRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let def = sms_template_definition(&key)?;
+    let body = req.body.trim_end().to_string();
+    sms_templates::validate(def, &body).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let previous = state
+        .sms_templates
+        .current_body(def)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    state
+        .sms_templates
+        .save(def, &body, &auth_user.username)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    audit::record(
+        &state.db,
+        "sms_templates",
+        def.key,
+        "update",
+        &auth_user,
+        audit::diff(&doc! { "body": previous }, &doc! { "body": &body }),
+    )
+    .await;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "key": def.key,
+        "body": body,
+    })))
+}
+
+/// Drops the edited text so the built-in default is used again.
+pub async fn reset_sms_template(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(key): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let def = sms_template_definition(&key)?;
+
+    let previous = state
+        .sms_templates
+        .current_body(def)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let removed = state
+        .sms_templates
+        .reset(def)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    if removed {
+        audit::record(
+            &state.db,
+            "sms_templates",
+            def.key,
+            "reset",
+            &auth_user,
+            audit::diff(&doc! { "body": previous }, &doc! { "body": def.body }),
+        )
+        .await;
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "key": def.key,
+        "body": def.body,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmsTemplatePreviewRequest {
+    /// Unsaved text to preview; the current template when absent.
+    pub body: Option<String>,
+    /// Placeholder values; sample values fill the rest.
+    #[serde(default)]
+    pub values: HashMap<String, String>,
+}
+
+pub async fn preview_sms_template(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Json(req): Json<SmsTemplatePreviewRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let def = sms_template_definition(&key)?;
+
+    let body = match req.body {
+        Some(body) => {
+            sms_templates::validate(def, &body).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+            body
+        }
+        None => state
+            .sms_templates
+            .current_body(def)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+    };
+
+    let text = state.sms_templates.preview(&body, &req.values);
+    let prepared = sms_encoding::prepare(&text, state.config.sms.transliterate);
+    Ok(Json(serde_json::json!({
+        "key": def.key,
+        "text": text,
+        "encoding": sms_encoding::analyze(&text),
+        "sent_text": prepared.body,
+        "sent_encoding": prepared.info,
+        "transliterated": prepared.transliterated,
+    })))
+}
+
+/// Sending window for queued SMS and when the next one may go out.
+pub async fn get_sms_schedule(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let now = chrono::Utc::now();
+    Json(serde_json::json!({
+        "window": state.sms_window,
+        "open_now": state.sms_window.is_open(now),
+        "next_slot": state.sms_window.next_slot(now).to_rfc3339(),
+    }))
+}
+
+const SMS_QUEUE_LIST_LIMIT: i64 = 50;
+const SMS_QUEUE_LIST_MAX_LIMIT: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct SmsQueueQuery {
+    /// pending, sent, cancelled or dead
+    pub state: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Queued status SMS, newest first.
+pub async fn list_sms_queue(
+    State(state): State<AppState>,
+    Query(query): Query<SmsQueueQuery>,
+) -> Result<Json<Vec<SmsQueueItemResponse>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(SMS_QUEUE_LIST_LIMIT);
+    if !(1..=SMS_QUEUE_LIST_MAX_LIMIT).contains(&limit) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("limit 1 ile {} arasında olmalı", SMS_QUEUE_LIST_MAX_LIMIT),
+        ));
+    }
+    let filter = match query.state.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        Some(queue_state) => sms_queue::state_filter(queue_state).map_err(|e| (StatusCode::BAD_REQUEST, e))?,
+        None => doc! {},
+    };
+
+    let items = sms_queue::list_items(&state.db, filter, limit)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(items.into_iter().map(Into::into).collect()))
+}
+
+fn queue_item_error(e: sms_queue::ItemActionError) -> (StatusCode, String) {
+    match e {
+        sms_queue::ItemActionError::NotFound => {
+            (StatusCode::NOT_FOUND, "Gönderilmemiş kuyruk kaydı bulunamadı".to_string())
+        }
+        sms_queue::ItemActionError::Leased => (
+            StatusCode::CONFLICT,
+            "SMS şu anda gönderiliyor, biraz sonra tekrar deneyin".to_string(),
+        ),
+        sms_queue::ItemActionError::Database(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+pub async fn retry_sms_queue_item(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<SmsQueueItemResponse>, (StatusCode, String)> {
+    let object_id = ObjectId::parse_str(&id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
+
+    let item = sms_queue::retry_item(&state.db, object_id)
+        .await
+        .map_err(queue_item_error)?;
+
+    audit::record(&state.db, "sms_queue", &id, "retry", &auth_user, vec![]).await;
+
+    Ok(Json(item.into()))
+}
+
+pub async fn cancel_sms_queue_item(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<SmsQueueItemResponse>, (StatusCode, String)> {
+    let object_id = ObjectId::parse_str(&id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
+
+    let item = sms_queue::cancel_item(&state.db, object_id, &auth_user.username)
+        .await
+        .map_err(queue_item_error)?;
+
+    audit::record(&state.db, "sms_queue", &id, "cancel", &auth_user, vec![]).await;
+
+    Ok(Json(item.into()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveryReportQuery {
+    pub token: String,
+}
+
+/// Public webhook for gateway delivery reports. The URL (with its token) is
+/// listed per provider by `GET /api/sms/providers`.
+pub async fn receive_sms_delivery_report(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    Query(query): Query<DeliveryReportQuery>,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !state.sms_reports.verify(&provider, &query.token) {
+        return Err((StatusCode::FORBIDDEN, "Geçersiz rapor anahtarı".to_string()));
+    }
+
+    let reports = sms_log::parse_reports(&body);
+    if reports.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Rapor bulunamadı".to_string()));
+    }
+
+    let mut matched = 0;
+    for report in &reports {
+        if sms_log::apply_report(&state.db, &provider, report)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        {
+            matched += 1;
+        }
+    }
+
+    Ok(Json(serde_json::json!({
+        "received": reports.len(),
+        "matched": matched,
+    })))
+}
+
+/// Public webhook for replies to our SMS. A reply starting with an opt-out
+/// keyword (RET, İPTAL, STOP, DUR) stops all SMS to that number.
+pub async fn receive_sms_inbound(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+    Query(query): Query<DeliveryReportQuery>,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if !state.sms_reports.verify_inbound(&provider, &query.token) {
+        return Err((StatusCode::FORBIDDEN, "Geçersiz anahtar".to_string()));
+    }
+
+    let messages = sms_consent::parse_inbound(&body);
+    if messages.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Mesaj bulunamadı".to_string()));
+    }
+
+    let mut opted_out = 0;
+    for message in &messages {
+        if !sms_consent::is_opt_out(&message.text) {
+            continue;
+        }
+        let Some(hash) = telefon_index(&state.cipher, &message.phone) else {
+            continue;
+        };
+        sms_consent::set(&state.db, &hash, ConsentChange::opt_out(), sms_consent::SOURCE_KEYWORD, None)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        println!("🔕 SMS izni geri alındı ({} yanıtı)", provider);
+        opted_out += 1;
+    }
+
+    Ok(Json(serde_json::json!({
+        "received": messages.len(),
+        "opted_out": opted_out,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmsConsentQuery {
+    pub telefon: String,
+}
+
+fn consent_hash(state: &AppState, telefon: &str) -> Result<String, (StatusCode, String)> {
+    telefon_index(&state.cipher, telefon).ok_or_else(|| (StatusCode::BAD_REQUEST, "Geçersiz telefon numarası".to_string()))
+}
+
+/// SMS consent of a phone number; numbers without a record get the defaults.
+pub async fn get_sms_consent(
+    State(state): State<AppState>,
+    Query(query): Query<SmsConsentQuery>,
+) -> Result<Json<SmsConsent>, (StatusCode, String)> {
+    let hash = consent_hash(&state, &query.telefon)?;
+    let consent = sms_consent::get(&state.db, &hash)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .unwrap_or_else(|| SmsConsent::default_for(&hash));
+    Ok(Json(consent))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateSmsConsentRequest {
+    pub telefon: String,
+    pub transactional: Option<bool>,
+    pub marketing: Option<bool>,
+}
+
+pub async fn update_sms_consent(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Json(req): Json<UpdateSmsConsentRequest>,
+) -> Result<Json<SmsConsent>, (StatusCode, String)> {
+    let hash = consent_hash(&state, &req.telefon)?;
+    let change = ConsentChange {
+        transactional: req.transactional,
+        marketing: req.marketing,
+    };
+    if change.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "transactional veya marketing verilmeli".to_string()));
+    }
+
+    let previous = sms_consent::get(&state.db, &hash)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .unwrap_or_else(|| SmsConsent::default_for(&hash));
+    let consent = sms_consent::set(&state.db, &hash, change, sms_consent::SOURCE_ADMIN, Some(&auth_user.username))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    audit::record(
+        &state.db,
+        sms_consent::COLLECTION,
+        &hash,
+        "update",
+        &auth_user,
+        audit::diff(
+            &doc! { "transactional": previous.transactional, "marketing": previous.marketing },
+            &doc! { "transactional": consent.transactional, "marketing": consent.marketing },
+        ),
+    )
+    .await;
+
+    Ok(Json(consent))
+}
+
 pub async fn get_system_sync(
     State(state): State<AppState>,
 ) -> Result<Json<SystemSyncResponse>, (StatusCode, String)> {
@@ -192,7 +717,7 @@ pub async fn get_system_sync(
     let users_collection = state.db.collection::<Document>("users");
 
     let musteri_total = musteri_collection
-        .count_documents(doc! {})
+        .count_documents(trash::active(doc! {}))
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
 
@@ -234,11 +759,8 @@ fn header_value(headers: &HeaderMap, key: &str) -> Option<String> {
         .filter(|v| !v.is_empty())
 }
 
-fn resolve_frontend_url(headers: &HeaderMap) -> String {
-    let env_frontend = std::env::var("FRONTEND_URL")
-        .ok()
-        .map(|v| v.trim().to_string())
-        .filter(|v| !v.is_empty());
+fn resolve_frontend_url(config: &Config, headers: &HeaderMap) -> String {
+    let env_frontend = config.frontend_url.clone();
 
     let forwarded_host = header_value(headers, "x-forwarded-host");
     let host = forwarded_host.or_else(|| header_value(headers, "host"));
@@ -254,16 +776,33 @@ fn resolve_frontend_url(headers: &HeaderMap) -> String {
     env_frontend.unwrap_or_else(|| "http://localhost:5173".to_string())
 }
 
-fn extract_client_ip(headers: &HeaderMap) -> Option<String> {
+/// Client address for rate limiting and captcha checks. Forwarded headers are
+/// only read when the connection comes from one of `TRUSTED_PROXIES`; in
+/// `x-forwarded-for` the rightmost hop that is not itself a trusted proxy wins.
+fn extract_client_ip(config: &Config, peer: SocketAddr, headers: &HeaderMap) -> String {
+    let trusted = |ip: &IpAddr| config.trusted_proxies.contains(ip);
+    if !trusted(&peer.ip()) {
+        return peer.ip().to_string();
+    }
+
+    let parse = |value: &str| value.trim().parse::<IpAddr>().ok();
     header_value(headers, "cf-connecting-ip")
-        .or_else(|| header_value(headers, "x-forwarded-for").map(|v| v.split(',').next().unwrap_or("").trim().to_string()))
-        .filter(|v| !v.is_empty())
+        .and_then(|value| parse(&value))
+        .or_else(|| {
+            header_value(headers, "x-forwarded-for")?
+                .rsplit(',')
+                .filter_map(parse)
+                .find(|ip| !trusted(ip))
+        })
+        .unwrap_or(peer.ip())
+        .to_string()
 }
 
-async fn verify_turnstile_token(token: &str, remote_ip: Option<String>) -> Result<bool, String> {
-    let secret = std::env::var("TURNSTILE_SECRET_KEY")
-        .or_else(|_| std::env::var("CLOUDFLARE_TURNSTILE_SECRET_KEY"))
-        .map_err(|_| "Turnstile secret key tanımlı değil".to_string())?;
+async fn verify_turnstile_token(config: &Config, token: &str, remote_ip: Option<String>) -> Result<bool, String> {
+    let secret = config
+        .turnstile_secret_key
+        .clone()
+        .ok_or_else(|| "Turnstile secret key tanımlı değil".to_string())?;
 
     let client = reqwest::Client::new();
     let mut form_data = vec![
@@ -290,10 +829,226 @@ async fn verify_turnstile_token(token: &str, remote_ip: Option<String>) -> Resul
     Ok(parsed.success)
 }
 
+/// Record a public fatura token points at, if the token is valid and has not
+/// been revoked or superseded.
+async fn fatura_link_record(
+    state: &AppState,
+    token: &str,
+) -> Result<(fatura_link::FaturaClaims, Document), (StatusCode, String)> {
+    let claims = state
+        .fatura_links
+        .verify(token)
+        .map_err(|e| (StatusCode::FORBIDDEN, e))?;
+
+    let mut filter = trash::active(doc! { "_id": claims.id });
+    filter.extend(fatura_link::version_filter(claims.version));
+
+    let record = state
+        .db
+        .collection::<Document>(claims.target.collection())
+        .find_one(filter)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
+        .ok_or((
+            StatusCode::GONE,
+            "Fatura bağlantısı artık geçerli değil, lütfen servisle iletişime geçin".to_string(),
+        ))?;
+
+    Ok((claims, record))
+}
+
+/// Read-only summary for the public upload page: whom the link is for,
+/// without exposing the full name or phone number.
+pub async fn get_fatura_upload_info(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (claims, record) = fatura_link_record(&state, &token).await?;
+
+    Ok(Json(serde_json::json!({
+        "ad_soyad": fatura_link::mask_name(record.get_str("ad_soyad").unwrap_or("")),
+        "cihaz": record.get_str(claims.target.device_field()).unwrap_or(""),
+        "fatura_yuklendi": record.get_str("belge_f").is_ok_and(|v| !v.is_empty()),
+        "expires_at": chrono::DateTime::from_timestamp(claims.expires_at, 0)
+            .map(|at| at.to_rfc3339())
+            .unwrap_or_default(),
+    })))
+}
+
+/// Public repair status page data, looked up by the token sent in the
+/// intake SMS. Rate limited per client IP so tokens cannot be enumerated.
+pub async fn get_tracking(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> Result<Json<TrackingResponse>, (StatusCode, String)> {
+    let client = extract_client_ip(&state.config, peer, &headers);
+    if !state.tracking_limiter.check(&client) {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            "Çok fazla istek, lütfen biraz sonra tekrar deneyin".to_string(),
+        ));
+    }
+
+    let not_found = || (StatusCode::NOT_FOUND, "Kayıt bulunamadı".to_string());
+    if !tracking::is_valid_token(&token) {
+        return Err(not_found());
+    }
+
+    let musteri = state
+        .db
+        .collection::<MusteriKabul>("musteri_kabul")
+        .find_one(trash::active(doc! { tracking::TOKEN_FIELD: &token }))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
+        .ok_or_else(not_found)?;
+
+    Ok(Json(TrackingResponse::from_musteri(&musteri)))
+}
+
+/// Public survey page data: masked name, device and whether it was answered.
+#[derive(Debug, Serialize)]
+pub struct SurveyInfoResponse {
+    pub ad_soyad: String,
+    pub cihaz: String,
+    pub yanitlandi: bool,
+    pub puan: Option<i32>,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SurveyAnswerRequest {
+    pub puan: i32,
+    pub yorum: Option<String>,
+}
+
+/// Looks up a survey for the public endpoints, rate limited like tracking.
+async fn public_survey(
+    state: &AppState,
+    peer: SocketAddr,
+    headers: &HeaderMap,
+    token: &str,
+) -> Result<survey::Survey, (StatusCode, String)> {
+    let client = extract_client_ip(&state.config, peer, headers);
+    if !state.tracking_limiter.check(&client) {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            "Çok fazla istek, lütfen biraz sonra tekrar deneyin".to_string(),
+        ));
+    }
+
+    let not_found = || (StatusCode::NOT_FOUND, "Anket bulunamadı".to_string());
+    if !tracking::is_valid_token(token) {
+        return Err(not_found());
+    }
+
+    survey::find(&state.db, token)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(not_found)
+}
+
+pub async fn get_survey(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> Result<Json<SurveyInfoResponse>, (StatusCode, String)> {
+    let survey = public_survey(&state, peer, &headers, &token).await?;
+
+    let musteri = state
+        .db
+        .collection::<MusteriKabul>("musteri_kabul")
+        .find_one(trash::active(doc! { "_id": survey.musteri_id }))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
+        .ok_or_else(|| (StatusCode::NOT_FOUND, "Anket bulunamadı".to_string()))?;
+
+    Ok(Json(SurveyInfoResponse {
+        ad_soyad: fatura_link::mask_name(&musteri.ad_soyad),
+        cihaz: musteri.marka_model,
+        yanitlandi: survey.answered_at.is_some(),
+        puan: survey.puan,
+        expires_at: survey.expires_at.to_rfc3339(),
+    }))
+}
+
+/// Stores the customer's rating (1–5) and optional comment; once per survey.
+pub async fn answer_survey(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+    Json(req): Json<SurveyAnswerRequest>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if !(survey::MIN_RATING..=survey::MAX_RATING).contains(&req.puan) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Puan {} ile {} arasında olmalı", survey::MIN_RATING, survey::MAX_RATING),
+        ));
+    }
+    let yorum = req
+        .yorum
+        .map(|yorum| yorum.trim().to_string())
+        .filter(|yorum| !yorum.is_empty());
+    if yorum
+        .as_ref()
+        .is_some_and(|yorum| yorum.chars().count() > survey::MAX_COMMENT_CHARS)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Yorum en fazla {} karakter olabilir", survey::MAX_COMMENT_CHARS),
+        ));
+    }
+
+    let survey = public_survey(&state, peer, &headers, &token).await?;
+
+    survey::answer(&state.db, &survey, req.puan, yorum)
+        .await
+        .map_err(|e| match e {
+            AnswerError::Expired => (StatusCode::GONE, "Anketin süresi dolmuş".to_string()),
+            AnswerError::AlreadyAnswered => (StatusCode::CONFLICT, "Anket zaten yanıtlanmış".to_string()),
+            AnswerError::Database(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+        })?;
+
+    println!("⭐ Anket yanıtlandı: {} ({} puan)", survey.musteri_id, req.puan);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SurveyReportQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Satisfaction per technician and servis_tipi for devices delivered in the
+/// `from`–`to` range (dates or RFC3339, as in the list endpoints).
+pub async fn get_survey_report(
+    State(state): State<AppState>,
+    Query(query): Query<SurveyReportQuery>,
+) -> Result<Json<SurveyReport>, (StatusCode, String)> {
+    let bound = |value: Option<String>, end_of_day: bool| {
+        value
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| pagination::parse_bound(&value, end_of_day))
+            .transpose()
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))
+    };
+    let from = bound(query.from, false)?;
+    let to = bound(query.to, true)?;
+
+    survey::report(&state.db, from, to)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
 pub async fn upload_fatura_public(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Path(id): Path<String>,
+    Path(token): Path<String>,
     Json(req): Json<PublicFaturaUploadRequest>,
 ) -> Result<StatusCode, (StatusCode, String)> {
     if req.belge_f.trim().is_empty() {
@@ -304,56 +1059,189 @@ pub async fn upload_fatura_public(
         return Err((StatusCode::BAD_REQUEST, "Captcha doğrulaması gerekli".to_string()));
     }
 
-    let is_valid_captcha = verify_turnstile_token(&req.turnstile_token, extract_client_ip(&headers))
-        .await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+    let is_valid_captcha = verify_turnstile_token(
+        &state.config,
+        &req.turnstile_token,
+        Some(extract_client_ip(&state.config, peer, &headers)),
+    )
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
 
     if !is_valid_captcha {
         return Err((StatusCode::UNAUTHORIZED, "Captcha doğrulaması başarısız".to_string()));
     }
 
-    let object_id = ObjectId::parse_str(&id)
-        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
+    // Checks the link version before anything is stored
+    let (claims, _) = fatura_link_record(&state, &token).await?;
+    let object_id = claims.id;
 
-    // First, try to find in montaj_kayitlari
-    let montaj_collection = state.db.collection::<Document>("montaj_kayitlari");
-    let montaj_result = montaj_collection
-        .update_one(
-            doc! { "_id": object_id },
-            doc! {
-                "$set": {
-                    "belge_f": req.belge_f.clone(),
-                    "updated_at": chrono::Utc::now().to_rfc3339(),
-                }
-            },
-        )
+    // Public uploads may only add new content, never point at existing attachments
+    let belge_f = state
+        .attachments
+        .store_data_url(&state.db, &req.belge_f, DOCUMENT_KINDS, "public")
+        .await?;
+
+    let set_doc = doc! {
+        "belge_f": belge_f.clone(),
+        "updated_at": chrono::Utc::now().to_rfc3339(),
+    };
+
+    // Re-check the version: the link may have been revoked while uploading
+    let collection_name = claims.target.collection();
+    let mut filter = trash::active(doc! { "_id": object_id });
+    filter.extend(fatura_link::version_filter(claims.version));
+
+    let linked = state
+        .db
+        .collection::<Document>(collection_name)
+        .find_one_and_update(filter, doc! { "$set": set_doc.clone() })
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
+        .and_then(|previous| {
+            previous.ok_or((
+                StatusCode::GONE,
+                "Fatura bağlantısı artık geçerli değil, lütfen servisle iletişime geçin".to_string(),
+            ))
+        });
+    let previous = match linked {
+        Ok(previous) => previous,
+        Err(e) => {
+            // Nothing points at the new file, don't leave it behind
+            if let Err(discard_error) = state.attachments.discard(&state.db, &belge_f).await {
+                tracing::error!("Unlinked fatura upload could not be deleted: {}", discard_error);
+            }
+            return Err(e);
+        }
+    };
 
-    if montaj_result.matched_count > 0 {
-        return Ok(StatusCode::NO_CONTENT);
-    }
+    audit::record(
+        &state.db,
+        collection_name,
+        &object_id.to_hex(),
+        "fatura_upload",
+        &audit::public_actor(),
+        audit::diff(&previous, &set_doc),
+    )
+    .await;
+    release_attachments(&state, &replaced_attachment_ids(&previous, &set_doc)).await;
 
-    // If not found in montaj_kayitlari, try musteri_kabul
-    let collection = state.db.collection::<MusteriKabul>("musteri_kabul");
-    let result = collection
-        .update_one(
-            doc! { "_id": object_id },
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Invalidates every outstanding fatura link of a record by bumping its link
+/// version. Returns the new version.
+async fn bump_fatura_link_version(
+    state: &AppState,
+    target: FaturaTarget,
+    id: &str,
+    action: &str,
+    auth_user: &AuthUser,
+) -> Result<(ObjectId, i64), (StatusCode, String)> {
+    let object_id = ObjectId::parse_str(id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
+
+    let updated = state
+        .db
+        .collection::<Document>(target.collection())
+        .find_one_and_update(
+            trash::active(doc! { "_id": object_id }),
             doc! {
-                "$set": {
-                    "belge_f": req.belge_f,
-                    "updated_at": chrono::Utc::now().to_rfc3339(),
-                }
+                "$inc": { fatura_link::VERSION_FIELD: 1_i64 },
+                "$set": { "updated_at": chrono::Utc::now().to_rfc3339() },
             },
         )
+        .return_document(ReturnDocument::After)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
+        .ok_or((StatusCode::NOT_FOUND, "Kayıt bulunamadı".to_string()))?;
+
+    let version = fatura_link::current_version(&updated);
+    audit::record(
+        &state.db,
+        target.collection(),
+        &object_id.to_hex(),
+        action,
+        auth_user,
+        vec![audit::FieldChange {
+            field: fatura_link::VERSION_FIELD.to_string(),
+            before: Bson::Int64(version - 1),
+            after: Bson::Int64(version),
+        }],
+    )
+    .await;
 
-    if result.matched_count == 0 {
-        return Err((StatusCode::NOT_FOUND, "Montaj kaydı veya müşteri bulunamadı".to_string()));
-    }
+    Ok((object_id, version))
+}
 
-    Ok(StatusCode::NO_CONTENT)
+async fn reissue_fatura_link(
+    state: &AppState,
+    headers: &HeaderMap,
+    target: FaturaTarget,
+    id: &str,
+    auth_user: &AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (object_id, version) = bump_fatura_link_version(state, target, id, "fatura_link_reissue", auth_user).await?;
+
+    let (token, expires_at) = state.fatura_links.issue(target, &object_id, version);
+    let frontend_url = resolve_frontend_url(&state.config, headers);
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "link": format!("{}/fatura/{}", frontend_url.trim_end_matches('/'), token),
+        "expires_at": chrono::DateTime::from_timestamp(expires_at, 0)
+            .map(|at| at.to_rfc3339())
+            .unwrap_or_default(),
+    })))
+}
+
+async fn revoke_fatura_link(
+    state: &AppState,
+    target: FaturaTarget,
+    id: &str,
+    auth_user: &AuthUser,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    bump_fatura_link_version(state, target, id, "fatura_link_revoke", auth_user).await?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "Fatura bağlantısı iptal edildi"
+    })))
+}
+
+/// Issues a new fatura upload link for a montaj record; earlier links stop working.
+pub async fn reissue_montaj_fatura_link(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    reissue_fatura_link(&state, &headers, FaturaTarget::Montaj, &id, &auth_user).await
+}
+
+pub async fn revoke_montaj_fatura_link(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    revoke_fatura_link(&state, FaturaTarget::Montaj, &id, &auth_user).await
+}
+
+/// Issues a new fatura upload link for a musteri record; earlier links stop working.
+pub async fn reissue_musteri_fatura_link(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    reissue_fatura_link(&state, &headers, FaturaTarget::Musteri, &id, &auth_user).await
+}
+
+pub async fn revoke_musteri_fatura_link(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    revoke_fatura_link(&state, FaturaTarget::Musteri, &id, &auth_user).await
 }
 
 fn normalize_delete_action(action: &str) -> Option<&'static str> {
@@ -361,6 +1249,7 @@ fn normalize_delete_action(action: &str) -> Option<&'static str> {
     match normalized.as_str() {
         "musteri" | "customer" => Some("musteri"),
         "user" | "kullanici" => Some("user"),
+        "montaj" | "montaj_kayit" | "montaj_kayitlari" => Some("montaj"),
         _ => None,
     }
 }
@@ -403,52 +1292,105 @@ fn generate_otp_code() -> String {
     format!("{:06}", code)
 }
 
+/// Checks the `x-delete-otp-*` headers against an unused OTP issued for exactly
+/// this action/resource. Wrong codes count against the request and lock it.
 async fn verify_delete_otp(
     state: &AppState,
     headers: &HeaderMap,
+    auth_user: &AuthUser,
     action: &str,
     resource_id: &str,
 ) -> Result<(), (StatusCode, String)> {
     let request_id = header_value(headers, "x-delete-otp-id")
-        .ok_or((StatusCode::UNAUTHORIZED, "OTP isteği bulunamadı".to_string()))?;
+        .ok_or((StatusCode::FORBIDDEN, "Silme işlemi için OTP doğrulaması gerekli".to_string()))?;
 
     let code_1 = header_value(headers, "x-delete-otp-code-1")
-        .ok_or((StatusCode::UNAUTHORIZED, "OTP kodu eksik".to_string()))?;
+        .ok_or((StatusCode::FORBIDDEN, "OTP kodu eksik".to_string()))?;
 
     let otp_id = ObjectId::parse_str(&request_id)
         .map_err(|_| (StatusCode::BAD_REQUEST, "Geçersiz OTP request id".to_string()))?;
 
     let otp_collection = state.db.collection::<Document>("delete_otp_requests");
     let otp_doc = otp_collection
-        .find_one(doc! {
-            "_id": otp_id,
-            "action": action,
-            "resource_id": resource_id,
-            "used": false,
-        })
+        .find_one(doc! { "_id": otp_id })
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
-        .ok_or((StatusCode::UNAUTHORIZED, "OTP doğrulama kaydı bulunamadı".to_string()))?;
+        .ok_or((StatusCode::FORBIDDEN, "OTP doğrulama kaydı bulunamadı".to_string()))?;
+
+    let bound_to_resource = otp_doc.get_str("action").ok() == Some(action)
+        && otp_doc.get_str("resource_id").ok() == Some(resource_id)
+        && otp_doc.get_str("requested_by").ok() == Some(auth_user.username.as_str());
+
+    if !bound_to_resource {
+        return Err((StatusCode::FORBIDDEN, "OTP bu kayıt için verilmemiş".to_string()));
+    }
+
+    if otp_doc.get_bool("used").unwrap_or(true) {
+        return Err((StatusCode::FORBIDDEN, "OTP daha önce kullanılmış".to_string()));
+    }
+
+    if otp_doc.get_bool("locked").unwrap_or(false) {
+        return Err((StatusCode::TOO_MANY_REQUESTS, "Çok fazla hatalı deneme. Yeni kod isteyin".to_string()));
+    }
 
     let expires_at = otp_doc
         .get_datetime("expires_at")
-        .map_err(|_| (StatusCode::UNAUTHORIZED, "OTP süresi dolmuş".to_string()))?;
+        .map_err(|_| (StatusCode::FORBIDDEN, "OTP süresi dolmuş".to_string()))?;
 
     if *expires_at < DateTime::now() {
-        return Err((StatusCode::UNAUTHORIZED, "OTP süresi dolmuş".to_string()));
+        return Err((StatusCode::FORBIDDEN, "OTP süresi dolmuş".to_string()));
     }
 
     let expected_code_1 = otp_doc
         .get_str("code_1")
-        .map_err(|_| (StatusCode::UNAUTHORIZED, "OTP kodları bulunamadı".to_string()))?;
+        .map_err(|_| (StatusCode::FORBIDDEN, "OTP kodları bulunamadı".to_string()))?;
+
+    if !crate::auth::constant_time_eq(code_1.as_bytes(), expected_code_1.as_bytes()) {
+        // Counted in the filter so concurrent guesses can't exceed the limit
+        let counted = otp_collection
+            .find_one_and_update(
+                doc! {
+                    "_id": otp_id,
+                    "used": false,
+                    "locked": { "$ne": true },
+                    "attempts": { "$lt": DELETE_OTP_MAX_ATTEMPTS },
+                },
+                doc! {
+                    "$inc": { "attempts": 1 },
+                    "$set": { "last_failed_at": DateTime::now() },
+                },
+            )
+            .return_document(ReturnDocument::After)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
+            .ok_or((StatusCode::TOO_MANY_REQUESTS, "Çok fazla hatalı deneme. Yeni kod isteyin".to_string()))?;
+
+        let attempts = counted.get_i32("attempts").unwrap_or(DELETE_OTP_MAX_ATTEMPTS);
+        if attempts >= DELETE_OTP_MAX_ATTEMPTS {
+            otp_collection
+                .update_one(doc! { "_id": otp_id }, doc! { "$set": { "locked": true } })
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+
+            println!("🔒 OTP kilitlendi: {} ({} {})", request_id, action, resource_id);
+            return Err((StatusCode::TOO_MANY_REQUESTS, "Çok fazla hatalı deneme. Yeni kod isteyin".to_string()));
+        }
 
-    if code_1 != expected_code_1 {
-        return Err((StatusCode::UNAUTHORIZED, "OTP kodu hatalı".to_string()));
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("OTP kodu hatalı. Kalan deneme: {}", DELETE_OTP_MAX_ATTEMPTS - attempts),
+        ));
     }
 
-    otp_collection
+    // Mark used only if still unused, so two concurrent deletes can't share one code
+    let claimed = otp_collection
         .update_one(
-            doc! { "_id": otp_id },
+            doc! {
+                "_id": otp_id,
+                "used": false,
+                "locked": { "$ne": true },
+                "attempts": { "$lt": DELETE_OTP_MAX_ATTEMPTS },
+            },
             doc! {
                 "$set": {
                     "used": true,
@@ -459,42 +1401,77 @@ async fn verify_delete_otp(
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
 
+    if claimed.modified_count == 0 {
+        return Err((StatusCode::FORBIDDEN, "OTP daha önce kullanılmış".to_string()));
+    }
+
     Ok(())
 }
 
 pub async fn create_delete_otp(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Json(req): Json<CreateDeleteOtpRequest>,
 ) -> Result<Json<CreateDeleteOtpResponse>, (StatusCode, String)> {
     let action = normalize_delete_action(&req.action)
         .ok_or((StatusCode::BAD_REQUEST, "Geçersiz silme aksiyonu".to_string()))?;
 
-    ObjectId::parse_str(&req.resource_id)
-        .map_err(|_| (StatusCode::BAD_REQUEST, "Geçersiz kayıt id".to_string()))?;
+    let resource_id = ObjectId::parse_str(&req.resource_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Geçersiz kayıt id".to_string()))?
+        .to_hex();
 
-    let code_1 = generate_otp_code();
-    let sms_1 = format!(
-        "SIS Teknik silme onay kodunuz: {}. Bu kod 10 dakika geçerlidir.",
-        code_1
-    );
+    let otp_collection = state.db.collection::<Document>("delete_otp_requests");
 
-    send_sms(DELETE_OTP_PHONE_1, &sms_1)
+    // Lock the user out of new codes after repeatedly burning OTPs with wrong guesses
+    let lockout_since = DateTime::from_millis(
+        (chrono::Utc::now() - chrono::Duration::minutes(DELETE_OTP_LOCKOUT_MINUTES)).timestamp_millis(),
+    );
+    let recent_locked = otp_collection
+        .count_documents(doc! {
+            "requested_by": &auth_user.username,
+            "locked": true,
+            "last_failed_at": { "$gte": lockout_since },
+        })
         .await
-        .map_err(|e| (StatusCode::BAD_GATEWAY, format!("1. numaraya OTP gönderilemedi: {}", e)))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+
+    if recent_locked >= DELETE_OTP_MAX_LOCKED_REQUESTS {
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("Çok fazla hatalı OTP denemesi. {} dakika sonra tekrar deneyin", DELETE_OTP_LOCKOUT_MINUTES),
+        ));
+    }
+
+    let code_1 = generate_otp_code();
+    let sms_1 = state.sms_templates.render("otp", &[("kod", &code_1)]).await;
+    let logged_sms_1 = state.sms_templates.render("otp", &[("kod", "******")]).await;
+
+    sms_log::send(
+        &state.db,
+        &state.cipher,
+        state.sms.as_ref(),
+        OutgoingSms::new(sms_log::TEMPLATE_OTP, &state.config.delete_otp_phone, &sms_1)
+            .logged_as(logged_sms_1)
+            .internal(),
+    )
+    .await
+    .map_err(|e| (StatusCode::BAD_GATEWAY, format!("1. numaraya OTP gönderilemedi: {}", e)))?;
 
     let otp_id = ObjectId::new();
     let expires_at = DateTime::from_millis(
         (chrono::Utc::now() + chrono::Duration::minutes(DELETE_OTP_EXPIRE_MINUTES)).timestamp_millis(),
     );
 
-    let otp_collection = state.db.collection::<Document>("delete_otp_requests");
     otp_collection
         .insert_one(doc! {
             "_id": otp_id,
             "action": action,
-            "resource_id": req.resource_id,
+            "resource_id": resource_id,
+            "requested_by": &auth_user.username,
             "code_1": code_1,
             "used": false,
+            "attempts": 0,
+            "locked": false,
             "created_at": DateTime::now(),
             "expires_at": expires_at,
         })
@@ -509,6 +1486,8 @@ pub async fn create_delete_otp(
 
 pub async fn create_montaj_kayit(
     State(state): State<AppState>,
+    auth_user: AuthUser,
+    headers: HeaderMap,
     Json(req): Json<CreateMontajRequest>,
 ) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
     let ad_soyad = req.ad_soyad.trim();
@@ -520,6 +1499,11 @@ pub async fn create_montaj_kayit(
         return Err((StatusCode::BAD_REQUEST, "Ad Soyad, Model, Telefon ve Servis Tipi zorunludur".to_string()));
     }
 
+    let consent = ConsentChange {
+        transactional: req.sms_izni,
+        marketing: req.pazarlama_izni,
+    };
+
     let collection = state.db.collection::<Document>("montaj_kayitlari");
 
     let now = chrono::Utc::now().to_rfc3339();
@@ -530,53 +1514,60 @@ pub async fn create_montaj_kayit(
         "telefon": telefon.to_string(),
         "adres": req.adres.unwrap_or_default().trim().to_string(),
         "servis_tipi": servis_tipi.to_uppercase(),
+        fatura_link::VERSION_FIELD: 0_i64,
         "created_at": now.clone(),
         "updated_at": now,
     };
 
     let result = collection
-        .insert_one(doc)
+        .insert_one(&doc)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
 
-    let id = result
-        .inserted_id
-        .as_object_id()
-        .map(|oid| oid.to_hex())
-        .unwrap_or_default();
+    let object_id = result.inserted_id.as_object_id().unwrap_or_default();
+    let id = object_id.to_hex();
+
+    audit::record(&state.db, "montaj_kayitlari", &id, "create", &auth_user, audit::snapshot(&doc)).await;
+
+    let consent_saved = sms_consent::record_form_consent(
+        &state.db,
+        telefon_index(&state.cipher, telefon).as_deref(),
+        consent,
+        sms_consent::SOURCE_MONTAJ,
+        &auth_user.username,
+    )
+    .await;
 
     // Send SMS notification about montaj record creation
-    let fatura_link = format!("https://tamir.sis-teknik.com.tr/fatura/{}", id);
+    let fatura_link = state.fatura_links.url(
+        &resolve_frontend_url(&state.config, &headers),
+        FaturaTarget::Montaj,
+        &object_id,
+        0,
+    );
     
-    let sms_message = match servis_tipi.to_lowercase().as_str() {
-        "tv kurulum" | "tv" => {
-            format!(
-                "SN : {}\nTV KURULUM KAYDI ALINDI.\nFATURA: {}\nFATURA YUKLENMEDEN HIZMET VERILMEZ.",
-                ad_soyad, fatura_link
-            )
-        },
-        "robot kurulum" | "robot" => {
-            format!(
-                "SN : {}\nROBOT KURULUM KAYDI ALINDI.\nFATURA: {}\nFATURA YUKLENMEDEN HIZMET VERILMEZ.",
-                ad_soyad, fatura_link
-            )
-        },
-        _ => {
-            // Default for other servis_tipi values
-            format!(
-                "SN : {}\nMONTAJ KAYDI ALINDI.\nCİHAZ: {}\nFATURA: {}\nFATURA YUKLENMEDEN HIZMET VERILMEZ.",
-                ad_soyad, model, fatura_link
-            )
-        }
+    let template = match servis_tipi.to_lowercase().as_str() {
+        "tv kurulum" | "tv" => "montaj_tv_kurulum",
+        "robot kurulum" | "robot" => "montaj_robot_kurulum",
+        _ => "montaj_diger",
     };
+    let sms_message = state
+        .sms_templates
+        .render(template, &[("ad_soyad", ad_soyad), ("model", model), ("link", &fatura_link)])
+        .await;
     
-    match send_sms(telefon, &sms_message).await {
-        Ok(response) => {
-            println!("✅ SMS gönderildi - Montaj Kaydı: {}, Response: {:?}", id, response);
-        }
-        Err(e) => {
-            println!("⚠️ SMS gönderilemedi - Montaj Kaydı: {}, Error: {}", id, e);
-            // Don't fail the whole operation if SMS fails
+    let outgoing = OutgoingSms::new(sms_log::TEMPLATE_MONTAJ, telefon, &sms_message).record("montaj_kayitlari", object_id);
+    if !consent_saved {
+        println!("⚠️ SMS izni kaydedilemedi, SMS gönderilmedi - Montaj Kaydı: {}", id);
+    } else {
+        match sms_log::send(&state.db, &state.cipher, state.sms.as_ref(), outgoing).await {
+            Ok(response) => {
+                println!("✅ SMS gönderildi ({}) - Montaj Kaydı: {}, Response: {:?}", response.provider, id, response);
+            }
+            Err(e) => {
+                println!("⚠️ SMS gönderilemedi - Montaj Kaydı: {}, Error: {}", id, e);
+                // Don't fail the whole operation if SMS fails
+            }
         }
     }
 
@@ -590,53 +1581,66 @@ pub async fn create_montaj_kayit(
     ))
 }
 
+fn montaj_list_item(current: &Document) -> serde_json::Value {
+    let id = current
+        .get_object_id("_id")
+        .ok()
+        .map(|v| v.to_hex())
+        .unwrap_or_default();
+
+    serde_json::json!({
+        "id": id,
+        "rnu_is_emri_no": current.get_str("rnu_is_emri_no").ok().unwrap_or(""),
+        "ad_soyad": current.get_str("ad_soyad").ok().unwrap_or(""),
+        "model": current.get_str("model").ok().unwrap_or(""),
+        "telefon": current.get_str("telefon").ok().unwrap_or(""),
+        "adres": current.get_str("adres").ok().unwrap_or(""),
+        "servis_tipi": current.get_str("servis_tipi").ok().unwrap_or(""),
+        "atanan_kullanici_username": current.get_str("atanan_kullanici_username").ok().unwrap_or(""),
+        "kapatildi": current.get_bool("kapatildi").ok().unwrap_or(false),
+        "belge_f": current.get_str("belge_f").ok().map(|s| !s.is_empty()).unwrap_or(false),
+        "created_at": current.get_str("created_at").ok().unwrap_or(""),
+    })
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MontajListFilter {
+    /// Only records assigned to this username.
+    pub atanan: Option<String>,
+    /// Only records that are not closed yet.
+    #[serde(default)]
+    pub acik: bool,
+}
+
 pub async fn list_montaj_kayitlari(
     State(state): State<AppState>,
-) -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
+    Query(query): Query<ListQuery>,
+    Query(filter): Query<MontajListFilter>,
+) -> Result<Json<Page<serde_json::Value>>, (StatusCode, String)> {
     let collection = state.db.collection::<Document>("montaj_kayitlari");
 
-    let mut cursor = collection
-        .find(doc! {})
-        .sort(doc! { "_id": -1 })
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+    let mut base_filter = doc! {};
+    if let Some(atanan) = filter.atanan.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        base_filter.insert("atanan_kullanici_username", atanan.to_lowercase());
+    }
+    if filter.acik {
+        base_filter.insert("kapatildi", doc! { "$ne": true });
+    }
 
-    let mut results = Vec::new();
-    while cursor
-        .advance()
+    let plan = query
+        .plan(trash::active(base_filter))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let page = plan
+        .fetch(&collection)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Cursor error: {}", e)))?
-    {
-        let current: Document = cursor
-            .deserialize_current()
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Deserialize error: {}", e)))?;
-
-        let id = current
-            .get_object_id("_id")
-            .ok()
-            .map(|v| v.to_hex())
-            .unwrap_or_default();
-
-        results.push(serde_json::json!({
-            "id": id,
-            "rnu_is_emri_no": current.get_str("rnu_is_emri_no").ok().unwrap_or(""),
-            "ad_soyad": current.get_str("ad_soyad").ok().unwrap_or(""),
-            "model": current.get_str("model").ok().unwrap_or(""),
-            "telefon": current.get_str("telefon").ok().unwrap_or(""),
-            "adres": current.get_str("adres").ok().unwrap_or(""),
-            "servis_tipi": current.get_str("servis_tipi").ok().unwrap_or(""),
-            "atanan_kullanici_username": current.get_str("atanan_kullanici_username").ok().unwrap_or(""),
-            "kapatildi": current.get_bool("kapatildi").ok().unwrap_or(false),
-            "belge_f": current.get_str("belge_f").ok().map(|s| !s.is_empty()).unwrap_or(false),
-            "created_at": current.get_str("created_at").ok().unwrap_or(""),
-        }));
-    }
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
-    Ok(Json(results))
+    Ok(Json(page.map(|current| montaj_list_item(&current))))
 }
 
 pub async fn update_montaj_kayit(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Path(id): Path<String>,
     Json(req): Json<UpdateMontajRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
@@ -712,14 +1716,21 @@ pub async fn update_montaj_kayit(
 
     update_doc.insert("updated_at", chrono::Utc::now().to_rfc3339());
 
-    let result = collection
-        .update_one(doc! { "_id": object_id }, doc! { "$set": update_doc })
+    let previous = collection
+        .find_one_and_update(trash::active(doc! { "_id": object_id }), doc! { "$set": update_doc.clone() })
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
+        .ok_or((StatusCode::NOT_FOUND, "Montaj kaydı bulunamadı".to_string()))?;
 
-    if result.matched_count == 0 {
-        return Err((StatusCode::NOT_FOUND, "Montaj kaydı bulunamadı".to_string()));
-    }
+    audit::record(
+        &state.db,
+        "montaj_kayitlari",
+        &object_id.to_hex(),
+        "update",
+        &auth_user,
+        audit::diff(&previous, &update_doc),
+    )
+    .await;
 
     Ok(Json(serde_json::json!({
         "success": true,
@@ -727,9 +1738,26 @@ pub async fn update_montaj_kayit(
     })))
 }
 
+/// `?full=true` on detail endpoints returns full-size file URLs instead of thumbnails.
+#[derive(Debug, Default, Deserialize)]
+pub struct FileLinkQuery {
+    pub full: Option<bool>,
+}
+
+impl FileLinkQuery {
+    fn link(&self) -> FileLink {
+        if self.full.unwrap_or(false) {
+            FileLink::Full
+        } else {
+            FileLink::Thumbnail
+        }
+    }
+}
+
 pub async fn get_montaj_kayit(
     State(state): State<AppState>,
     Path(id): Path<String>,
+    Query(files): Query<FileLinkQuery>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     let object_id = ObjectId::parse_str(&id)
         .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
@@ -737,11 +1765,22 @@ pub async fn get_montaj_kayit(
     let collection = state.db.collection::<Document>("montaj_kayitlari");
 
     let doc = collection
-        .find_one(doc! { "_id": object_id })
+        .find_one(trash::active(doc! { "_id": object_id }))
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
         .ok_or_else(|| (StatusCode::NOT_FOUND, "Montaj kaydı bulunamadı".to_string()))?;
 
+    let kurulum_resimleri: Vec<String> = doc
+        .get_array("kurulum_resimleri")
+        .map(|images| {
+            images
+                .iter()
+                .filter_map(|image| image.as_str())
+                .filter_map(|image| state.attachments.public_value(Some(image.to_string()), files.link()))
+                .collect()
+        })
+        .unwrap_or_default();
+
     let result = serde_json::json!({
         "id": id,
         "rnu_is_emri_no": doc.get_str("rnu_is_emri_no").ok().unwrap_or(""),
@@ -752,7 +1791,8 @@ pub async fn get_montaj_kayit(
         "servis_tipi": doc.get_str("servis_tipi").ok().unwrap_or(""),
         "atanan_kullanici_username": doc.get_str("atanan_kullanici_username").ok().unwrap_or(""),
         "kapatildi": doc.get_bool("kapatildi").ok().unwrap_or(false),
-        "belge_f": doc.get_str("belge_f").ok().unwrap_or(""),
+        "belge_f": state.attachments.public_value(doc.get_str("belge_f").ok().map(str::to_string), files.link()).unwrap_or_default(),
+        "kurulum_resimleri": kurulum_resimleri,
         "created_at": doc.get_str("created_at").ok().unwrap_or(""),
     });
 
@@ -761,6 +1801,8 @@ pub async fn get_montaj_kayit(
 
 pub async fn delete_montaj_kayit(
     State(state): State<AppState>,
+    auth_user: AuthUser,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     let object_id = ObjectId::parse_str(&id)
@@ -768,23 +1810,31 @@ pub async fn delete_montaj_kayit(
 
     let collection = state.db.collection::<Document>("montaj_kayitlari");
 
+    verify_delete_otp(&state, &headers, &auth_user, "montaj", &object_id.to_hex()).await?;
+
     let result = collection
-        .delete_one(doc! { "_id": object_id })
+        .update_one(
+            trash::active(doc! { "_id": object_id }),
+            trash::soft_delete_update(&auth_user.username),
+        )
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
 
-    if result.deleted_count == 0 {
+    if result.matched_count == 0 {
         return Err((StatusCode::NOT_FOUND, "Montaj kaydı bulunamadı".to_string()));
     }
 
+    audit::record(&state.db, "montaj_kayitlari", &object_id.to_hex(), "delete", &auth_user, vec![]).await;
+
     Ok(Json(serde_json::json!({
         "success": true,
-        "message": "Montaj kaydı silindi"
+        "message": "Montaj kaydı çöp kutusuna taşındı"
     })))
 }
 
 pub async fn close_montaj_kayit(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Path(id): Path<String>,
     Json(req): Json<CloseMontajRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
@@ -800,38 +1850,58 @@ pub async fn close_montaj_kayit(
         return Err((StatusCode::BAD_REQUEST, "En az bir resim yüklenmelidir".to_string()));
     }
 
-    let images: Vec<String> = req
-        .resimler
-        .into_iter()
-        .map(|img| img.trim().to_string())
-        .filter(|img| !img.is_empty())
-        .collect();
+    let collection = state.db.collection::<Document>("montaj_kayitlari");
+
+    // Montaj ekibi can only close records assigned to them
+    let mut filter = trash::active(doc! { "_id": object_id });
+    if auth_user.role == ROLE_MONTAJ_EKIBI {
+        filter.insert("atanan_kullanici_username", &auth_user.username);
+    }
+
+    let current = collection
+        .find_one(filter.clone())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
+        .ok_or((StatusCode::NOT_FOUND, "Montaj kaydı bulunamadı".to_string()))?;
+    let current_files = record_attachment_ids(&current);
+
+    let mut images: Vec<String> = Vec::new();
+    for image in req.resimler.iter().map(|img| img.trim()).filter(|img| !img.is_empty()) {
+        let stored = state
+            .attachments
+            .resolve_field_input(&state.db, image, PHOTO_KINDS, &current_files, &auth_user.username)
+            .await?;
+        images.push(stored);
+    }
 
     if images.is_empty() {
         return Err((StatusCode::BAD_REQUEST, "Geçerli resim bulunamadı".to_string()));
     }
 
-    let collection = state.db.collection::<Document>("montaj_kayitlari");
+    let set_doc = doc! {
+        "kapatildi": true,
+        "kapatildi_at": chrono::Utc::now().to_rfc3339(),
+        "kurulum_tipi": kurulum_tipi,
+        "kurulum_resimleri": images,
+        "updated_at": chrono::Utc::now().to_rfc3339(),
+    };
 
-    let result = collection
-        .update_one(
-            doc! { "_id": object_id },
-            doc! {
-                "$set": {
-                    "kapatildi": true,
-                    "kapatildi_at": chrono::Utc::now().to_rfc3339(),
-                    "kurulum_tipi": kurulum_tipi,
-                    "kurulum_resimleri": images,
-                    "updated_at": chrono::Utc::now().to_rfc3339(),
-                }
-            },
-        )
+    let previous = collection
+        .find_one_and_update(filter, doc! { "$set": set_doc.clone() })
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
+        .ok_or((StatusCode::NOT_FOUND, "Montaj kaydı bulunamadı".to_string()))?;
 
-    if result.matched_count == 0 {
-        return Err((StatusCode::NOT_FOUND, "Montaj kaydı bulunamadı".to_string()));
-    }
+    audit::record(
+        &state.db,
+        "montaj_kayitlari",
+        &object_id.to_hex(),
+        "close",
+        &auth_user,
+        audit::diff(&previous, &set_doc),
+    )
+    .await;
+    release_attachments(&state, &replaced_attachment_ids(&previous, &set_doc)).await;
 
     Ok(Json(serde_json::json!({
         "success": true,
@@ -855,21 +1925,44 @@ fn sanitize_zip_name(value: &str) -> String {
     }
 }
 
-fn decode_data_url_image(payload: &str) -> Result<Vec<u8>, String> {
-    let content = payload.trim();
-    if content.is_empty() {
-        return Err("Boş dosya içeriği".to_string());
+/// Stores an uploaded file field (data URL or existing attachment id) and
+/// returns the value to keep on the record. `current` are the attachment ids
+/// already on the record.
+async fn resolve_file_field(
+    state: &AppState,
+    value: Option<String>,
+    current: &[String],
+    actor: &str,
+) -> Result<Option<String>, (StatusCode, String)> {
+    match value {
+        Some(value) => state
+            .attachments
+            .resolve_field_input(&state.db, &value, DOCUMENT_KINDS, current, actor)
+            .await
+            .map(Some),
+        None => Ok(None),
     }
+}
 
-    let encoded = if let Some(idx) = content.find(",") {
-        &content[(idx + 1)..]
-    } else {
-        content
-    };
+/// Deletes attachments an update replaced, unless another record still uses
+/// them. Failures are only logged; the update itself has been saved.
+async fn release_attachments(state: &AppState, ids: &[String]) {
+    if ids.is_empty() {
+        return;
+    }
+    if let Err(e) = state.attachments.delete_unreferenced(&state.db, ids).await {
+        tracing::error!("Replaced attachments could not be deleted: {}", e);
+    }
+}
 
-    BASE64_STANDARD
-        .decode(encoded)
-        .map_err(|_| "Base64 görsel decode edilemedi".to_string())
+/// API shape of a musteri record, with file fields as signed download URLs.
+fn musteri_response(state: &AppState, musteri: MusteriKabul, link: FileLink) -> MusteriKabulResponse {
+    let mut response = musteri.to_response(&state.cipher);
+    response.belge_f = state.attachments.public_value(response.belge_f, link);
+    response.belge_g = state.attachments.public_value(response.belge_g, link);
+    response.belge_u = state.attachments.public_value(response.belge_u, link);
+    response.belge_a = state.attachments.public_value(response.belge_a, link);
+    response
 }
 
 pub async fn download_montaj_files_zip(
@@ -881,7 +1974,7 @@ pub async fn download_montaj_files_zip(
 
     let collection = state.db.collection::<Document>("montaj_kayitlari");
     let doc = collection
-        .find_one(doc! { "_id": object_id })
+        .find_one(trash::active(doc! { "_id": object_id }))
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
         .ok_or((StatusCode::NOT_FOUND, "Montaj kaydı bulunamadı".to_string()))?;
@@ -906,12 +1999,13 @@ pub async fn download_montaj_files_zip(
             None => continue,
         };
 
-        let bytes = match decode_data_url_image(image_text) {
+        let bytes = match state.attachments.load_field(&state.db, image_text).await {
             Ok(v) => v,
             Err(_) => continue,
         };
 
-        let entry_name = format!("{}_{}.jpg", sanitize_zip_name(ad_soyad), index + 1);
+        let extension = media::sniff(&bytes).map(|kind| kind.extension()).unwrap_or("bin");
+        let entry_name = format!("{}_{}.{}", sanitize_zip_name(ad_soyad), index + 1, extension);
         zip_writer
             .start_file(entry_name, options)
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("ZIP oluşturulamadı: {}", e)))?;
@@ -944,13 +2038,122 @@ pub async fn download_montaj_files_zip(
     ).into_response())
 }
 
+#[derive(Debug, Deserialize)]
+pub struct AttachmentUploadQuery {
+    pub filename: Option<String>,
+}
+
+/// Stores a raw request body (not base64) in the attachment store. The type
+/// is detected from the content; the Content-Type header is not trusted.
+pub async fn upload_attachment(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    headers: HeaderMap,
+    Query(query): Query<AttachmentUploadQuery>,
+    body: axum::body::Body,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    let too_large = format!("Dosya en fazla {} MB olabilir", MAX_ATTACHMENT_BYTES / 1024 / 1024);
+    if header_value(&headers, "content-length")
+        .and_then(|v| v.parse::<usize>().ok())
+        .is_some_and(|len| len > MAX_ATTACHMENT_BYTES)
+    {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, too_large));
+    }
+
+    let filename = query
+        .filename
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+
+    let attachment = state
+        .attachments
+        .put_stream(&state.db, body.into_data_stream(), DOCUMENT_KINDS, filename, &auth_user.username)
+        .await?;
+
+    let id = attachment.id.to_hex();
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({
+            "id": id,
+            "content_type": attachment.content_type,
+            "size": attachment.size,
+            "url": state.attachments.signed_url(&id, FileLink::Full),
+            "thumbnail_url": attachment
+                .thumbnail_sha256
+                .as_ref()
+                .map(|_| state.attachments.signed_url(&id, FileLink::Thumbnail)),
+        })),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttachmentDownloadQuery {
+    pub exp: i64,
+    pub sig: String,
+}
+
+/// Serves a stored file. Public, but only through the signed URLs the API
+/// hands out, so `<img src>` works without an Authorization header.
+pub async fn download_attachment(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<AttachmentDownloadQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    if !state.attachments.verify_url(&id, query.exp, &query.sig) {
+        return Err((StatusCode::FORBIDDEN, "Dosya bağlantısı geçersiz veya süresi dolmuş".to_string()));
+    }
+
+    let attachment = state
+        .attachments
+        .get(&state.db, &id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((StatusCode::NOT_FOUND, "Dosya bulunamadı".to_string()))?;
+
+    let file = state
+        .attachments
+        .open(&attachment)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e))?;
+
+    let content_type = HeaderValue::from_str(&attachment.content_type)
+        .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream"));
+    let content_disposition = if attachment.extension.is_empty() {
+        HeaderValue::from_static("inline")
+    } else {
+        HeaderValue::from_str(&format!("inline; filename=\"{}.{}\"", id, attachment.extension))
+            .unwrap_or_else(|_| HeaderValue::from_static("inline"))
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_LENGTH, HeaderValue::from(attachment.size)),
+            (header::CONTENT_DISPOSITION, content_disposition),
+            (header::CACHE_CONTROL, HeaderValue::from_static("private, max-age=3600")),
+            (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+        ],
+        axum::body::Body::from_stream(tokio_util::io::ReaderStream::new(file)),
+    )
+        .into_response())
+}
+
 pub async fn create_musteri_kabul(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     headers: HeaderMap,
     Json(req): Json<CreateMusteriKabulRequest>,
 ) -> Result<(StatusCode, Json<MusteriKabulResponse>), (StatusCode, String)> {
     let collection = state.db.collection::<MusteriKabul>("musteri_kabul");
-    let frontend_base_url = resolve_frontend_url(&headers);
+    let frontend_base_url = resolve_frontend_url(&state.config, &headers);
+
+    // New records always start at MÜŞTERİ KABUL; later statuses go through the transition checks
+    if req.status.is_some_and(|status_id| status_id != 1) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Yeni kayıt yalnızca MÜŞTERİ KABUL durumunda açılabilir".to_string(),
+        ));
+    }
     
     // Debug logging
     println!("📝 Create request received:");
@@ -967,54 +2170,99 @@ pub async fn create_musteri_kabul(
     let device_model_for_sms = req.marka_model.clone();
     let service_type_for_sms = req.servis_tipi.clone();
     let service_type_fallback_for_sms = req.aksesuarlar.clone();
+    let consent = ConsentChange {
+        transactional: req.sms_izni,
+        marketing: req.pazarlama_izni,
+    };
     
-    let mut musteri = MusteriKabul::new(req);
+    let mut musteri = MusteriKabul::new(req, &auth_user.username);
+    let takip_token = tracking::generate_token();
+    musteri.takip_token = Some(takip_token.clone());
+
+    for belge in [&mut musteri.belge_f, &mut musteri.belge_g, &mut musteri.belge_u, &mut musteri.belge_a] {
+        *belge = resolve_file_field(&state, belge.take(), &[], &auth_user.username).await?;
+    }
     
     println!("💾 Musteri created - Belge_f: {}", if musteri.belge_f.is_some() { "✓ VAR" } else { "✗ YOK" });
     
     // Telefon numarasını şifrele
-    let encrypted_phone = encrypt_value(&musteri.telefon)
+    let encrypted_phone = state.cipher.encrypt_value(&musteri.telefon)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Encryption error: {}", e)))?;
     
+    musteri.telefon_index = telefon_index(&state.cipher, &musteri.telefon);
+    if let Ok(record) = mongodb::bson::to_document(&musteri) {
+        musteri.search_terms = Some(search::search_terms(&record));
+    }
     musteri.telefon = encrypted_phone;
     
     match collection.insert_one(&musteri).await {
         Ok(result) => {
-            musteri.id = Some(result.inserted_id.as_object_id().unwrap().clone());
+            musteri.id = Some(result.inserted_id.as_object_id().unwrap());
+
+            if let Ok(created) = mongodb::bson::to_document(&musteri) {
+                let id_hex = result.inserted_id.as_object_id().unwrap().to_hex();
+                audit::record(&state.db, "musteri_kabul", &id_hex, "create", &auth_user, audit::snapshot(&created)).await;
+            }
+
+            let consent_saved = sms_consent::record_form_consent(
+                &state.db,
+                musteri.telefon_index.as_deref(),
+                consent,
+                sms_consent::SOURCE_INTAKE,
+                &auth_user.username,
+            )
+            .await;
             
             // SMS gönder - async olarak arka planda
             let collection_clone = state.db.collection::<MusteriKabul>("musteri_kabul");
-            let id_clone = result.inserted_id.as_object_id().unwrap().clone();
+            let id_clone = result.inserted_id.as_object_id().unwrap();
+            let sms_provider = state.sms.clone();
+            let templates = state.sms_templates.clone();
+            let db = state.db.clone();
+            let cipher = state.cipher.clone();
+            let fatura_links = state.fatura_links.clone();
+            let tracking_link = tracking::tracking_url(&frontend_base_url, &takip_token);
             
             tokio::spawn(async move {
+                if !consent_saved {
+                    println!("⚠️ SMS izni kaydedilemedi, kabul SMS'i gönderilmedi (Müşteri: {})", id_clone);
+                    return;
+                }
                 println!("📱 SMS Task Started for phone: {}", phone_for_sms);
-                let frontend_url = frontend_base_url.trim_end_matches('/').to_string();
-                let fatura_link = format!("{}/fatura/{}", frontend_url, id_clone.to_hex());
+                let fatura_link = fatura_links.url(&frontend_base_url, FaturaTarget::Musteri, &id_clone, 0);
 
                 let normalized_service_type = detect_montaj_service_type(
                     service_type_for_sms.as_deref(),
                     Some(service_type_fallback_for_sms.as_str()),
                 ).unwrap_or_default();
 
+                let customer = [
+                    ("ad_soyad", customer_name_for_sms.as_str()),
+                    ("model", device_model_for_sms.as_str()),
+                ];
+                let with_fatura_link = [customer[0], customer[1], ("link", fatura_link.as_str())];
+                let fatura_section = templates.render("intake_fatura", &with_fatura_link).await;
+
                 let mut full_sms_msg = if normalized_service_type == "TV_KURULUM"
                     || normalized_service_type == "TV_MONTAJ"
                     || normalized_service_type == "TVKURULUM"
                     || normalized_service_type == "TVMONTAJ"
                 {
-                    build_tv_kurulum_sms_message(&customer_name_for_sms, &fatura_link)
+                    templates.render("intake_tv_kurulum", &with_fatura_link).await
                 } else if normalized_service_type == "ROBOT_KURULUM"
                     || normalized_service_type == "ROBOTKURULUM"
                 {
-                    build_robot_kurulum_sms_message(&customer_name_for_sms, &fatura_link)
+                    templates.render("intake_robot_kurulum", &with_fatura_link).await
                 } else if normalized_service_type == "TV_ARIZA"
                     || normalized_service_type == "ROBOT_ARIZA"
                     || normalized_service_type == "TVARIZA"
                     || normalized_service_type == "ROBOTARIZA"
                 {
-                    build_montaj_ariza_sms_message(&customer_name_for_sms, &device_model_for_sms)
+                    templates.render("intake_ariza", &customer).await
                 } else {
-                    let sms_msg = build_sms_message(&customer_name_for_sms, &device_model_for_sms);
-                    format!("{}\n\nFatura Yükleme:\n{}", sms_msg, fatura_link)
+                    let with_tracking_link = [customer[0], customer[1], ("link", tracking_link.as_str())];
+                    let sms_msg = templates.render("intake", &with_tracking_link).await;
+                    format!("{}\n\n{}", sms_msg, fatura_section)
                 };
 
                 let is_kurulum_service = normalized_service_type == "TV_KURULUM"
@@ -1025,22 +2273,26 @@ pub async fn create_musteri_kabul(
                     || normalized_service_type == "ROBOTKURULUM";
 
                 if is_kurulum_service && !full_sms_msg.contains("/fatura/") {
-                    full_sms_msg = format!("{}\n\nFatura Yükleme:\n{}", full_sms_msg, fatura_link);
+                    full_sms_msg = format!("{}\n\n{}", full_sms_msg, fatura_section);
                 }
                 
-                println!("📱 SMS Message: {}", full_sms_msg);
+                // The text carries the fatura and tracking tokens, keep it out of the logs
+                println!("📱 Kabul SMS'i hazırlandı (Müşteri: {}, {} karakter)", id_clone, full_sms_msg.chars().count());
                 
-                match send_sms(&phone_for_sms, &full_sms_msg).await {
+                let outgoing = OutgoingSms::new(sms_log::TEMPLATE_INTAKE, &phone_for_sms, &full_sms_msg)
+                    .record("musteri_kabul", id_clone);
+                match sms_log::send(&db, &cipher, sms_provider.as_ref(), outgoing).await {
                     Ok(response) => {
-                        println!("✅ SMS sent successfully: {}", response.message);
-                        tracing::info!("SMS sent successfully: {}", response.message);
+                        println!("✅ SMS sent successfully via {}: {}", response.provider, response.message);
+                        tracing::info!("SMS sent successfully via {}: {}", response.provider, response.message);
                         
                         if let Err(e) = collection_clone.update_one(
                             doc! { "_id": id_clone },
                             doc! {
                                 "$set": {
                                     "sms_gonderildi": true,
-                                    "sms_mesaj": full_sms_msg
+                                    "sms_mesaj": full_sms_msg,
+                                    "sms_provider": response.provider,
                                 }
                             }
                         ).await {
@@ -1055,7 +2307,7 @@ pub async fn create_musteri_kabul(
                 }
             });
             
-            Ok((StatusCode::CREATED, Json(musteri.to_response())))
+            Ok((StatusCode::CREATED, Json(musteri_response(&state, musteri, FileLink::Full))))
         }
         Err(e) => {
             Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))
@@ -1065,36 +2317,123 @@ pub async fn create_musteri_kabul(
 pub async fn get_musteri_kabul(
     State(state): State<AppState>,
     Path(id): Path<String>,
+    Query(files): Query<FileLinkQuery>,
 ) -> Result<Json<MusteriKabulResponse>, (StatusCode, String)> {
     let collection = state.db.collection::<MusteriKabul>("musteri_kabul");
     
     let object_id = ObjectId::parse_str(&id)
         .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
     
-    match collection.find_one(doc! { "_id": object_id }).await {
-        Ok(Some(musteri)) => Ok(Json(musteri.to_response())),
+    match collection.find_one(trash::active(doc! { "_id": object_id })).await {
+        Ok(Some(musteri)) => Ok(Json(musteri_response(&state, musteri, files.link()))),
         Ok(None) => Err((StatusCode::NOT_FOUND, "Müşteri not found".to_string())),
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e))),
     }
 }
 
+/// Runs a paginated musteri_kabul query; documents that no longer match the
+/// model are skipped, as the old streaming lists did.
+async fn musteri_kabul_page(
+    state: &AppState,
+    query: &ListQuery,
+    base_filter: Document,
+) -> Result<Page<MusteriKabulResponse>, (StatusCode, String)> {
+    let collection = state.db.collection::<Document>("musteri_kabul");
+
+    let plan = query
+        .plan(trash::active(base_filter))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let page = plan
+        .fetch(&collection)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let items = page
+        .items
+        .into_iter()
+        .filter_map(|doc| mongodb::bson::from_document::<MusteriKabul>(doc).ok())
+        .map(|musteri| musteri_response(state, musteri, FileLink::Full))
+        .collect();
+
+    Ok(Page {
+        items,
+        total: page.total,
+        limit: page.limit,
+        next_cursor: page.next_cursor,
+    })
+}
+
 pub async fn list_musteri_kabul(
     State(state): State<AppState>,
-) -> Result<Json<Vec<MusteriKabulResponse>>, (StatusCode, String)> {
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Page<MusteriKabulResponse>>, (StatusCode, String)> {
+    musteri_kabul_page(&state, &query, doc! {}).await.map(Json)
+}
+
+const TEXT_SEARCH_LIMIT: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct MusteriSearchQuery {
+    /// Exact phone match through the blind index
+    pub telefon: Option<String>,
+    /// Words matched in ad_soyad, marka_model, musteri_sikayeti,
+    /// teknisyen_aciklamasi and tamir_fisi_no
+    pub q: Option<String>,
+}
+
+/// Searches non-trashed records by phone and/or text, newest first. Text
+/// words match as prefixes and must all be present; text searches return at
+/// most `TEXT_SEARCH_LIMIT` records, phone-only searches return all of them.
+pub async fn search_musteri_kabul(
+    State(state): State<AppState>,
+    Query(query): Query<MusteriSearchQuery>,
+) -> Result<Json<Vec<MusteriKabulListResponse>>, (StatusCode, String)> {
+    let telefon = query.telefon.as_deref().map(str::trim).filter(|v| !v.is_empty());
+    let text = query.q.as_deref().map(str::trim).filter(|v| !v.is_empty());
+
+    if telefon.is_none() && text.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "Arama için telefon veya q parametresi gerekli".to_string()));
+    }
+
+    let mut filter = doc! {};
+    if let Some(telefon) = telefon {
+        let index = telefon_index(&state.cipher, telefon).ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, "Telefon numarası en az 10 haneli olmalı".to_string())
+        })?;
+        filter.insert("telefon_index", index);
+    }
+
+    if let Some(text) = text {
+        let terms = search::query_terms(text);
+        if terms.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "Arama metni en az 2 karakterlik bir kelime içermeli".to_string()));
+        }
+        filter.insert("search_terms", doc! { "$all": terms });
+    }
+
     let collection = state.db.collection::<MusteriKabul>("musteri_kabul");
-    
-    match collection.find(doc! {}).sort(doc! { "_id": -1 }).await {
-        Ok(mut cursor) => {
-            let mut results = Vec::new();
-            while cursor.advance().await.unwrap_or(false) {
-                if let Ok(doc) = cursor.deserialize_current() {
-                    results.push(doc.to_response());
-                }
-            }
-            Ok(Json(results))
+
+    let find = collection
+        .find(trash::active(filter))
+        .sort(doc! { "created_at": -1, "_id": -1 });
+    let find = if text.is_some() { find.limit(TEXT_SEARCH_LIMIT) } else { find };
+
+    let mut cursor = find
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+
+    let mut results = Vec::new();
+    while cursor
+        .advance()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Cursor error: {}", e)))?
+    {
+        if let Ok(musteri) = cursor.deserialize_current() {
+            results.push(musteri.to_list_response(&state.cipher));
         }
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e))),
     }
+
+    Ok(Json(results))
 }
 
 pub async fn get_musteri_kabul_stats(
@@ -1108,12 +2447,15 @@ pub async fn get_musteri_kabul_stats(
     }
 
     let mut cursor = collection
-        .aggregate(vec![doc! {
-            "$group": {
-                "_id": "$status",
-                "count": { "$sum": 1 }
-            }
-        }])
+        .aggregate(vec![
+            doc! { "$match": trash::active(doc! {}) },
+            doc! {
+                "$group": {
+                    "_id": "$status",
+                    "count": { "$sum": 1 }
+                }
+            },
+        ])
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
 
@@ -1131,22 +2473,10 @@ pub async fn get_musteri_kabul_stats(
             Some(mongodb::bson::Bson::Int32(v)) => i64::from(*v),
             Some(mongodb::bson::Bson::Int64(v)) => *v,
             Some(mongodb::bson::Bson::Double(v)) => *v as i64,
-            _ => 0,
-        };
-
-        let mapped_id = status_string_to_id(status_value).or_else(|| {
-            let normalized = status_value.trim().to_uppercase();
-            for id in 1..=9 {
-                if let Some(aliases) = status_id_aliases(id) {
-                    if aliases.iter().any(|alias| alias.to_uppercase() == normalized) {
-                        return Some(id);
-                    }
-                }
-            }
-            None
-        });
+            _ => 0,
+        };
 
-        if let Some(id) = mapped_id {
+        if let Some(id) = status_id_from_any(status_value) {
             stats_map.insert(id.to_string(), serde_json::json!(count_value));
         }
     }
@@ -1157,40 +2487,57 @@ pub async fn get_musteri_kabul_stats(
 pub async fn list_musteri_kabul_by_status(
     State(state): State<AppState>,
     Path(status_id): Path<i32>,
-) -> Result<Json<Vec<MusteriKabulResponse>>, (StatusCode, String)> {
-    let collection = state.db.collection::<MusteriKabul>("musteri_kabul");
-
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Page<MusteriKabulResponse>>, (StatusCode, String)> {
     let aliases = status_id_aliases(status_id)
         .ok_or((StatusCode::BAD_REQUEST, format!("Invalid status ID: {}", status_id)))?;
     let aliases: Vec<String> = aliases.into_iter().map(|s| s.to_string()).collect();
 
-    match collection.find(doc! { "status": { "$in": aliases } }).sort(doc! { "_id": -1 }).await {
-        Ok(mut cursor) => {
-            let mut results = Vec::new();
-            while cursor.advance().await.unwrap_or(false) {
-                if let Ok(doc) = cursor.deserialize_current() {
-                    results.push(doc.to_response());
-                }
-            }
-            Ok(Json(results))
-        }
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e))),
-    }
+    musteri_kabul_page(&state, &query, doc! { "status": { "$in": aliases } }).await.map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AwaitingPickupQuery {
+    #[serde(default)]
+    pub overdue: bool,
+}
+
+/// Repaired (7) and returned (9) devices not yet collected; `?overdue=true`
+/// lists only those past their pickup deadline.
+pub async fn list_awaiting_pickup(
+    State(state): State<AppState>,
+    Query(query): Query<AwaitingPickupQuery>,
+) -> Result<Json<Vec<AwaitingPickup>>, (StatusCode, String)> {
+    pickup_reminders::awaiting_pickup(&state.db, &state.cipher, query.overdue)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
 }
 
 pub async fn delete_musteri_kabul(
     State(state): State<AppState>,
+    auth_user: AuthUser,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<StatusCode, (StatusCode, String)> {
     let collection = state.db.collection::<MusteriKabul>("musteri_kabul");
     
     let object_id = ObjectId::parse_str(&id)
         .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
+
+    verify_delete_otp(&state, &headers, &auth_user, "musteri", &object_id.to_hex()).await?;
     
-    match collection.delete_one(doc! { "_id": object_id }).await {
+    match collection
+        .update_one(
+            trash::active(doc! { "_id": object_id }),
+            trash::soft_delete_update(&auth_user.username),
+        )
+        .await
+    {
         Ok(result) => {
-            if result.deleted_count > 0 {
-                println!("🗑️ Müşteri silindi: {}", id);
+            if result.matched_count > 0 {
+                println!("🗑️ Müşteri çöp kutusuna taşındı: {} ({})", id, auth_user.username);
+                audit::record(&state.db, "musteri_kabul", &object_id.to_hex(), "delete", &auth_user, vec![]).await;
                 Ok(StatusCode::NO_CONTENT)
             } else {
                 Err((StatusCode::NOT_FOUND, "Müşteri bulunamadı".to_string()))
@@ -1200,8 +2547,64 @@ pub async fn delete_musteri_kabul(
     }
 }
 
+/// Rejects status changes that are not in the transition graph or whose
+/// required fields (in the request or already on the record) are empty.
+fn check_status_transition(
+    existing: &MusteriKabul,
+    req: &crate::models::UpdateMusteriKabulRequest,
+    to: i32,
+) -> Result<(), (StatusCode, String)> {
+    let to_label = status_id_to_string(to).unwrap_or_default();
+
+    // Legacy records with an unrecognised status may move anywhere once.
+    if let Some(from) = status_id_from_any(&existing.status) {
+        let allowed = allowed_next_statuses(from);
+        if !allowed.contains(&to) {
+            let allowed_labels: Vec<String> = allowed
+                .iter()
+                .filter_map(|id| status_id_to_string(*id))
+                .collect();
+            let allowed_text = if allowed_labels.is_empty() {
+                "yok (son durum)".to_string()
+            } else {
+                allowed_labels.join(", ")
+            };
+            return Err((
+                StatusCode::CONFLICT,
+                format!(
+                    "{} durumundan {} durumuna geçilemez. İzin verilen durumlar: {}",
+                    existing.status, to_label, allowed_text
+                ),
+            ));
+        }
+    }
+
+    let filled = |requested: &Option<String>, stored: &Option<String>| {
+        requested
+            .as_ref()
+            .or(stored.as_ref())
+            .is_some_and(|value| !value.trim().is_empty())
+    };
+
+    for requirement in transition_requirements(to) {
+        let satisfied = match requirement {
+            TransitionRequirement::TechnicianNote => {
+                filled(&req.teknisyen_aciklamasi, &existing.teknisyen_aciklamasi)
+            }
+            TransitionRequirement::PartsInfo => filled(&req.parca_bilgisi, &existing.parca_bilgisi),
+        };
+        if !satisfied {
+            return Err((StatusCode::BAD_REQUEST, requirement.message(&to_label)));
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn update_musteri_kabul(
     State(state): State<AppState>,
+    auth_user: AuthUser,
+    headers: HeaderMap,
     Path(id): Path<String>,
     Json(req): Json<crate::models::UpdateMusteriKabulRequest>,
 ) -> Result<StatusCode, (StatusCode, String)> {
@@ -1212,10 +2615,16 @@ pub async fn update_musteri_kabul(
     
     // Fetch customer data before update to send SMS if status changes
     let existing_customer = collection
-        .find_one(doc! { "_id": object_id.clone() })
+        .find_one(trash::active(doc! { "_id": object_id }))
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
         .ok_or_else(|| (StatusCode::NOT_FOUND, "Müşteri bulunamadı".to_string()))?;
+
+    if let Some(status_id) = req.status {
+        if status_id_to_string(status_id).is_some() && is_status_change(&existing_customer.status, status_id) {
+            check_status_transition(&existing_customer, &req, status_id)?;
+        }
+    }
     
     let mut update_doc = doc! {};
     
@@ -1223,9 +2632,10 @@ pub async fn update_musteri_kabul(
         update_doc.insert("ad_soyad", ad_soyad.to_uppercase());
     }
     if let Some(telefon) = req.telefon {
-        let encrypted_phone = encrypt_value(&telefon)
+        let encrypted_phone = state.cipher.encrypt_value(&telefon)
             .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Encryption error: {}", e)))?;
         update_doc.insert("telefon", encrypted_phone);
+        update_doc.insert("telefon_index", telefon_index(&state.cipher, &telefon));
     }
     if let Some(marka_model) = req.marka_model {
         update_doc.insert("marka_model", marka_model.to_uppercase());
@@ -1242,40 +2652,65 @@ pub async fn update_musteri_kabul(
     if let Some(teknisyen_aciklamasi) = req.teknisyen_aciklamasi {
         update_doc.insert("teknisyen_aciklamasi", teknisyen_aciklamasi.to_uppercase());
     }
+    if let Some(parca_bilgisi) = req.parca_bilgisi {
+        update_doc.insert("parca_bilgisi", parca_bilgisi.to_uppercase());
+    }
     if let Some(tamir_fisi_no) = req.tamir_fisi_no {
         update_doc.insert("tamir_fisi_no", tamir_fisi_no);
     }
-    if let Some(belge_f) = req.belge_f {
+    let current_files = record_attachment_ids(&mongodb::bson::to_document(&existing_customer).unwrap_or_default());
+    if let Some(belge_f) = resolve_file_field(&state, req.belge_f, &current_files, &auth_user.username).await? {
         update_doc.insert("belge_f", belge_f);
     }
-    if let Some(belge_g) = req.belge_g {
+    if let Some(belge_g) = resolve_file_field(&state, req.belge_g, &current_files, &auth_user.username).await? {
         update_doc.insert("belge_g", belge_g);
     }
-    if let Some(belge_u) = req.belge_u {
+    if let Some(belge_u) = resolve_file_field(&state, req.belge_u, &current_files, &auth_user.username).await? {
         update_doc.insert("belge_u", belge_u);
     }
-    if let Some(belge_a) = req.belge_a {
+    if let Some(belge_a) = resolve_file_field(&state, req.belge_a, &current_files, &auth_user.username).await? {
         update_doc.insert("belge_a", belge_a);
     }
     
     let mut queued_status_sms: Option<(String, String, i32)> = None;
+    let mut status_transition: Option<StatusTransition> = None;
+    let mut delivered = false;
 
     // Handle status change and queue SMS
     if let Some(status_id) = req.status {
         if let Some(status_str) = status_id_to_string(status_id) {
-            let status_changed = existing_customer.status != status_str;
-            update_doc.insert("status", status_str);
+            let status_changed = is_status_change(&existing_customer.status, status_id);
+            update_doc.insert("status", status_str.clone());
             
             if status_changed {
+                delivered = status_id == 8;
+                status_transition = Some(StatusTransition {
+                    from: Some(existing_customer.status.clone()),
+                    to: status_str,
+                    at: mongodb::bson::DateTime::now(),
+                    actor: auth_user.username.clone(),
+                });
+
                 // Check if SMS was already sent for this status before
                 let already_sent = existing_customer.sms_sent_statuses
                     .as_ref()
-                    .map_or(false, |statuses| statuses.contains(&status_id));
+                    .is_some_and(|statuses| statuses.contains(&status_id));
 
                 if !already_sent {
-                    // Queue SMS with +1 hour delay if status actually changed and not previously sent
-                    if let Ok(phone) = decrypt_value(&existing_customer.telefon) {
-                        if let Some(sms_message) = build_status_sms_message(status_id, &existing_customer.ad_soyad, &existing_customer.marka_model) {
+                    // Queue SMS if status actually changed and not previously sent
+                    let decrypted_phone = state.cipher.decrypt_value(&existing_customer.telefon).ok();
+                    if let Some(phone) = decrypted_phone {
+                        if let Some(template) = sms_templates::status_key(status_id) {
+                            let sms_message = state
+                                .sms_templates
+                                .render(
+                                    template,
+                                    &[
+                                        ("ad_soyad", &existing_customer.ad_soyad),
+                                        ("model", &existing_customer.marka_model),
+                                    ],
+                                )
+                                .await;
                             queued_status_sms = Some((phone, sms_message, status_id));
                         }
                     }
@@ -1291,36 +2726,117 @@ pub async fn update_musteri_kabul(
     let now = chrono::Utc::now();
     update_doc.insert("updated_at", now.to_rfc3339());
 
+    let previous_doc = mongodb::bson::to_document(&existing_customer).unwrap_or_default();
+
+    if search::SEARCH_FIELDS.iter().any(|field| update_doc.contains_key(field)) {
+        let mut merged = previous_doc.clone();
+        merged.extend(update_doc.clone());
+        update_doc.insert("search_terms", search::search_terms(&merged));
+    }
+
+    let changes = audit::diff(&previous_doc, &update_doc);
+    let replaced_files = replaced_attachment_ids(&previous_doc, &update_doc);
+    let audit_action = if changes.iter().any(|change| change.field == "status") {
+        "status_change"
+    } else {
+        "update"
+    };
+
     // Build update operation: $set + optionally $addToSet for sms_sent_statuses
     let mut update_op = doc! { "$set": update_doc };
     if let Some((_, _, status_id)) = &queued_status_sms {
         update_op.insert("$addToSet", doc! { "sms_sent_statuses": *status_id });
     }
+    if let Some(transition) = &status_transition {
+        let entry = mongodb::bson::to_bson(transition)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Serialize error: {}", e)))?;
+        update_op.insert("$push", doc! { "status_history": entry });
+    }
     
+    // Filtered on the status we validated against, so a concurrent status change can't be skipped over
     match collection.update_one(
-        doc! { "_id": object_id.clone() },
+        trash::active(doc! { "_id": object_id, "status": &existing_customer.status }),
         update_op
     ).await {
         Ok(result) => {
             if result.matched_count > 0 {
                 if let Some((phone, sms_message, status_id)) = queued_status_sms {
-                    enqueue_status_sms(&state.db, object_id.clone(), phone, sms_message, status_id)
-                        .await
-                        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("SMS queue error: {}", e)))?;
+                    enqueue_status_sms(
+                        &state.db,
+                        &state.sms_window,
+                        object_id,
+                        phone,
+                        sms_message,
+                        status_id,
+                        req.sms_acil,
+                    )
+                    .await
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("SMS queue error: {}", e)))?;
+                }
+
+                if delivered && state.config.sms.survey {
+                    let frontend_url = resolve_frontend_url(&state.config, &headers);
+                    queue_delivery_survey(&state, &existing_customer, object_id, &frontend_url).await;
                 }
 
+                audit::record(&state.db, "musteri_kabul", &object_id.to_hex(), audit_action, &auth_user, changes).await;
+                release_attachments(&state, &replaced_files).await;
+
                 println!("✏️ Müşteri güncellendi: {}", id);
                 Ok(StatusCode::NO_CONTENT)
             } else {
-                Err((StatusCode::NOT_FOUND, "Müşteri bulunamadı".to_string()))
+                Err((
+                    StatusCode::CONFLICT,
+                    "Kayıt bu sırada başka bir kullanıcı tarafından güncellendi. Sayfayı yenileyip tekrar deneyin".to_string(),
+                ))
             }
         },
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e))),
     }
 }
 
+/// Creates the satisfaction survey for a delivered record and queues its
+/// SMS. Failures are logged; the status update has already been saved.
+async fn queue_delivery_survey(state: &AppState, musteri: &MusteriKabul, object_id: ObjectId, frontend_url: &str) {
+    let survey = match survey::create(&state.db, musteri, object_id).await {
+        Ok(Some(survey)) => survey,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::error!("{}", e);
+            return;
+        }
+    };
+    let Ok(phone) = state.cipher.decrypt_value(&musteri.telefon) else {
+        tracing::error!("Survey SMS skipped, phone could not be decrypted: {}", object_id);
+        return;
+    };
+
+    let link = survey::survey_url(frontend_url, &survey.token);
+    let message = state
+        .sms_templates
+        .render(
+            "survey",
+            &[("ad_soyad", &musteri.ad_soyad), ("model", &musteri.marka_model), ("link", &link)],
+        )
+        .await;
+
+    if let Err(e) = sms_queue::enqueue_survey_sms(
+        &state.db,
+        &state.sms_window,
+        object_id,
+        phone,
+        message,
+        state.config.sms.survey_delay_hours,
+    )
+    .await
+    {
+        tracing::error!("Survey SMS queue error: {}", e);
+    }
+}
+
 pub async fn resend_musteri_sms(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
@@ -1330,12 +2846,12 @@ pub async fn resend_musteri_sms(
         .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
 
     let musteri = collection
-        .find_one(doc! { "_id": object_id.clone() })
+        .find_one(trash::active(doc! { "_id": object_id }))
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?
         .ok_or_else(|| (StatusCode::NOT_FOUND, "Müşteri bulunamadı".to_string()))?;
 
-    let phone_candidate = match decrypt_value(&musteri.telefon) {
+    let phone_candidate = match state.cipher.decrypt_value(&musteri.telefon) {
         Ok(value) => value,
         Err(_) => musteri.telefon.clone(),
     };
@@ -1348,15 +2864,59 @@ pub async fn resend_musteri_sms(
         ));
     }
 
-    let sms_msg = build_sms_message(&musteri.ad_soyad, &musteri.marka_model);
-    let frontend_url = resolve_frontend_url(&headers).trim_end_matches('/').to_string();
-    let fatura_link = format!("{}/fatura/{}", frontend_url, object_id.to_hex());
-    let full_sms_msg = format!("{}\n\nFatura Yükleme:\n{}", sms_msg, fatura_link);
+    // Records from before tracking existed get their token on first resend
+    let takip_token = match musteri.takip_token.clone() {
+        Some(token) => token,
+        None => {
+            let token = tracking::generate_token();
+            collection
+                .update_one(
+                    doc! { "_id": object_id, tracking::TOKEN_FIELD: Bson::Null },
+                    doc! { "$set": { tracking::TOKEN_FIELD: &token } },
+                )
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+            token
+        }
+    };
 
-    let stored_sms_message = match send_sms(&phone_candidate, &full_sms_msg).await {
-        Ok(_) => full_sms_msg,
+    let frontend_url = resolve_frontend_url(&state.config, &headers);
+    let tracking_link = tracking::tracking_url(&frontend_url, &takip_token);
+    let fatura_link = state.fatura_links.url(
+        &frontend_url,
+        FaturaTarget::Musteri,
+        &object_id,
+        musteri.fatura_link_version,
+    );
+    let customer = [("ad_soyad", musteri.ad_soyad.as_str()), ("model", musteri.marka_model.as_str())];
+    let sms_msg = state
+        .sms_templates
+        .render("intake", &[customer[0], customer[1], ("link", &tracking_link)])
+        .await;
+    let fatura_section = state
+        .sms_templates
+        .render("intake_fatura", &[customer[0], customer[1], ("link", &fatura_link)])
+        .await;
+    let full_sms_msg = format!("{}\n\n{}", sms_msg, fatura_section);
+
+    let outgoing = OutgoingSms::new(sms_log::TEMPLATE_RESEND, &phone_candidate, &full_sms_msg)
+        .record("musteri_kabul", object_id);
+    let (stored_sms_message, response) = match sms_log::send(&state.db, &state.cipher, state.sms.as_ref(), outgoing).await {
+        Ok(response) => (full_sms_msg, response),
+        Err(SendError::Suppressed) => {
+            return Err((StatusCode::CONFLICT, "Müşteri SMS almayı reddetmiş".to_string()));
+        }
+        Err(SendError::Uncertain(e)) => {
+            // The long message may have gone out; sending the short one too could duplicate it
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                format!("SMS gönderim sonucu belirsiz, tekrar göndermeden önce SMS kayıtlarını kontrol edin: {}", e),
+            ));
+        }
         Err(primary_error) => {
-            send_sms(&phone_candidate, &sms_msg)
+            let outgoing = OutgoingSms::new(sms_log::TEMPLATE_RESEND, &phone_candidate, &sms_msg)
+                .record("musteri_kabul", object_id);
+            let response = sms_log::send(&state.db, &state.cipher, state.sms.as_ref(), outgoing)
                 .await
                 .map_err(|fallback_error| {
                     (
@@ -1367,30 +2927,202 @@ pub async fn resend_musteri_sms(
                         ),
                     )
                 })?;
-            sms_msg
+            (sms_msg, response)
         }
     };
 
+    let set_doc = doc! {
+        "sms_gonderildi": true,
+        "sms_mesaj": &stored_sms_message,
+        "sms_provider": response.provider,
+        "updated_at": chrono::Utc::now().to_rfc3339(),
+    };
+
     collection
+        .update_one(doc! { "_id": object_id }, doc! { "$set": set_doc.clone() })
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+
+    let previous_doc = mongodb::bson::to_document(&musteri).unwrap_or_default();
+    audit::record(
+        &state.db,
+        "musteri_kabul",
+        &object_id.to_hex(),
+        "sms_resend",
+        &auth_user,
+        audit::diff(&previous_doc, &set_doc),
+    )
+    .await;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "SMS tekrar gönderildi"
+    })))
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashItem {
+    pub id: String,
+    pub kind: &'static str,
+    pub ad_soyad: String,
+    pub model: String,
+    pub deleted_by: String,
+    pub deleted_at_ms: i64,
+    pub purge_at_ms: i64,
+}
+
+pub async fn list_trash(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TrashItem>>, (StatusCode, String)> {
+    let retention_ms = state.config.trash_retention_days * 24 * 60 * 60 * 1000;
+    let mut results = Vec::new();
+
+    for (collection_name, kind, model_field) in [
+        ("musteri_kabul", "musteri", "marka_model"),
+        ("montaj_kayitlari", "montaj", "model"),
+    ] {
+        let mut cursor = state
+            .db
+            .collection::<Document>(collection_name)
+            .find(doc! { "deleted": true })
+            .projection(doc! { "ad_soyad": 1, model_field: 1, "deleted_by": 1, "deleted_at": 1 })
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
+
+        while cursor
+            .advance()
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Cursor error: {}", e)))?
+        {
+            let current: Document = cursor
+                .deserialize_current()
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Deserialize error: {}", e)))?;
+
+            let deleted_at_ms = current
+                .get_datetime("deleted_at")
+                .map(|d| d.timestamp_millis())
+                .unwrap_or(0);
+
+            results.push(TrashItem {
+                id: current.get_object_id("_id").map(|v| v.to_hex()).unwrap_or_default(),
+                kind,
+                ad_soyad: current.get_str("ad_soyad").unwrap_or("").to_string(),
+                model: current.get_str(model_field).unwrap_or("").to_string(),
+                deleted_by: current.get_str("deleted_by").unwrap_or("").to_string(),
+                deleted_at_ms,
+                purge_at_ms: deleted_at_ms + retention_ms,
+            });
+        }
+    }
+
+    // Most recently deleted first
+    results.sort_by_key(|item| std::cmp::Reverse(item.deleted_at_ms));
+
+    Ok(Json(results))
+}
+
+async fn restore_from_trash(
+    state: &AppState,
+    auth_user: &AuthUser,
+    collection_name: &str,
+    id: &str,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let object_id = ObjectId::parse_str(id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
+
+    let result = state
+        .db
+        .collection::<Document>(collection_name)
         .update_one(
-            doc! { "_id": object_id },
-            doc! {
-                "$set": {
-                    "sms_gonderildi": true,
-                    "sms_mesaj": &stored_sms_message,
-                    "updated_at": chrono::Utc::now().to_rfc3339(),
-                }
-            },
+            doc! { "_id": object_id, "deleted": true },
+            trash::restore_update(&auth_user.username),
         )
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
 
+    if result.matched_count == 0 {
+        return Err((StatusCode::NOT_FOUND, "Çöp kutusunda kayıt bulunamadı".to_string()));
+    }
+
+    println!("♻️ Kayıt geri yüklendi: {} {} ({})", collection_name, id, auth_user.username);
+    audit::record(&state.db, collection_name, &object_id.to_hex(), "restore", auth_user, vec![]).await;
+
     Ok(Json(serde_json::json!({
         "success": true,
-        "message": "SMS tekrar gönderildi"
+        "message": "Kayıt geri yüklendi"
     })))
 }
 
+pub async fn restore_musteri_kabul(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    restore_from_trash(&state, &auth_user, "musteri_kabul", &id).await
+}
+
+pub async fn restore_montaj_kayit(
+    State(state): State<AppState>,
+    auth_user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    restore_from_trash(&state, &auth_user, "montaj_kayitlari", &id).await
+}
+
+async fn record_history(
+    state: &AppState,
+    collection_name: &str,
+    id: &str,
+) -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
+    let object_id = ObjectId::parse_str(id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid ID format".to_string()))?;
+
+    let entries = audit::history(&state.db, collection_name, &object_id.to_hex())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let results = entries
+        .into_iter()
+        .map(|entry| {
+            let changes: Vec<serde_json::Value> = entry
+                .changes
+                .into_iter()
+                .map(|change| {
+                    serde_json::json!({
+                        "field": change.field,
+                        "before": Bson::into_relaxed_extjson(change.before),
+                        "after": Bson::into_relaxed_extjson(change.after),
+                    })
+                })
+                .collect();
+
+            serde_json::json!({
+                "action": entry.action,
+                "actor": entry.actor,
+                "actor_role": entry.actor_role,
+                "created_at": entry.created_at.try_to_rfc3339_string().unwrap_or_default(),
+                "changes": changes,
+            })
+        })
+        .collect();
+
+    Ok(Json(results))
+}
+
+pub async fn get_musteri_kabul_history(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
+    record_history(&state, "musteri_kabul", &id).await
+}
+
+pub async fn get_montaj_kayit_history(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<serde_json::Value>>, (StatusCode, String)> {
+    record_history(&state, "montaj_kayitlari", &id).await
+}
+
 pub async fn login(
     State(state): State<AppState>,
     Json(req): Json<LoginRequest>,
@@ -1407,7 +3139,22 @@ pub async fn login(
 
     let matched_user_exists = matched_user.is_some();
     let matched_user_with_password = matched_user
-        .filter(|user| user.password == password);
+        .filter(|user| verify_password(&password, &user.password));
+
+    // Legacy plaintext password: replace it with a hash now that we know it
+    if let Some(user) = matched_user_with_password.as_ref().filter(|user| !is_password_hash(&user.password)) {
+        match hash_password(&password) {
+            Ok(hashed) => {
+                if let Err(e) = users
+                    .update_one(doc! { "username": &user.username }, doc! { "$set": { "password": hashed } })
+                    .await
+                {
+                    tracing::error!("Password rehash failed for {}: {}", user.username, e);
+                }
+            }
+            Err(e) => tracing::error!("Password rehash failed for {}: {}", user.username, e),
+        }
+    }
 
     let user_level = matched_user_with_password
         .as_ref()
@@ -1427,7 +3174,7 @@ pub async fn login(
         false
     };
 
-    let fallback_admin = username == "admin" && verify_admin_password(&password) && !has_admin_in_db;
+    let fallback_admin = username == "admin" && verify_admin_password(&password, &state.config.admin_password) && !has_admin_in_db;
 
     let is_valid_user = matched_user_with_password.is_some() || fallback_admin;
 
@@ -1436,7 +3183,7 @@ pub async fn login(
     }
     
     // Generate JWT token
-    match generate_token(&username, user_role) {
+    match generate_token(&state.config.jwt_secret, &username, user_role) {
         Ok(token) => {
             println!("🔐 Login successful for user: {}", username);
             Ok(Json(LoginResponse {
@@ -1486,11 +3233,14 @@ pub async fn create_user(
         return Err((StatusCode::CONFLICT, "Bu kullanıcı adı zaten kullanılıyor".to_string()));
     }
 
+    let password_hash = hash_password(&password)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
     let new_user = User {
         id: None,
         ad_soyad,
         username: username.clone(),
-        password,
+        password: password_hash,
         theme_color: None,
         level: Some(level.clone()),
         created_at: Some(DateTime::now()),
@@ -1522,11 +3272,13 @@ pub async fn list_users(
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
 
     if admin_check.is_none() {
+        let admin_password = hash_password(&state.config.admin_password)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
         let admin_user = User {
             id: None,
             ad_soyad: "ADMIN".to_string(),
             username: "admin".to_string(),
-            password: "123456".to_string(),
+            password: admin_password,
             theme_color: None,
             level: Some("level1".to_string()),
             created_at: Some(DateTime::now()),
@@ -1585,6 +3337,7 @@ pub async fn list_users(
 
 pub async fn update_user(
     State(state): State<AppState>,
+    auth_user: AuthUser,
     Path(id): Path<String>,
     Json(req): Json<UpdateUserRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
@@ -1601,6 +3354,16 @@ pub async fn update_user(
 
     let existing_username = existing.username.clone();
 
+    // Non-admin users may only change their own theme color and password
+    if !auth_user.is_admin() {
+        if existing_username != auth_user.username {
+            return Err((StatusCode::FORBIDDEN, "Sadece kendi hesabınızı düzenleyebilirsiniz".to_string()));
+        }
+        if req.ad_soyad.is_some() || req.username.is_some() || req.level.is_some() {
+            return Err((StatusCode::FORBIDDEN, "Bu alanları sadece admin değiştirebilir".to_string()));
+        }
+    }
+
     let mut update_doc = doc! {};
 
     if let Some(ad_soyad) = &req.ad_soyad {
@@ -1634,7 +3397,9 @@ pub async fn update_user(
     if let Some(password) = &req.password {
         let value = password.trim().to_string();
         if !value.is_empty() {
-            update_doc.insert("password", value);
+            let hashed = hash_password(&value)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            update_doc.insert("password", hashed);
         }
     }
 
@@ -1684,6 +3449,8 @@ pub async fn update_user(
 
 pub async fn delete_user(
     State(state): State<AppState>,
+    auth_user: AuthUser,
+    headers: HeaderMap,
     Path(id): Path<String>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     let object_id = ObjectId::parse_str(&id)
@@ -1701,6 +3468,12 @@ pub async fn delete_user(
         return Err((StatusCode::FORBIDDEN, "Kök kullanıcı silinemez".to_string()));
     }
 
+    if existing.username == auth_user.username {
+        return Err((StatusCode::FORBIDDEN, "Kendi hesabınızı silemezsiniz".to_string()));
+    }
+
+    verify_delete_otp(&state, &headers, &auth_user, "user", &object_id.to_hex()).await?;
+
     users.delete_one(doc! { "_id": object_id })
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)))?;
@@ -1784,12 +3557,16 @@ pub async fn migrate_teknisyen_notes(
 
         if let Some(note_value) = note_text {
             if teknisyen_text.is_empty() {
+                let mut next = current.clone();
+                next.insert("teknisyen_aciklamasi", note_value.to_uppercase());
+
                 collection
                     .update_one(
                         doc! { "_id": id },
                         doc! {
                             "$set": {
                                 "teknisyen_aciklamasi": note_value.to_uppercase(),
+                                "search_terms": search::search_terms(&next),
                                 "updated_at": chrono::Utc::now().to_rfc3339()
                             },
                             "$unset": {
@@ -1813,6 +3590,109 @@ pub async fn migrate_teknisyen_notes(
     })))
 }
 
+/// Replaces every legacy plaintext `users.password` with an argon2 hash.
+/// Returns (scanned, migrated). Safe to run repeatedly.
+pub async fn hash_plaintext_passwords(db: &mongodb::Database) -> Result<(i64, i64), String> {
+    let collection = db.collection::<Document>("users");
+
+    let mut cursor = collection
+        .find(doc! {})
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let mut scanned: i64 = 0;
+    let mut migrated: i64 = 0;
+
+    while cursor
+        .advance()
+        .await
+        .map_err(|e| format!("Cursor error: {}", e))?
+    {
+        let current: Document = cursor
+            .deserialize_current()
+            .map_err(|e| format!("Deserialize error: {}", e))?;
+
+        scanned += 1;
+
+        let id = match current.get_object_id("_id") {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        let stored = current.get_str("password").unwrap_or("");
+        if stored.is_empty() || is_password_hash(stored) {
+            continue;
+        }
+
+        let hashed = hash_password(stored)?;
+
+        // Only replace the value we read, in case the user logged in meanwhile
+        let result = collection
+            .update_one(
+                doc! { "_id": id, "password": stored },
+                doc! {
+                    "$set": {
+                        "password": hashed,
+                        "updated_at": DateTime::now()
+                    }
+                },
+            )
+            .await
+            .map_err(|e| format!("Update error: {}", e))?;
+
+        if result.modified_count == 1 {
+            migrated += 1;
+        }
+    }
+
+    Ok((scanned, migrated))
+}
+
+pub async fn migrate_hash_passwords(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (scanned, migrated) = hash_plaintext_passwords(&state.db)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "Kullanıcı şifreleri hash'lendi",
+        "scanned": scanned,
+        "migrated": migrated
+    })))
+}
+
+pub async fn migrate_telefon_index(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (scanned, indexed) = backfill_telefon_index(&state.db, &state.cipher)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "Telefon arama indeksi güncellendi",
+        "scanned": scanned,
+        "indexed": indexed
+    })))
+}
+
+pub async fn migrate_attachments(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let (updated, failed) = migrate_embedded_files(&state.db, &state.attachments)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "Gömülü belgeler dosya deposuna taşındı",
+        "updated": updated,
+        "failed": failed
+    })))
+}
+
 // ─── Argox OS-214 Plus PPLA Etiket Yazdırma ─────────────────────────────────
 
 #[derive(Debug, Deserialize)]
@@ -1851,6 +3731,7 @@ fn truncate_for_label(text: &str, max_chars: usize) -> String {
 }
 
 pub async fn print_customer_label(
+    State(state): State<AppState>,
     Json(req): Json<PrintLabelRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     let ad_soyad = truncate_for_label(&sanitize_for_printer(&req.ad_soyad), 24);
@@ -1872,7 +3753,7 @@ pub async fn print_customer_label(
         ad_soyad, telefon, ariza, tarih
     );
 
-    let printer_path = "/dev/usb/lp0";
+    let printer_path = state.config.printer_path.as_str();
 
     // Yazıcıya gönder
     let mut file = std::fs::OpenOptions::new()
@@ -1902,3 +3783,43 @@ pub async fn print_customer_label(
         "message": "Etiket yazdırıldı"
     })))
 }
+
+/// Serves the JPEG thumbnail of a stored file, with the same signed query as
+/// the file itself. 404 when the file has no preview (PDF, HEIC).
+pub async fn download_attachment_thumbnail(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<AttachmentDownloadQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    if !state.attachments.verify_url(&id, query.exp, &query.sig) {
+        return Err((StatusCode::FORBIDDEN, "Dosya bağlantısı geçersiz veya süresi dolmuş".to_string()));
+    }
+
+    let attachment = state
+        .attachments
+        .get(&state.db, &id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((StatusCode::NOT_FOUND, "Dosya bulunamadı".to_string()))?;
+
+    let path = state
+        .attachments
+        .thumbnail_path(&state.db, &attachment)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or((StatusCode::NOT_FOUND, "Bu dosya için önizleme yok".to_string()))?;
+
+    let bytes = tokio::fs::read(&path)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("Önizleme okunamadı: {}", e)))?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("image/jpeg")),
+            (header::CACHE_CONTROL, HeaderValue::from_static("private, max-age=3600")),
+            (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+        ],
+        bytes,
+    )
+        .into_response())
+}
@@ -1,49 +1,211 @@
+mod config;
 mod models;
 mod handlers;
 mod crypto;
 mod sms;
 mod auth;
+mod audit;
+mod sms_consent;
+mod sms_encoding;
+mod sms_log;
+mod sms_provider;
 mod sms_queue;
+mod sms_schedule;
+mod sms_templates;
+mod trash;
+mod pagination;
+mod phone_index;
+mod search;
+mod attachments;
+mod media;
+mod fatura_link;
+mod rate_limit;
+mod tracking;
+mod pickup_reminders;
+mod survey;
 
 use axum::{
     extract::DefaultBodyLimit,
+    middleware,
     routing::{get, post, put},
     Router,
 };
 use tower_http::cors::CorsLayer;
-use std::env;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 use dotenvy::dotenv;
 use mongodb::Client as MongoClient;
 
-use handlers::{AppState, create_montaj_kayit, create_musteri_kabul, get_musteri_kabul, list_montaj_kayitlari, list_musteri_kabul, get_musteri_kabul_stats, list_musteri_kabul_by_status, delete_musteri_kabul, update_musteri_kabul, resend_musteri_sms, login, get_bing_daily_image, create_user, list_users, update_user, delete_user, migrate_teknisyen_notes, get_system_sync, create_delete_otp, upload_fatura_public, update_montaj_kayit, delete_montaj_kayit, get_montaj_kayit, close_montaj_kayit, download_montaj_files_zip, print_customer_label};
-use sms_queue::start_sms_queue_worker;
+use handlers::{AppState, hash_plaintext_passwords, create_montaj_kayit, create_musteri_kabul, get_musteri_kabul, list_montaj_kayitlari, list_musteri_kabul, get_musteri_kabul_stats, list_musteri_kabul_by_status, list_awaiting_pickup, delete_musteri_kabul, update_musteri_kabul, resend_musteri_sms, login, get_bing_daily_image, create_user, list_users, update_user, delete_user, migrate_teknisyen_notes, migrate_hash_passwords, migrate_telefon_index, migrate_attachments, upload_attachment, download_attachment, download_attachment_thumbnail, search_musteri_kabul, get_system_sync, get_sms_outbox, get_sms_provider_health, list_sms_messages, list_sms_queue, get_sms_schedule, list_sms_templates, update_sms_template, reset_sms_template, preview_sms_template, retry_sms_queue_item, cancel_sms_queue_item, receive_sms_delivery_report, receive_sms_inbound, get_sms_consent, update_sms_consent, create_delete_otp, upload_fatura_public, get_fatura_upload_info, get_tracking, get_survey, answer_survey, get_survey_report, reissue_montaj_fatura_link, revoke_montaj_fatura_link, reissue_musteri_fatura_link, revoke_musteri_fatura_link, update_montaj_kayit, delete_montaj_kayit, get_montaj_kayit, close_montaj_kayit, download_montaj_files_zip, print_customer_label, list_trash, restore_musteri_kabul, restore_montaj_kayit, get_musteri_kabul_history, get_montaj_kayit_history};
+use config::Config;
+use crypto::FieldCipher;
+use attachments::AttachmentStore;
+use fatura_link::FaturaLinks;
+use rate_limit::RateLimiter;
+use sms_queue::{start_sms_queue_worker, QueueWorker};
+use sms_schedule::SendWindow;
+use pickup_reminders::{start_pickup_reminder_worker, ReminderWorker};
+use trash::start_trash_purge_worker;
+
+/// Lookups per client IP and minute on the public tracking endpoint.
+const TRACKING_REQUESTS_PER_MINUTE: u32 = 20;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
     tracing_subscriber::fmt::init();
 
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("❌ Yapılandırma hatası: {}", e);
+        std::process::exit(1);
+    });
+    let cipher = FieldCipher::new(&config.encryption_key).expect("ENCRYPTION_KEY validated in Config::load");
+
     // MongoDB connection
-    let mongo_url = env::var("MONGODB_URL").unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
-    let mongo_db_name = env::var("MONGODB_DB").unwrap_or_else(|_| "sis_teknik".to_string());
-    let mongo_client = MongoClient::with_uri_str(&mongo_url)
+    let mongo_client = MongoClient::with_uri_str(&config.mongodb_url)
         .await
         .expect("Failed to connect to MongoDB");
-    let db = mongo_client.database(&mongo_db_name);
+    let db = mongo_client.database(&config.mongodb_database);
     println!("✓ MongoDB connected");
 
+    // One-shot: convert any plaintext user passwords left from older versions
+    match hash_plaintext_passwords(&db).await {
+        Ok((_, 0)) => {}
+        Ok((_, migrated)) => println!("✓ {} kullanıcı şifresi hash'lendi", migrated),
+        Err(e) => tracing::error!("Password migration failed: {}", e),
+    }
+
+    if let Err(e) = audit::ensure_indexes(&db).await {
+        tracing::error!("{}", e);
+    }
+
+    if let Err(e) = pagination::ensure_indexes(&db).await {
+        tracing::error!("{}", e);
+    }
+
+    // One-shot: list sorting skips non-string timestamps, convert old BSON dates
+    match pagination::normalize_timestamps(&db).await {
+        Ok(0) => {}
+        Ok(converted) => println!("✓ {} kaydın tarih alanları metne çevrildi", converted),
+        Err(e) => tracing::error!("{}", e),
+    }
+
+    if let Err(e) = phone_index::ensure_index(&db).await {
+        tracing::error!("{}", e);
+    }
+
+    if let Err(e) = search::ensure_index(&db).await {
+        tracing::error!("{}", e);
+    }
+
+    if let Err(e) = attachments::ensure_indexes(&db).await {
+        tracing::error!("{}", e);
+    }
+
+    if let Err(e) = tracking::ensure_index(&db).await {
+        tracing::error!("{}", e);
+    }
+
+    if let Err(e) = sms_log::ensure_indexes(&db).await {
+        tracing::error!("{}", e);
+    }
+
+    if let Err(e) = sms_queue::ensure_indexes(&db).await {
+        tracing::error!("{}", e);
+    }
+
+    if let Err(e) = survey::ensure_indexes(&db).await {
+        tracing::error!("{}", e);
+    }
+
+    let attachment_store = AttachmentStore::new(
+        &config.attachment_dir,
+        &config.jwt_secret,
+        config.image_max_dimension,
+    );
+
+    // Backfill the phone and text search indexes and move embedded base64
+    // files to the attachment store in the background; large collections
+    // should not delay startup
+    {
+        let db = db.clone();
+        let cipher = cipher.clone();
+        let attachment_store = attachment_store.clone();
+        tokio::spawn(async move {
+            match phone_index::backfill_telefon_index(&db, &cipher).await {
+                Ok((_, 0)) => {}
+                Ok((_, indexed)) => println!("✓ {} müşteri kaydı telefon indeksine eklendi", indexed),
+                Err(e) => tracing::error!("Telefon index backfill failed: {}", e),
+            }
+            match search::backfill_search_terms(&db).await {
+                Ok((_, 0)) => {}
+                Ok((_, updated)) => println!("✓ {} müşteri kaydı arama indeksine eklendi", updated),
+                Err(e) => tracing::error!("Search text backfill failed: {}", e),
+            }
+            match attachments::migrate_embedded_files(&db, &attachment_store).await {
+                Ok((0, 0)) => {}
+                Ok((updated, failed)) => println!(
+                    "✓ {} kaydın gömülü belgeleri dosya deposuna taşındı ({} okunamadı)",
+                    updated, failed
+                ),
+                Err(e) => tracing::error!("Attachment migration failed: {}", e),
+            }
+        });
+    }
+
+    let sms = sms_provider::build_provider(&config.sms).unwrap_or_else(|e| {
+        eprintln!("❌ SMS sağlayıcısı başlatılamadı: {}", e);
+        std::process::exit(1);
+    });
+    println!("✓ SMS sağlayıcıları (öncelik sırasıyla): {}", config.sms.providers.join(" → "));
+
     // Start SMS queue worker
-    start_sms_queue_worker(db.clone());
+    start_sms_queue_worker(QueueWorker {
+        db: db.clone(),
+        cipher: cipher.clone(),
+        sms: sms.clone(),
+        max_attempts: config.sms.queue_max_attempts,
+        window: SendWindow::new(&config.sms.schedule),
+    });
+    start_pickup_reminder_worker(ReminderWorker {
+        db: db.clone(),
+        cipher: cipher.clone(),
+        templates: sms_templates::SmsTemplates::new(db.clone(), &config.shop_phone),
+        window: SendWindow::new(&config.sms.schedule),
+        days: config.sms.pickup_reminder_days.clone(),
+    });
+    start_trash_purge_worker(
+        db.clone(),
+        attachment_store.clone(),
+        config.trash_retention_days,
+        config.attachment_orphan_hours,
+    );
 
-    let state = AppState { db };
+    let state = AppState {
+        sms_templates: sms_templates::SmsTemplates::new(db.clone(), &config.shop_phone),
+        sms_window: SendWindow::new(&config.sms.schedule),
+        db,
+        attachments: attachment_store,
+        fatura_links: FaturaLinks::new(&config.jwt_secret, config.fatura_link_ttl_days),
+        sms_reports: sms_log::DeliveryReports::new(&config.jwt_secret),
+        tracking_limiter: RateLimiter::new(TRACKING_REQUESTS_PER_MINUTE, Duration::from_secs(60)),
+        config: Arc::new(config),
+        cipher,
+        sms,
+    };
 
-    // Routes
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .route("/api/login", post(login))
+    let api_port = state.config.api_port;
+
+    // Authenticated routes — permissions per route live in auth::ROUTE_PERMISSIONS
+    let protected = Router::new()
         .route("/api/users", get(list_users).post(create_user))
         .route("/api/users/:id", put(update_user).delete(delete_user))
         .route("/api/migrations/fix-teknisyen-notes", post(migrate_teknisyen_notes))
+        .route("/api/migrations/hash-passwords", post(migrate_hash_passwords))
+        .route("/api/migrations/telefon-index", post(migrate_telefon_index))
+        .route("/api/migrations/attachments", post(migrate_attachments))
+        .route("/api/attachments", post(upload_attachment))
         .route("/api/bing/daily-image", get(get_bing_daily_image))
         .route("/api/montaj", get(list_montaj_kayitlari).post(create_montaj_kayit))
         .route("/api/montaj/:id", get(get_montaj_kayit).put(update_montaj_kayit).delete(delete_montaj_kayit))
@@ -52,23 +214,59 @@ async fn main() {
         .route("/api/musteri-kabul", post(create_musteri_kabul))
         .route("/api/musteri-kabul", get(list_musteri_kabul))
         .route("/api/musteri-kabul/stats", get(get_musteri_kabul_stats))
+        .route("/api/musteri-kabul/search", get(search_musteri_kabul))
+        .route("/api/musteri-kabul/awaiting-pickup", get(list_awaiting_pickup))
         .route("/api/delete-otp/request", post(create_delete_otp))
         .route("/api/system/sync", get(get_system_sync))
+        .route("/api/dev/sms-outbox", get(get_sms_outbox))
+        .route("/api/sms/providers", get(get_sms_provider_health))
+        .route("/api/sms/messages", get(list_sms_messages))
+        .route("/api/sms/queue", get(list_sms_queue))
+        .route("/api/sms/schedule", get(get_sms_schedule))
+        .route("/api/surveys/report", get(get_survey_report))
+        .route("/api/sms/consent", get(get_sms_consent).put(update_sms_consent))
+        .route("/api/sms/templates", get(list_sms_templates))
+        .route("/api/sms/templates/:key", put(update_sms_template).delete(reset_sms_template))
+        .route("/api/sms/templates/:key/preview", post(preview_sms_template))
+        .route("/api/sms/queue/:id/retry", post(retry_sms_queue_item))
+        .route("/api/sms/queue/:id/cancel", post(cancel_sms_queue_item))
         .route("/api/musteri-kabul/by-status/:status", get(list_musteri_kabul_by_status))
-        .route("/api/fatura-upload/:id", put(upload_fatura_public))
         .route("/api/musteri-kabul/:id/resend-sms", post(resend_musteri_sms))
         .route("/api/musteri-kabul/:id", get(get_musteri_kabul).delete(delete_musteri_kabul).put(update_musteri_kabul))
         .route("/api/print-label", post(print_customer_label))
+        .route("/api/trash", get(list_trash))
+        .route("/api/musteri-kabul/:id/restore", post(restore_musteri_kabul))
+        .route("/api/montaj/:id/restore", post(restore_montaj_kayit))
+        .route("/api/musteri-kabul/:id/history", get(get_musteri_kabul_history))
+        .route("/api/montaj/:id/history", get(get_montaj_kayit_history))
+        .route("/api/montaj/:id/fatura-link", post(reissue_montaj_fatura_link).delete(revoke_montaj_fatura_link))
+        .route("/api/musteri-kabul/:id/fatura-link", post(reissue_musteri_fatura_link).delete(revoke_musteri_fatura_link))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_auth));
+
+    // Routes
+    let app = Router::new()
+        .route("/health", get(health_check))
+        .route("/api/login", post(login))
+        .route("/api/fatura-upload/:token", get(get_fatura_upload_info).put(upload_fatura_public))
+        .route("/api/track/:token", get(get_tracking))
+        .route("/api/survey/:token", get(get_survey).post(answer_survey))
+        .route("/api/sms/delivery-report/:provider", post(receive_sms_delivery_report))
+        .route("/api/sms/inbound/:provider", post(receive_sms_inbound))
+        .route("/api/attachments/:id", get(download_attachment))
+        .route("/api/attachments/:id/thumbnail", get(download_attachment_thumbnail))
+        .merge(protected)
         .with_state(state)
         .layer(DefaultBodyLimit::max(15 * 1024 * 1024))
         .layer(CorsLayer::permissive());
 
-    let port = env::var("API_PORT").unwrap_or_else(|_| "3000".to_string());
-    let addr = format!("0.0.0.0:{}", port);
+    let addr = format!("0.0.0.0:{}", api_port);
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
     
     println!("✓ API listening on http://{}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    // Peer address feeds the public endpoints' rate limiter, see `extract_client_ip`
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .unwrap();
 }
 
 async fn health_check() -> &'static str {
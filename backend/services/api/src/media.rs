@@ -0,0 +1,249 @@
+use std::io::Cursor;
+
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    imageops::FilterType,
+    metadata::Orientation,
+    DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits,
+};
+
+/// JPEG quality used when photos are recompressed.
+const JPEG_QUALITY: u8 = 82;
+
+/// Images larger than this (in either direction) are refused before decoding.
+const MAX_SOURCE_DIMENSION: u32 = 16_384;
+
+/// Longest side of generated thumbnails; always stored as JPEG.
+const THUMBNAIL_DIMENSION: u32 = 320;
+const THUMBNAIL_JPEG_QUALITY: u8 = 75;
+
+/// File types the API accepts, identified from the bytes themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Jpeg,
+    Png,
+    Webp,
+    Heic,
+    Pdf,
+}
+
+/// Photos and scanned documents: record attachments and fatura uploads.
+pub const DOCUMENT_KINDS: &[MediaKind] = &[
+    MediaKind::Jpeg,
+    MediaKind::Png,
+    MediaKind::Webp,
+    MediaKind::Heic,
+    MediaKind::Pdf,
+];
+
+/// Photos only: montaj installation pictures.
+pub const PHOTO_KINDS: &[MediaKind] = &[MediaKind::Jpeg, MediaKind::Png, MediaKind::Webp, MediaKind::Heic];
+
+impl MediaKind {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            MediaKind::Jpeg => "image/jpeg",
+            MediaKind::Png => "image/png",
+            MediaKind::Webp => "image/webp",
+            MediaKind::Heic => "image/heic",
+            MediaKind::Pdf => "application/pdf",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MediaKind::Jpeg => "jpg",
+            MediaKind::Png => "png",
+            MediaKind::Webp => "webp",
+            MediaKind::Heic => "heic",
+            MediaKind::Pdf => "pdf",
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.trim().to_ascii_lowercase().as_str() {
+            "image/jpeg" | "image/jpg" => Some(MediaKind::Jpeg),
+            "image/png" => Some(MediaKind::Png),
+            "image/webp" => Some(MediaKind::Webp),
+            "image/heic" | "image/heif" => Some(MediaKind::Heic),
+            "application/pdf" => Some(MediaKind::Pdf),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            MediaKind::Jpeg => "JPEG",
+            MediaKind::Png => "PNG",
+            MediaKind::Webp => "WebP",
+            MediaKind::Heic => "HEIC",
+            MediaKind::Pdf => "PDF",
+        }
+    }
+}
+
+/// Identifies a file from its magic bytes; the declared type is ignored.
+pub fn sniff(bytes: &[u8]) -> Option<MediaKind> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(MediaKind::Jpeg);
+    }
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+        return Some(MediaKind::Png);
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some(MediaKind::Webp);
+    }
+    // Only HEVC brands: `mif1`/`msf1` alone are generic HEIF and also used
+    // by AVIF, which is not accepted
+    if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+        let major_brand = &bytes[8..12];
+        if [b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis"]
+            .iter()
+            .any(|known| major_brand == *known)
+        {
+            return Some(MediaKind::Heic);
+        }
+    }
+    // Leading whitespace or a BOM is tolerated, anything else before the
+    // header could be another format with a PDF appended
+    let body = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    let start = body.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(body.len());
+    if body[start..].starts_with(b"%PDF-") {
+        return Some(MediaKind::Pdf);
+    }
+    None
+}
+
+/// HEIC and PDF cannot be decoded here: they are stored byte for byte as
+/// uploaded, without resizing and without stripping EXIF/GPS or document
+/// metadata, and get no thumbnail.
+pub fn is_stored_as_is(kind: MediaKind) -> bool {
+    matches!(kind, MediaKind::Heic | MediaKind::Pdf)
+}
+
+/// Error message listing the accepted types, e.g. "JPEG, PNG veya PDF".
+pub fn allowed_label(allowed: &[MediaKind]) -> String {
+    let labels: Vec<&str> = allowed.iter().map(|kind| kind.label()).collect();
+    match labels.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} veya {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// A file ready to be stored, with the type it actually has.
+pub struct NormalizedMedia {
+    pub bytes: Vec<u8>,
+    pub kind: MediaKind,
+    /// JPEG preview, for the types that can be decoded here.
+    pub thumbnail: Option<Vec<u8>>,
+}
+
+fn decode(bytes: &[u8], kind: MediaKind) -> Result<Option<DynamicImage>, String> {
+    let format = match kind {
+        MediaKind::Jpeg => ImageFormat::Jpeg,
+        MediaKind::Png => ImageFormat::Png,
+        MediaKind::Webp => ImageFormat::WebP,
+        MediaKind::Heic | MediaKind::Pdf => return Ok(None),
+    };
+
+    let unreadable = |e: image::ImageError| format!("Görsel okunamadı: {}", e);
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(unreadable)?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(unreadable)?;
+    image.apply_orientation(orientation);
+
+    Ok(Some(image))
+}
+
+fn encode_jpeg(image: &DynamicImage, quality: u8) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    DynamicImage::ImageRgb8(image.to_rgb8())
+        .write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality))
+        .map_err(|e| format!("Görsel kaydedilemedi: {}", e))?;
+    Ok(out)
+}
+
+fn encode_thumbnail(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    encode_jpeg(&image.thumbnail(THUMBNAIL_DIMENSION, THUMBNAIL_DIMENSION), THUMBNAIL_JPEG_QUALITY)
+}
+
+/// Thumbnail of an already stored file, or `None` for HEIC/PDF.
+///
+/// CPU heavy; call from `spawn_blocking`.
+pub fn thumbnail(bytes: &[u8], kind: MediaKind) -> Result<Option<Vec<u8>>, String> {
+    match decode(bytes, kind)? {
+        Some(image) => encode_thumbnail(&image).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Prepares an upload for storage. JPEG/PNG/WebP photos are rotated upright
+/// according to their EXIF orientation, downscaled to `max_dimension` and
+/// recompressed (which also strips EXIF, GPS included); WebP is stored as
+/// JPEG. HEIC and PDF are returned unchanged, see `is_stored_as_is`.
+///
+/// CPU heavy; call from `spawn_blocking`.
+pub fn normalize(bytes: Vec<u8>, kind: MediaKind, max_dimension: u32) -> Result<NormalizedMedia, String> {
+    let Some(mut image) = decode(&bytes, kind)? else {
+        return Ok(NormalizedMedia { bytes, kind, thumbnail: None });
+    };
+
+    if max_dimension > 0 && (image.width() > max_dimension || image.height() > max_dimension) {
+        image = image.resize(max_dimension, max_dimension, FilterType::Lanczos3);
+    }
+
+    let (bytes, kind) = match kind {
+        // PNGs are mostly screenshots; keep them lossless
+        MediaKind::Png => {
+            let mut out = Vec::new();
+            image
+                .write_with_encoder(PngEncoder::new(&mut out))
+                .map_err(|e| format!("Görsel kaydedilemedi: {}", e))?;
+            (out, MediaKind::Png)
+        }
+        _ => (encode_jpeg(&image, JPEG_QUALITY)?, MediaKind::Jpeg),
+    };
+
+    Ok(NormalizedMedia {
+        bytes,
+        kind,
+        thumbnail: Some(encode_thumbnail(&image)?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ftyp(major_brand: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let mut bytes = vec![0, 0, 0, 0x18];
+        bytes.extend_from_slice(b"ftyp");
+        bytes.extend_from_slice(major_brand);
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        for brand in compatible {
+            bytes.extend_from_slice(*brand);
+        }
+        bytes
+    }
+
+    #[test]
+    fn sniff_accepts_heic_major_brands() {
+        assert_eq!(sniff(&ftyp(b"heic", &[b"mif1", b"heic"])), Some(MediaKind::Heic));
+        assert_eq!(sniff(&ftyp(b"heix", &[b"mif1"])), Some(MediaKind::Heic));
+    }
+
+    #[test]
+    fn sniff_rejects_avif_and_generic_heif() {
+        assert_eq!(sniff(&ftyp(b"avif", &[b"mif1", b"miaf"])), None);
+        assert_eq!(sniff(&ftyp(b"mif1", &[b"avif"])), None);
+        assert_eq!(sniff(&ftyp(b"msf1", &[b"avis"])), None);
+    }
+}
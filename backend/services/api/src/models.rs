@@ -1,7 +1,8 @@
 use serde::{Deserialize, Serialize, Deserializer};
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime as BsonDateTime;
 use chrono::{DateTime, Utc};
-use crate::crypto::decrypt_value;
+use crate::crypto::FieldCipher;
 
 // Custom deserializer for DateTime fields that handles BSON DateTime
 fn deserialize_datetime<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
@@ -75,6 +76,124 @@ pub fn status_id_aliases(id: i32) -> Option<Vec<&'static str>> {
     }
 }
 
+/// Resolves a stored status string, including legacy spellings, to its id.
+pub fn status_id_from_any(status: &str) -> Option<i32> {
+    status_string_to_id(status).or_else(|| {
+        let normalized = status.trim().to_uppercase();
+        (1..=9).find(|id| {
+            status_id_aliases(*id)
+                .is_some_and(|aliases| aliases.iter().any(|alias| alias.to_uppercase() == normalized))
+        })
+    })
+}
+
+/// Whether setting status `to` changes the record's status; a legacy
+/// spelling of the same status does not count as a change.
+pub fn is_status_change(current: &str, to: i32) -> bool {
+    status_id_from_any(current) != Some(to)
+}
+
+// Status transition graph: which statuses may follow the current one.
+// TESLİM_EDİLDİ is terminal; İADE can only be handed back to the customer.
+pub fn allowed_next_statuses(from: i32) -> &'static [i32] {
+    match from {
+        1 => &[2, 3, 9],
+        2 => &[3, 4, 5, 6, 7, 9],
+        3 => &[2, 4, 5, 6, 7, 9],
+        4 => &[2, 3, 5, 6, 7, 9],
+        5 => &[3, 6, 7, 9],
+        6 => &[7, 8, 9],
+        7 => &[2, 8],
+        9 => &[8],
+        _ => &[],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionRequirement {
+    TechnicianNote,
+    PartsInfo,
+}
+
+impl TransitionRequirement {
+    pub fn message(&self, status: &str) -> String {
+        match self {
+            TransitionRequirement::TechnicianNote => {
+                format!("{} durumuna geçmek için teknisyen açıklaması gerekli", status)
+            }
+            TransitionRequirement::PartsInfo => {
+                format!("{} durumuna geçmek için parça bilgisi gerekli", status)
+            }
+        }
+    }
+}
+
+/// Fields that must be filled before a record can enter the `to` status.
+pub fn transition_requirements(to: i32) -> &'static [TransitionRequirement] {
+    match to {
+        4 => &[TransitionRequirement::PartsInfo],
+        6 | 7 | 9 => &[TransitionRequirement::TechnicianNote],
+        _ => &[],
+    }
+}
+
+/// One entry of `MusteriKabul.status_history`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatusTransition {
+    pub from: Option<String>,
+    pub to: String,
+    pub at: BsonDateTime,
+    pub actor: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusTransitionResponse {
+    pub from: Option<String>,
+    pub to: String,
+    pub at: String,
+    pub actor: String,
+}
+
+impl From<StatusTransition> for StatusTransitionResponse {
+    fn from(transition: StatusTransition) -> Self {
+        Self {
+            from: transition.from,
+            to: transition.to,
+            at: transition.at.try_to_rfc3339_string().unwrap_or_default(),
+            actor: transition.actor,
+        }
+    }
+}
+
+/// One entry of `MusteriKabul.pickup_reminders`, see `pickup_reminders`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PickupReminder {
+    pub status_id: i32,
+    /// Days since `ready_since` the reminder was scheduled for.
+    pub day: i64,
+    pub ready_since: BsonDateTime,
+    pub queued_at: BsonDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PickupReminderResponse {
+    pub status_id: i32,
+    pub day: i64,
+    pub ready_since: String,
+    pub queued_at: String,
+}
+
+impl From<PickupReminder> for PickupReminderResponse {
+    fn from(reminder: PickupReminder) -> Self {
+        Self {
+            status_id: reminder.status_id,
+            day: reminder.day,
+            ready_since: reminder.ready_since.try_to_rfc3339_string().unwrap_or_default(),
+            queued_at: reminder.queued_at.try_to_rfc3339_string().unwrap_or_default(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(crate = "serde")]
 pub struct MusteriKabul {
@@ -82,13 +201,21 @@ pub struct MusteriKabul {
     pub id: Option<ObjectId>,
     pub ad_soyad: String,
     pub telefon: String,
+    /// Blind index of the normalized phone, see `phone_index`.
+    #[serde(default)]
+    pub telefon_index: Option<String>,
     pub marka_model: String,
     pub servis_tipi: Option<String>,
     pub aksesuarlar: String,
     pub musteri_sikayeti: String,
     pub not: Option<String>,
     pub teknisyen_aciklamasi: Option<String>,
+    #[serde(default)]
+    pub parca_bilgisi: Option<String>,
     pub tamir_fisi_no: Option<String>,
+    /// Turkish-folded word prefixes of the searchable fields, see `search::search_terms`.
+    #[serde(default)]
+    pub search_terms: Option<Vec<String>>,
     pub sirala_dosya_url: Option<String>,
     pub belge_f: Option<String>,
     pub belge_g: Option<String>,
@@ -99,6 +226,20 @@ pub struct MusteriKabul {
     pub sms_mesaj: Option<String>,
     #[serde(default)]
     pub sms_sent_statuses: Option<Vec<i32>>,
+    /// Gateway that delivered the intake SMS, see `sms_provider`.
+    #[serde(default)]
+    pub sms_provider: Option<String>,
+    #[serde(default)]
+    pub status_history: Vec<StatusTransition>,
+    /// Reminders queued while the device waits for pickup.
+    #[serde(default)]
+    pub pickup_reminders: Vec<PickupReminder>,
+    /// Bumped when the public fatura upload link is revoked or reissued.
+    #[serde(default)]
+    pub fatura_link_version: i64,
+    /// Token of the public status page, see `tracking`.
+    #[serde(default)]
+    pub takip_token: Option<String>,
     #[serde(deserialize_with = "deserialize_datetime")]
     pub created_at: DateTime<Utc>,
     #[serde(deserialize_with = "deserialize_datetime")]
@@ -115,12 +256,16 @@ pub struct CreateMusteriKabulRequest {
     pub musteri_sikayeti: String,
     pub not: Option<String>,
     pub teknisyen_aciklamasi: Option<String>,
+    pub parca_bilgisi: Option<String>,
     pub tamir_fisi_no: Option<String>,
     pub status: Option<i32>,
     pub belge_f: Option<String>,
     pub belge_g: Option<String>,
     pub belge_u: Option<String>,
     pub belge_a: Option<String>,
+    /// SMS consent given at the counter; absent leaves the number's record as is.
+    pub sms_izni: Option<bool>,
+    pub pazarlama_izni: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -132,12 +277,16 @@ pub struct UpdateMusteriKabulRequest {
     pub musteri_sikayeti: Option<String>,
     pub not: Option<String>,
     pub teknisyen_aciklamasi: Option<String>,
+    pub parca_bilgisi: Option<String>,
     pub tamir_fisi_no: Option<String>,
     pub status: Option<i32>,
     pub belge_f: Option<String>,
     pub belge_g: Option<String>,
     pub belge_u: Option<String>,
     pub belge_a: Option<String>,
+    /// Send the status SMS without waiting for the sending window.
+    #[serde(default)]
+    pub sms_acil: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -151,13 +300,17 @@ pub struct MusteriKabulResponse {
     pub musteri_sikayeti: String,
     pub not: Option<String>,
     pub teknisyen_aciklamasi: Option<String>,
+    pub parca_bilgisi: Option<String>,
     pub tamir_fisi_no: Option<String>,
     pub belge_f: Option<String>,
     pub belge_g: Option<String>,
     pub belge_u: Option<String>,
     pub belge_a: Option<String>,
     pub status: String,
+    pub status_history: Vec<StatusTransitionResponse>,
+    pub pickup_reminders: Vec<PickupReminderResponse>,
     pub sms_gonderildi: bool,
+    pub sms_provider: Option<String>,
     pub created_at: String,
 }
 
@@ -179,7 +332,7 @@ pub struct MusteriKabulListResponse {
 }
 
 impl MusteriKabul {
-    pub fn new(req: CreateMusteriKabulRequest) -> Self {
+    pub fn new(req: CreateMusteriKabulRequest, actor: &str) -> Self {
         let now = Utc::now();
         let status_id = req.status.unwrap_or(1);
         let status_string = status_id_to_string(status_id).unwrap_or_else(|| "MÜŞTERI_KABUL".to_string());
@@ -187,29 +340,43 @@ impl MusteriKabul {
             id: None,
             ad_soyad: req.ad_soyad.to_uppercase(),
             telefon: req.telefon.to_uppercase(),
+            telefon_index: None,
             marka_model: req.marka_model.to_uppercase(),
             servis_tipi: req.servis_tipi.map(|v| v.to_uppercase()),
             aksesuarlar: req.aksesuarlar.to_uppercase(),
             musteri_sikayeti: req.musteri_sikayeti.to_uppercase(),
             not: req.not.map(|n| n.to_uppercase()),
             teknisyen_aciklamasi: req.teknisyen_aciklamasi.map(|n| n.to_uppercase()),
+            parca_bilgisi: req.parca_bilgisi.map(|n| n.to_uppercase()),
             tamir_fisi_no: req.tamir_fisi_no,
+            search_terms: None,
             sirala_dosya_url: None,
             belge_f: req.belge_f,
             belge_g: req.belge_g,
             belge_u: req.belge_u,
             belge_a: req.belge_a,
-            status: status_string,
+            status: status_string.clone(),
             sms_gonderildi: false,
             sms_mesaj: None,
             sms_sent_statuses: Some(vec![]),
+            sms_provider: None,
+            status_history: vec![StatusTransition {
+                from: None,
+                to: status_string,
+                at: BsonDateTime::from_millis(now.timestamp_millis()),
+                actor: actor.to_string(),
+            }],
+            pickup_reminders: Vec::new(),
+            fatura_link_version: 0,
+            takip_token: None,
             created_at: now,
             updated_at: now,
         }
     }
 
-    pub fn to_response(self) -> MusteriKabulResponse {
-        let decrypted_phone = decrypt_value(&self.telefon).unwrap_or_else(|_| self.telefon.clone());
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_response(self, cipher: &FieldCipher) -> MusteriKabulResponse {
+        let decrypted_phone = cipher.decrypt_value(&self.telefon).unwrap_or_else(|_| self.telefon.clone());
         
         MusteriKabulResponse {
             id: self.id.map(|id| id.to_hex()).unwrap_or_default(),
@@ -221,20 +388,25 @@ impl MusteriKabul {
             musteri_sikayeti: self.musteri_sikayeti,
             not: self.not,
             teknisyen_aciklamasi: self.teknisyen_aciklamasi,
+            parca_bilgisi: self.parca_bilgisi,
             tamir_fisi_no: self.tamir_fisi_no,
             belge_f: self.belge_f,
             belge_g: self.belge_g,
             belge_u: self.belge_u,
             belge_a: self.belge_a,
             status: self.status,
+            status_history: self.status_history.into_iter().map(Into::into).collect(),
+            pickup_reminders: self.pickup_reminders.into_iter().map(Into::into).collect(),
             sms_gonderildi: self.sms_gonderildi,
+            sms_provider: self.sms_provider,
             created_at: self.created_at.to_rfc3339(),
         }
     }
 
     /// Lightweight conversion for list views — no belge content, just presence flags
-    pub fn to_list_response(self) -> MusteriKabulListResponse {
-        let decrypted_phone = decrypt_value(&self.telefon).unwrap_or_else(|_| self.telefon.clone());
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_list_response(self, cipher: &FieldCipher) -> MusteriKabulListResponse {
+        let decrypted_phone = cipher.decrypt_value(&self.telefon).unwrap_or_else(|_| self.telefon.clone());
 
         MusteriKabulListResponse {
             id: self.id.map(|id| id.to_hex()).unwrap_or_default(),
@@ -244,11 +416,79 @@ impl MusteriKabul {
             servis_tipi: self.servis_tipi,
             musteri_sikayeti: self.musteri_sikayeti,
             status: self.status,
-            belge_f: self.belge_f.as_ref().map_or(false, |s| !s.is_empty()),
-            belge_g: self.belge_g.as_ref().map_or(false, |s| !s.is_empty()),
-            belge_u: self.belge_u.as_ref().map_or(false, |s| !s.is_empty()),
-            belge_a: self.belge_a.as_ref().map_or(false, |s| !s.is_empty()),
+            belge_f: self.belge_f.as_ref().is_some_and(|s| !s.is_empty()),
+            belge_g: self.belge_g.as_ref().is_some_and(|s| !s.is_empty()),
+            belge_u: self.belge_u.as_ref().is_some_and(|s| !s.is_empty()),
+            belge_a: self.belge_a.as_ref().is_some_and(|s| !s.is_empty()),
             created_at: self.created_at.to_rfc3339(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delivered_is_terminal() {
+        assert!(allowed_next_statuses(8).is_empty());
+        assert!(allowed_next_statuses(0).is_empty());
+        assert!(allowed_next_statuses(10).is_empty());
+    }
+
+    #[test]
+    fn returns_can_only_be_delivered() {
+        assert_eq!(allowed_next_statuses(9), &[8]);
+    }
+
+    #[test]
+    fn transitions_stay_within_known_statuses() {
+        for from in 1..=9 {
+            for &to in allowed_next_statuses(from) {
+                assert_ne!(from, to, "{from} may not transition to itself");
+                assert_ne!(to, 1, "{from} may not go back to MÜŞTERİ KABUL");
+                assert!(status_id_to_string(to).is_some(), "{from} -> unknown {to}");
+            }
+        }
+    }
+
+    #[test]
+    fn every_status_can_reach_delivery() {
+        for from in 1..=9 {
+            let mut seen = vec![from];
+            let mut queue = vec![from];
+            while let Some(status) = queue.pop() {
+                for &next in allowed_next_statuses(status) {
+                    if !seen.contains(&next) {
+                        seen.push(next);
+                        queue.push(next);
+                    }
+                }
+            }
+            assert!(seen.contains(&8), "{from} cannot reach TESLİM EDİLDİ");
+        }
+    }
+
+    #[test]
+    fn delivery_requires_a_finished_repair_or_exchange() {
+        let into_delivery: Vec<i32> = (1..=9).filter(|from| allowed_next_statuses(*from).contains(&8)).collect();
+        assert_eq!(into_delivery, vec![6, 7, 9]);
+    }
+
+    #[test]
+    fn legacy_alias_of_the_same_status_is_not_a_transition() {
+        assert!(!is_status_change("PARCA_BEKLIYOR", 4));
+        assert!(!is_status_change("IADE", 9));
+        assert!(!is_status_change("musteri_kabul", 1));
+        assert!(!is_status_change("TESLİM_EDİLDİ", 8));
+    }
+
+    #[test]
+    fn legacy_alias_transitions_follow_the_graph() {
+        assert!(is_status_change("PARCA_BEKLIYOR", 5));
+        let from = status_id_from_any("PARCA_BEKLIYOR").unwrap();
+        assert!(allowed_next_statuses(from).contains(&5));
+        assert!(!allowed_next_statuses(status_id_from_any("teslim_edildi").unwrap()).contains(&2));
+        assert!(is_status_change("BILINMEYEN", 2));
+    }
+}
@@ -0,0 +1,408 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Document},
+    options::IndexOptions,
+    Collection, Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{status_id_aliases, status_id_to_string};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+/// Plain `YYYY-MM-DD` dates in filters are interpreted in Turkey time (UTC+3).
+const LOCAL_UTC_OFFSET_SECONDS: i32 = 3 * 60 * 60;
+
+/// Computed field holding the status id while sorting by status.
+const STATUS_ORDER_FIELD: &str = "_status_order";
+
+/// Sort position of statuses that match no known label.
+const UNKNOWN_STATUS_ORDER: i32 = 99;
+
+/// `$dateToString` format matching `chrono::DateTime::to_rfc3339` output, so
+/// converted values sort correctly against the ones written by the handlers.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S.%L+00:00";
+
+/// Query string shared by the list endpoints.
+///
+/// `from` / `to` filter on `created_at` and accept either a date or an RFC3339
+/// timestamp; a plain `to` date includes the whole day. `sort=status` orders
+/// by status id. The date sorts only see string values so every page boundary
+/// is a real value; `normalize_timestamps` converts older BSON dates at startup.
+#[derive(Debug, Default, Deserialize)]
+pub struct ListQuery {
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+    pub sort: Option<String>,
+    pub order: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub servis_tipi: Option<String>,
+}
+
+/// Response envelope of the list endpoints.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: u64,
+    pub limit: i64,
+    pub next_cursor: Option<String>,
+}
+
+/// Sort value stored in a cursor: a date/label string or a status id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum CursorValue {
+    Text(String),
+    Number(i32),
+}
+
+impl CursorValue {
+    fn from_bson(value: &Bson) -> Option<Self> {
+        match value {
+            Bson::String(text) => Some(CursorValue::Text(text.clone())),
+            Bson::Int32(number) => Some(CursorValue::Number(*number)),
+            _ => None,
+        }
+    }
+
+    fn into_bson(self) -> Bson {
+        match self {
+            CursorValue::Text(text) => Bson::String(text),
+            CursorValue::Number(number) => Bson::Int32(number),
+        }
+    }
+}
+
+/// Position of the last returned item: its sort value and `_id`.
+#[derive(Debug, Serialize, Deserialize)]
+struct PageCursor {
+    value: CursorValue,
+    id: String,
+}
+
+/// Resolved query: filter for the count, filter + sort for the page itself.
+/// Status sorts run as an aggregation that first computes the status id.
+pub struct ListPlan {
+    count_filter: Document,
+    cursor_filter: Option<Document>,
+    sort: Document,
+    sort_field: &'static str,
+    by_status: bool,
+    limit: i64,
+}
+
+/// Indexes backing the sort options of the paginated list endpoints.
+pub async fn ensure_indexes(db: &Database) -> Result<(), String> {
+    let index = |keys: Document, name: &str| {
+        IndexModel::builder()
+            .keys(keys)
+            .options(IndexOptions::builder().name(name.to_string()).build())
+            .build()
+    };
+
+    for name in ["musteri_kabul", "montaj_kayitlari"] {
+        db.collection::<Document>(name)
+            .create_indexes(vec![
+                index(doc! { "created_at": -1, "_id": -1 }, "created_at_-1__id_-1"),
+                index(doc! { "updated_at": -1, "_id": -1 }, "updated_at_-1__id_-1"),
+            ])
+            .await
+            .map_err(|e| format!("List index error ({}): {}", name, e))?;
+    }
+
+    // Backs `/by-status/{id}`: a `status $in` filter sorted by `created_at`.
+    // `sort=status` orders by a computed field and cannot use an index.
+    db.collection::<Document>("musteri_kabul")
+        .create_index(index(doc! { "status": 1, "created_at": -1, "_id": -1 }, "status_1_created_at_-1__id_-1"))
+        .await
+        .map_err(|e| format!("List index error (musteri_kabul): {}", e))?;
+
+    Ok(())
+}
+
+/// Rewrites `created_at` / `updated_at` values that are not strings (BSON
+/// dates from older versions, or missing) as RFC3339 strings, so those records
+/// are not left out of the lists. A missing `created_at` falls back to the
+/// `_id` timestamp, a missing `updated_at` to `created_at`. Returns the number
+/// of documents changed.
+pub async fn normalize_timestamps(db: &Database) -> Result<u64, String> {
+    let as_date = |field: &str| {
+        Bson::Document(doc! {
+            "$convert": { "input": format!("${}", field), "to": "date", "onError": Bson::Null, "onNull": Bson::Null }
+        })
+    };
+    let id_date = Bson::Document(doc! { "$toDate": "$_id" });
+
+    let created_at = doc! { "$ifNull": [as_date("created_at"), id_date.clone()] };
+    let updated_at = doc! {
+        "$ifNull": [as_date("updated_at"), { "$ifNull": [as_date("created_at"), id_date] }]
+    };
+
+    let mut modified = 0;
+    for name in ["musteri_kabul", "montaj_kayitlari"] {
+        let collection = db.collection::<Document>(name);
+        for (field, date) in [("created_at", &created_at), ("updated_at", &updated_at)] {
+            let update = vec![doc! {
+                "$set": { field: { "$dateToString": { "format": TIMESTAMP_FORMAT, "date": date.clone() } } }
+            }];
+            let result = collection
+                .update_many(doc! { field: { "$not": { "$type": "string" } } }, update)
+                .await
+                .map_err(|e| format!("Timestamp migration error ({}.{}): {}", name, field, e))?;
+            modified += result.modified_count;
+        }
+    }
+
+    Ok(modified)
+}
+
+pub fn parse_bound(value: &str, end_of_day: bool) -> Result<String, String> {
+    let value = value.trim();
+
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc).to_rfc3339());
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| format!("Geçersiz tarih: {}", value))?;
+    let date = if end_of_day {
+        date.succ_opt().ok_or_else(|| format!("Geçersiz tarih: {}", value))?
+    } else {
+        date
+    };
+
+    let offset = FixedOffset::east_opt(LOCAL_UTC_OFFSET_SECONDS).expect("valid offset");
+    let local = date
+        .and_hms_opt(0, 0, 0)
+        .and_then(|naive| naive.and_local_timezone(offset).single())
+        .ok_or_else(|| format!("Geçersiz tarih: {}", value))?;
+
+    Ok(local.with_timezone(&Utc).to_rfc3339())
+}
+
+fn decode_cursor(cursor: &str) -> Result<(CursorValue, ObjectId), String> {
+    let invalid = || "Geçersiz cursor".to_string();
+    let bytes = URL_SAFE_NO_PAD.decode(cursor.trim()).map_err(|_| invalid())?;
+    let cursor: PageCursor = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+    let id = ObjectId::parse_str(&cursor.id).map_err(|_| invalid())?;
+    Ok((cursor.value, id))
+}
+
+fn encode_cursor(value: CursorValue, id: ObjectId) -> String {
+    let cursor = PageCursor { value, id: id.to_hex() };
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(&cursor).unwrap_or_default())
+}
+
+/// `$addFields` stage mapping the stored status label (or a legacy
+/// spelling) to its id, see `models::status_id_from_any`.
+fn status_order_stage() -> Document {
+    let branches: Vec<Bson> = (1..=9)
+        .map(|id| {
+            let mut labels: Vec<String> = status_id_to_string(id).into_iter().collect();
+            labels.extend(status_id_aliases(id).unwrap_or_default().into_iter().map(str::to_string));
+            Bson::Document(doc! { "case": { "$in": ["$status", labels] }, "then": id })
+        })
+        .collect();
+
+    doc! {
+        "$addFields": {
+            STATUS_ORDER_FIELD: { "$switch": { "branches": branches, "default": UNKNOWN_STATUS_ORDER } }
+        }
+    }
+}
+
+impl ListQuery {
+    /// Applies sorting, filters and the cursor on top of `base_filter`.
+    pub fn plan(&self, base_filter: Document) -> Result<ListPlan, String> {
+        let limit = self.limit.unwrap_or(DEFAULT_LIMIT);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(format!("limit 1 ile {} arasında olmalı", MAX_LIMIT));
+        }
+
+        let (sort_field, by_status) = match self.sort.as_deref().map(str::trim) {
+            None | Some("") | Some("created_at") => ("created_at", false),
+            Some("updated_at") => ("updated_at", false),
+            Some("status") => (STATUS_ORDER_FIELD, true),
+            Some(other) => return Err(format!("Geçersiz sıralama alanı: {}", other)),
+        };
+
+        let direction = match self.order.as_deref().map(str::trim) {
+            None | Some("") | Some("desc") => -1,
+            Some("asc") => 1,
+            Some(other) => return Err(format!("Geçersiz sıralama yönü: {}", other)),
+        };
+
+        let mut filter = base_filter;
+
+        let mut created_range = Document::new();
+        if let Some(from) = self.from.as_deref().filter(|v| !v.trim().is_empty()) {
+            created_range.insert("$gte", parse_bound(from, false)?);
+        }
+        if let Some(to) = self.to.as_deref().filter(|v| !v.trim().is_empty()) {
+            let is_date_only = DateTime::parse_from_rfc3339(to.trim()).is_err();
+            let bound = parse_bound(to, is_date_only)?;
+            created_range.insert(if is_date_only { "$lt" } else { "$lte" }, bound);
+        }
+        if !created_range.is_empty() {
+            filter.insert("created_at", created_range);
+        }
+
+        if let Some(servis_tipi) = self.servis_tipi.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+            filter.insert("servis_tipi", servis_tipi.to_uppercase());
+        }
+
+        let count_filter = if by_status {
+            filter
+        } else {
+            doc! { "$and": [filter, { sort_field: { "$type": "string" } }] }
+        };
+
+        let cursor_filter = match self.cursor.as_deref().filter(|v| !v.trim().is_empty()) {
+            Some(cursor) => {
+                let (value, id) = decode_cursor(cursor)?;
+                let value = value.into_bson();
+                let op = if direction < 0 { "$lt" } else { "$gt" };
+                Some(doc! {
+                    "$or": [
+                        { sort_field: { op: value.clone() } },
+                        { sort_field: value, "_id": { op: id } },
+                    ]
+                })
+            }
+            None => None,
+        };
+
+        Ok(ListPlan {
+            count_filter,
+            cursor_filter,
+            sort: doc! { sort_field: direction, "_id": direction },
+            sort_field,
+            by_status,
+            limit,
+        })
+    }
+}
+
+impl ListPlan {
+    /// Loads one page plus the total number of matching documents.
+    pub async fn fetch(&self, collection: &Collection<Document>) -> Result<Page<Document>, String> {
+        let total = collection
+            .count_documents(self.count_filter.clone())
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        // One extra document tells whether another page exists.
+        let mut cursor = if self.by_status {
+            let mut pipeline = vec![doc! { "$match": self.count_filter.clone() }, status_order_stage()];
+            if let Some(cursor_filter) = &self.cursor_filter {
+                pipeline.push(doc! { "$match": cursor_filter.clone() });
+            }
+            pipeline.push(doc! { "$sort": self.sort.clone() });
+            pipeline.push(doc! { "$limit": self.limit + 1 });
+            collection.aggregate(pipeline).await
+        } else {
+            let page_filter = match &self.cursor_filter {
+                Some(cursor_filter) => doc! { "$and": [self.count_filter.clone(), cursor_filter.clone()] },
+                None => self.count_filter.clone(),
+            };
+            collection
+                .find(page_filter)
+                .sort(self.sort.clone())
+                .limit(self.limit + 1)
+                .await
+        }
+        .map_err(|e| format!("Database error: {}", e))?;
+
+        let mut items = Vec::new();
+        while cursor
+            .advance()
+            .await
+            .map_err(|e| format!("Cursor error: {}", e))?
+        {
+            items.push(
+                cursor
+                    .deserialize_current()
+                    .map_err(|e| format!("Deserialize error: {}", e))?,
+            );
+        }
+
+        let next_cursor = if items.len() as i64 > self.limit {
+            items.truncate(self.limit as usize);
+            items.last().and_then(|last: &Document| {
+                let value = last.get(self.sort_field).and_then(CursorValue::from_bson)?;
+                last.get_object_id("_id").ok().map(|id| encode_cursor(value, id))
+            })
+        } else {
+            None
+        };
+        if self.by_status {
+            for item in &mut items {
+                item.remove(STATUS_ORDER_FIELD);
+            }
+        }
+
+        Ok(Page {
+            items,
+            total,
+            limit: self.limit,
+            next_cursor,
+        })
+    }
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            limit: self.limit,
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips_text_values() {
+        let id = ObjectId::new();
+        let value = CursorValue::Text("2026-10-12T07:30:00+00:00".to_string());
+        let cursor = encode_cursor(value.clone(), id);
+        assert_eq!(decode_cursor(&cursor).unwrap(), (value, id));
+    }
+
+    #[test]
+    fn cursor_round_trips_status_ids() {
+        let id = ObjectId::new();
+        let cursor = encode_cursor(CursorValue::Number(4), id);
+        assert_eq!(decode_cursor(&cursor).unwrap(), (CursorValue::Number(4), id));
+    }
+
+    #[test]
+    fn numeric_text_stays_text() {
+        let id = ObjectId::new();
+        let cursor = encode_cursor(CursorValue::Text("4".to_string()), id);
+        assert_eq!(decode_cursor(&cursor).unwrap().0, CursorValue::Text("4".to_string()));
+    }
+
+    #[test]
+    fn cursor_values_map_to_bson() {
+        assert_eq!(CursorValue::from_bson(&Bson::Int32(7)), Some(CursorValue::Number(7)));
+        assert_eq!(CursorValue::Number(7).into_bson(), Bson::Int32(7));
+        assert_eq!(CursorValue::from_bson(&Bson::Null), None);
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        let bad_id = URL_SAFE_NO_PAD.encode(br#"{"value":"x","id":"nope"}"#);
+        let bad_value = URL_SAFE_NO_PAD.encode(br#"{"value":true,"id":"000000000000000000000000"}"#);
+        for cursor in ["", "not base64!", "e30", bad_id.as_str(), bad_value.as_str()] {
+            assert!(decode_cursor(cursor).is_err(), "{cursor:?} should be rejected");
+        }
+    }
+}
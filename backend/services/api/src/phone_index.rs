@@ -0,0 +1,87 @@
+use mongodb::{
+    bson::{doc, Document},
+    options::IndexOptions,
+    Database, IndexModel,
+};
+
+use crate::crypto::FieldCipher;
+use crate::sms::normalize_phone;
+
+/// Minimum digits for a phone number to be indexed or searched.
+const MIN_PHONE_DIGITS: usize = 10;
+
+/// Blind index of `telefon` in `musteri_kabul.telefon_index`. Numbers are
+/// normalized the same way as for SMS, so "0532 ..." and "+90532..." match.
+pub fn telefon_index(cipher: &FieldCipher, phone: &str) -> Option<String> {
+    let digits = phone.chars().filter(|c| c.is_ascii_digit()).count();
+    if digits < MIN_PHONE_DIGITS {
+        return None;
+    }
+    Some(cipher.blind_index(&normalize_phone(phone)))
+}
+
+pub async fn ensure_index(db: &Database) -> Result<(), String> {
+    db.collection::<Document>("musteri_kabul")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "telefon_index": 1 })
+                .options(IndexOptions::builder().name("telefon_index_1".to_string()).build())
+                .build(),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Telefon index error: {}", e))
+}
+
+/// Fills `telefon_index` for records created before it existed (trashed ones
+/// included). Returns (scanned, indexed). Safe to run repeatedly.
+pub async fn backfill_telefon_index(db: &Database, cipher: &FieldCipher) -> Result<(i64, i64), String> {
+    let collection = db.collection::<Document>("musteri_kabul");
+
+    let mut cursor = collection
+        .find(doc! { "telefon_index": { "$exists": false } })
+        .projection(doc! { "telefon": 1 })
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let mut scanned: i64 = 0;
+    let mut indexed: i64 = 0;
+
+    while cursor
+        .advance()
+        .await
+        .map_err(|e| format!("Cursor error: {}", e))?
+    {
+        let current: Document = cursor
+            .deserialize_current()
+            .map_err(|e| format!("Deserialize error: {}", e))?;
+
+        scanned += 1;
+
+        let Ok(id) = current.get_object_id("_id") else {
+            continue;
+        };
+        let stored = current.get_str("telefon").unwrap_or("");
+
+        // Very old records may still hold the number in plaintext
+        let phone = cipher.decrypt_value(stored).unwrap_or_else(|_| stored.to_string());
+        // Unusable numbers get null so they are not rescanned on every run
+        let index = telefon_index(cipher, &phone);
+        let is_indexed = index.is_some();
+
+        // Only touch the value we read, in case the record was edited meanwhile
+        let result = collection
+            .update_one(
+                doc! { "_id": id, "telefon": stored },
+                doc! { "$set": { "telefon_index": index } },
+            )
+            .await
+            .map_err(|e| format!("Update error: {}", e))?;
+
+        if is_indexed && result.modified_count > 0 {
+            indexed += 1;
+        }
+    }
+
+    Ok((scanned, indexed))
+}
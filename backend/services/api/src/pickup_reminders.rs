@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use mongodb::{
+    bson::{doc, DateTime, Document},
+    Database,
+};
+use serde::Serialize;
+
+use crate::crypto::FieldCipher;
+use crate::models::{status_id_aliases, MusteriKabul, PickupReminder};
+use crate::sms_queue::enqueue_pickup_reminder;
+use crate::sms_schedule::SendWindow;
+use crate::sms_templates::SmsTemplates;
+use crate::tracking::{self, PickupWindow};
+use crate::trash;
+
+/// Statuses in which a device waits for the customer.
+const PICKUP_STATUSES: &[i32] = &[7, 9];
+
+/// Devices waiting longer than the last reminder day plus this are not
+/// reminded any more, so turning reminders on does not text every customer
+/// with a long-forgotten device.
+const REMINDER_GRACE_DAYS: i64 = 3;
+
+fn pickup_filter() -> Document {
+    let statuses: Vec<String> = PICKUP_STATUSES
+        .iter()
+        .filter_map(|id| status_id_aliases(*id))
+        .flatten()
+        .map(str::to_string)
+        .collect();
+    trash::active(doc! { "status": { "$in": statuses } })
+}
+
+fn bson_datetime(at: chrono::DateTime<Utc>) -> DateTime {
+    DateTime::from_millis(at.timestamp_millis())
+}
+
+/// Records waiting for pickup, oldest first, with their pickup window.
+async fn waiting_records(db: &Database) -> Result<Vec<(MusteriKabul, PickupWindow)>, String> {
+    let mut cursor = db
+        .collection::<Document>("musteri_kabul")
+        .find(pickup_filter())
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let mut records = Vec::new();
+    while cursor
+        .advance()
+        .await
+        .map_err(|e| format!("Cursor error: {}", e))?
+    {
+        let document = cursor
+            .deserialize_current()
+            .map_err(|e| format!("Deserialize error: {}", e))?;
+        // Documents that no longer match the model are skipped, as in the lists
+        let Ok(musteri) = mongodb::bson::from_document::<MusteriKabul>(document) else {
+            continue;
+        };
+        if let Some(window) = tracking::pickup_window(&musteri) {
+            records.push((musteri, window));
+        }
+    }
+
+    records.sort_by_key(|(_, window)| window.ready_since);
+    Ok(records)
+}
+
+/// Reminder job settings.
+#[derive(Clone)]
+pub struct ReminderWorker {
+    pub db: Database,
+    pub cipher: FieldCipher,
+    pub templates: SmsTemplates,
+    pub window: SendWindow,
+    /// Ascending, see `sms.pickup_reminder_days`.
+    pub days: Vec<i64>,
+}
+
+pub fn start_pickup_reminder_worker(worker: ReminderWorker) {
+    if worker.days.is_empty() {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            match queue_due_reminders(&worker).await {
+                Ok(0) => {}
+                Ok(queued) => println!("⏰ {} teslim hatırlatması kuyruğa alındı", queued),
+                Err(e) => tracing::error!("Pickup reminder worker error: {}", e),
+            }
+            tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+        }
+    });
+}
+
+/// Queues the latest reminder each waiting device is due for. Earlier days
+/// that were missed (e.g. while the API was down) are not sent late.
+async fn queue_due_reminders(worker: &ReminderWorker) -> Result<usize, String> {
+    let Some(last_day) = worker.days.last().copied() else {
+        return Ok(0);
+    };
+    let collection = worker.db.collection::<Document>("musteri_kabul");
+    let now = Utc::now();
+    let mut queued = 0;
+
+    for (musteri, window) in waiting_records(&worker.db).await? {
+        let Some(id) = musteri.id else {
+            continue;
+        };
+        let waited = (now - window.ready_since).num_days();
+        if waited > last_day + REMINDER_GRACE_DAYS {
+            continue;
+        }
+        let Some(day) = worker.days.iter().rev().find(|day| **day <= waited).copied() else {
+            continue;
+        };
+
+        let ready_since = bson_datetime(window.ready_since);
+        let reminder = PickupReminder {
+            status_id: window.status_id,
+            day,
+            ready_since,
+            queued_at: DateTime::now(),
+        };
+        let entry = mongodb::bson::to_bson(&reminder).map_err(|e| format!("Serialize error: {}", e))?;
+
+        // Claimed on the record first, so several API instances never queue
+        // the same reminder twice
+        let claimed = collection
+            .update_one(
+                doc! {
+                    "_id": id,
+                    "pickup_reminders": {
+                        "$not": { "$elemMatch": { "day": day, "ready_since": ready_since } }
+                    },
+                },
+                doc! { "$push": { "pickup_reminders": entry } },
+            )
+            .await
+            .map_err(|e| format!("Reminder update error: {}", e))?;
+        if claimed.modified_count == 0 {
+            continue;
+        }
+
+        // Until the SMS is queued the claim is released on failure, so the
+        // next run tries again instead of the reminder being lost
+        let release = || async {
+            if let Err(e) = collection
+                .update_one(
+                    doc! { "_id": id },
+                    doc! { "$pull": { "pickup_reminders": { "day": day, "ready_since": ready_since } } },
+                )
+                .await
+            {
+                tracing::error!("Pickup reminder claim could not be released for {}: {}", id, e);
+            }
+        };
+
+        let Ok(phone) = worker.cipher.decrypt_value(&musteri.telefon) else {
+            tracing::error!("Pickup reminder skipped, phone could not be decrypted: {}", id);
+            release().await;
+            continue;
+        };
+        let deadline = window.deadline.format("%d.%m.%Y").to_string();
+        let message = worker
+            .templates
+            .render(
+                &format!("pickup_reminder_{}", window.status_id),
+                &[
+                    ("ad_soyad", &musteri.ad_soyad),
+                    ("model", &musteri.marka_model),
+                    ("son_tarih", &deadline),
+                ],
+            )
+            .await;
+
+        if let Err(e) = enqueue_pickup_reminder(&worker.db, &worker.window, id, phone, message, window.status_id).await {
+            release().await;
+            return Err(e);
+        }
+        queued += 1;
+    }
+
+    Ok(queued)
+}
+
+/// A device waiting for pickup, as listed for staff.
+#[derive(Debug, Serialize)]
+pub struct AwaitingPickup {
+    pub id: String,
+    pub ad_soyad: String,
+    pub telefon: String,
+    pub marka_model: String,
+    pub status_id: i32,
+    pub ready_since: String,
+    pub teslim_son_tarih: String,
+    pub days_waiting: i64,
+    /// Past `teslim_son_tarih`.
+    pub overdue: bool,
+    /// Reminders queued since the device became ready.
+    pub reminder_days: Vec<i64>,
+}
+
+/// Devices in status 7 or 9, longest waiting first; only those past their
+/// pickup deadline with `overdue_only`.
+pub async fn awaiting_pickup(
+    db: &Database,
+    cipher: &FieldCipher,
+    overdue_only: bool,
+) -> Result<Vec<AwaitingPickup>, String> {
+    let now = Utc::now();
+
+    Ok(waiting_records(db)
+        .await?
+        .into_iter()
+        .filter(|(_, window)| !overdue_only || window.deadline < now)
+        .map(|(musteri, window)| {
+            let ready_since = bson_datetime(window.ready_since);
+            let reminder_days = musteri
+                .pickup_reminders
+                .iter()
+                .filter(|reminder| reminder.ready_since == ready_since)
+                .map(|reminder| reminder.day)
+                .collect();
+            AwaitingPickup {
+                id: musteri.id.map(|id| id.to_hex()).unwrap_or_default(),
+                telefon: cipher
+                    .decrypt_value(&musteri.telefon)
+                    .unwrap_or_else(|_| musteri.telefon.clone()),
+                ad_soyad: musteri.ad_soyad,
+                marka_model: musteri.marka_model,
+                status_id: window.status_id,
+                ready_since: window.ready_since.to_rfc3339(),
+                teslim_son_tarih: window.deadline.date_naive().to_string(),
+                days_waiting: (now - window.ready_since).num_days(),
+                overdue: window.deadline < now,
+                reminder_days,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Keys tracked before expired windows are swept out.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+/// Fixed-window request counter per key (usually a client IP), kept in
+/// memory. Good enough for one API instance; limits reset on restart.
+#[derive(Clone)]
+pub struct RateLimiter {
+    windows: Arc<Mutex<HashMap<String, (Instant, u32)>>>,
+    max_requests: u32,
+    window: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            windows: Arc::new(Mutex::new(HashMap::new())),
+            max_requests,
+            window,
+        }
+    }
+
+    /// Counts a request for `key`; false once the key is over its limit.
+    pub fn check(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+
+        if windows.len() >= SWEEP_THRESHOLD {
+            let window = self.window;
+            windows.retain(|_, (started, _)| now.duration_since(*started) < window);
+        }
+
+        let entry = windows.entry(key.to_string()).or_insert((now, 0));
+        if now.duration_since(entry.0) >= self.window {
+            *entry = (now, 0);
+        }
+
+        entry.1 += 1;
+        entry.1 <= self.max_requests
+    }
+}
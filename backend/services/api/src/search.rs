@@ -0,0 +1,173 @@
+use mongodb::{
+    bson::{doc, Document},
+    options::IndexOptions,
+    Database, IndexModel,
+};
+
+/// `musteri_kabul` fields covered by full-text search.
+pub const SEARCH_FIELDS: [&str; 5] = [
+    "ad_soyad",
+    "marka_model",
+    "musteri_sikayeti",
+    "teknisyen_aciklamasi",
+    "tamir_fisi_no",
+];
+
+/// Folds Turkish text to a plain lowercase ASCII-ish form so that
+/// "İSMAİL", "ismail", "ISMAIL" and "ısmaıl" all compare equal.
+///
+/// Stored values are upper-cased with Rust's (non-Turkish) `to_uppercase`,
+/// which turns both `i` and `ı` into `I`, so the dotted/dotless distinction
+/// cannot be kept anyway.
+pub fn fold_turkish(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            'İ' | 'I' | 'ı' | 'i' | 'Î' | 'î' => folded.push('i'),
+            'Ş' | 'ş' => folded.push('s'),
+            'Ğ' | 'ğ' => folded.push('g'),
+            'Ü' | 'ü' | 'Û' | 'û' => folded.push('u'),
+            'Ö' | 'ö' => folded.push('o'),
+            'Ç' | 'ç' => folded.push('c'),
+            'Â' | 'â' => folded.push('a'),
+            // Combining dot left over from lower-casing "İ" elsewhere
+            '\u{0307}' => {}
+            c if c.is_alphanumeric() => folded.extend(c.to_lowercase()),
+            _ => folded.push(' '),
+        }
+    }
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Shortest and longest prefix stored per word. Longer query words are cut
+/// to `MAX_TERM_CHARS`, so they still match.
+const MIN_TERM_CHARS: usize = 2;
+const MAX_TERM_CHARS: usize = 16;
+
+fn truncate_term(word: &str) -> String {
+    word.chars().take(MAX_TERM_CHARS).collect()
+}
+
+/// Value of the `search_terms` field for a `musteri_kabul` document: every
+/// prefix of every folded word, so "SAMS" finds "SAMSUNG" through the index.
+pub fn search_terms(record: &Document) -> Vec<String> {
+    let mut terms: Vec<String> = Vec::new();
+    for field in SEARCH_FIELDS {
+        let Ok(value) = record.get_str(field) else {
+            continue;
+        };
+        for word in fold_turkish(value).split_whitespace() {
+            let chars: Vec<char> = word.chars().take(MAX_TERM_CHARS).collect();
+            for len in MIN_TERM_CHARS.min(chars.len())..=chars.len() {
+                terms.push(chars[..len].iter().collect());
+            }
+        }
+    }
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+/// Folded query words; a record matches when it has all of them as prefixes.
+pub fn query_terms(query: &str) -> Vec<String> {
+    fold_turkish(query)
+        .split_whitespace()
+        .filter(|word| word.chars().count() >= MIN_TERM_CHARS)
+        .map(truncate_term)
+        .collect()
+}
+
+pub async fn ensure_index(db: &Database) -> Result<(), String> {
+    db.collection::<Document>("musteri_kabul")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "search_terms": 1 })
+                .options(IndexOptions::builder().name("search_terms_1".to_string()).build())
+                .build(),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Search index error: {}", e))
+}
+
+/// Fills `search_terms` for records written before it existed.
+/// Returns (scanned, updated). Safe to run repeatedly.
+pub async fn backfill_search_terms(db: &Database) -> Result<(i64, i64), String> {
+    let collection = db.collection::<Document>("musteri_kabul");
+
+    let mut projection = doc! { "_id": 1 };
+    for field in SEARCH_FIELDS {
+        projection.insert(field, 1);
+    }
+
+    let mut cursor = collection
+        .find(doc! { "search_terms": { "$exists": false } })
+        .projection(projection)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let mut scanned: i64 = 0;
+    let mut updated: i64 = 0;
+
+    while cursor
+        .advance()
+        .await
+        .map_err(|e| format!("Cursor error: {}", e))?
+    {
+        let current: Document = cursor
+            .deserialize_current()
+            .map_err(|e| format!("Deserialize error: {}", e))?;
+
+        scanned += 1;
+
+        let Ok(id) = current.get_object_id("_id") else {
+            continue;
+        };
+
+        let result = collection
+            .update_one(
+                doc! { "_id": id, "search_terms": { "$exists": false } },
+                doc! { "$set": { "search_terms": search_terms(&current) } },
+            )
+            .await
+            .map_err(|e| format!("Update error: {}", e))?;
+
+        updated += result.modified_count as i64;
+    }
+
+    Ok((scanned, updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotted_and_dotless_i_fold_together() {
+        for text in ["İSMAİL", "ismail", "ISMAIL", "ısmaıl", "i\u{0307}smai\u{0307}l"] {
+            assert_eq!(fold_turkish(text), "ismail", "{text:?}");
+        }
+    }
+
+    #[test]
+    fn turkish_letters_fold_to_ascii() {
+        assert_eq!(fold_turkish("ŞĞÜÖÇ şğüöç"), "sguoc sguoc");
+        assert_eq!(fold_turkish("Âlî Ûmit"), "ali umit");
+    }
+
+    #[test]
+    fn punctuation_and_spacing_collapse() {
+        assert_eq!(fold_turkish("  Samsung/Galaxy-S21,  ekran  "), "samsung galaxy s21 ekran");
+        assert_eq!(fold_turkish("..."), "");
+    }
+
+    #[test]
+    fn query_terms_match_stored_prefixes() {
+        let record = doc! { "ad_soyad": "ŞÜKRÜ IŞIK", "marka_model": "SAMSUNG GALAXY S21 ULTRA PROFESSIONALISMS" };
+        let terms = search_terms(&record);
+        for query in query_terms("şük ışı sams professionalismsxyz") {
+            assert!(terms.contains(&query), "{query:?} not in {terms:?}");
+        }
+        assert_eq!(query_terms("a tv"), vec!["tv".to_string()]);
+    }
+}
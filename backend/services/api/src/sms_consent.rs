@@ -0,0 +1,192 @@
+use chrono::{DateTime, Utc};
+use mongodb::{
+    bson::{doc, Document},
+    options::ReturnDocument,
+    Database,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::sms_encoding::transliterate;
+
+pub const COLLECTION: &str = "sms_consents";
+
+/// Where a consent change came from.
+pub const SOURCE_INTAKE: &str = "intake";
+pub const SOURCE_MONTAJ: &str = "montaj";
+pub const SOURCE_ADMIN: &str = "admin";
+pub const SOURCE_KEYWORD: &str = "keyword";
+
+/// Replies that opt a number out of every SMS (compared transliterated and
+/// upper-cased, so "İptal" and "iptal" both match).
+const OPT_OUT_KEYWORDS: &[&str] = &["RET", "IPTAL", "STOP", "DUR"];
+
+/// Consent for one phone number, keyed by its blind index.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmsConsent {
+    #[serde(rename = "_id")]
+    pub recipient_hash: String,
+    /// Service notifications about the customer's own device (intake,
+    /// status, montaj). Allowed unless the number opted out.
+    pub transactional: bool,
+    /// Promotional messages. Only with explicit consent.
+    pub marketing: bool,
+    pub source: String,
+    pub updated_by: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SmsConsent {
+    /// What applies to a number nobody has recorded consent for.
+    pub fn default_for(recipient_hash: &str) -> Self {
+        Self {
+            recipient_hash: recipient_hash.to_string(),
+            transactional: true,
+            marketing: false,
+            source: "default".to_string(),
+            updated_by: None,
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+/// Requested change; `None` leaves that consent as it is.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConsentChange {
+    pub transactional: Option<bool>,
+    pub marketing: Option<bool>,
+}
+
+impl ConsentChange {
+    pub fn opt_out() -> Self {
+        Self {
+            transactional: Some(false),
+            marketing: Some(false),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactional.is_none() && self.marketing.is_none()
+    }
+}
+
+pub async fn get(db: &Database, recipient_hash: &str) -> Result<Option<SmsConsent>, String> {
+    db.collection::<SmsConsent>(COLLECTION)
+        .find_one(doc! { "_id": recipient_hash })
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Whether service notifications may be sent to the number. Errors are
+/// returned so the caller can hold the message instead of risking an opt-out.
+pub async fn allows_transactional(db: &Database, recipient_hash: &str) -> Result<bool, String> {
+    get(db, recipient_hash)
+        .await
+        .map(|consent| consent.is_none_or(|consent| consent.transactional))
+}
+
+/// Applies `change`, creating the record with the defaults for anything the
+/// change leaves out. Returns the stored consent.
+pub async fn set(
+    db: &Database,
+    recipient_hash: &str,
+    change: ConsentChange,
+    source: &str,
+    actor: Option<&str>,
+) -> Result<SmsConsent, String> {
+    let defaults = SmsConsent::default_for(recipient_hash);
+    let mut set = doc! {
+        "source": source,
+        "updated_by": actor,
+        "updated_at": Utc::now().to_rfc3339(),
+    };
+    let mut set_on_insert = Document::new();
+    match change.transactional {
+        Some(value) => set.insert("transactional", value),
+        None => set_on_insert.insert("transactional", defaults.transactional),
+    };
+    match change.marketing {
+        Some(value) => set.insert("marketing", value),
+        None => set_on_insert.insert("marketing", defaults.marketing),
+    };
+
+    let mut update = doc! { "$set": set };
+    if !set_on_insert.is_empty() {
+        update.insert("$setOnInsert", set_on_insert);
+    }
+
+    db.collection::<SmsConsent>(COLLECTION)
+        .find_one_and_update(doc! { "_id": recipient_hash }, update)
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or_else(|| "SMS izni kaydedilemedi".to_string())
+}
+
+/// Records the choices ticked on an intake or montaj form. Called once the
+/// record is inserted, so a failed insert leaves no consent behind. Returns
+/// false when the write failed; the caller then skips its SMS rather than
+/// send against a choice it could not store.
+pub async fn record_form_consent(
+    db: &Database,
+    recipient_hash: Option<&str>,
+    change: ConsentChange,
+    source: &str,
+    actor: &str,
+) -> bool {
+    let (false, Some(hash)) = (change.is_empty(), recipient_hash) else {
+        return true;
+    };
+    match set(db, hash, change, source, Some(actor)).await {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::error!("SMS consent write failed ({}): {}", source, e);
+            false
+        }
+    }
+}
+
+/// Whether an incoming reply asks to stop messages: its first word is one
+/// of the opt-out keywords.
+pub fn is_opt_out(text: &str) -> bool {
+    let first_word = text
+        .split(|c: char| c.is_whitespace() || c.is_ascii_punctuation())
+        .find(|word| !word.is_empty())
+        .unwrap_or_default();
+    let word = transliterate(first_word).to_uppercase();
+    OPT_OUT_KEYWORDS.contains(&word.as_str())
+}
+
+/// One incoming SMS pulled out of a gateway callback.
+#[derive(Debug)]
+pub struct InboundSms {
+    pub phone: String,
+    pub text: String,
+}
+
+fn json_string(value: &serde_json::Value, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| match value.get(key)? {
+        serde_json::Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+/// Reads an inbound callback body: one message or an array of them, with the
+/// sender and text under the names the supported gateways use.
+pub fn parse_inbound(body: &serde_json::Value) -> Vec<InboundSms> {
+    let items = match body {
+        serde_json::Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+
+    items
+        .into_iter()
+        .filter_map(|item| {
+            Some(InboundSms {
+                phone: json_string(item, &["from", "phone", "msisdn", "gsmno", "sender", "no"])?,
+                text: json_string(item, &["message", "text", "msg", "body", "content"])?,
+            })
+        })
+        .collect()
+}
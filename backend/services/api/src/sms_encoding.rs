@@ -0,0 +1,252 @@
+use serde::Serialize;
+
+/// GSM 03.38 default alphabet (without the escape code).
+const GSM7_BASIC: &str = "@£$¥èéùìòÇ\nØø\rÅåΔ_ΦΓΛΩΠΨΣΘΞÆæßÉ !\"#¤%&'()*+,-./0123456789:;<=>?\
+¡ABCDEFGHIJKLMNOPQRSTUVWXYZÄÖÑÜ§¿abcdefghijklmnopqrstuvwxyzäöñüà";
+/// Default extension table; each character costs two septets.
+const GSM7_EXTENSION: &str = "\u{0C}^{}\\[~]|€";
+/// Turkish national language single shift table (3GPP TS 23.038). Replaces
+/// the default extension table and adds the Turkish letters missing from the
+/// default alphabet; each character costs two septets.
+const GSM7_TURKISH_SHIFT: &str = "\u{0C}^{}\\[~]|€ĞİŞçğış";
+
+/// Septets per message: plain, and per part of a concatenated message. The
+/// Turkish shift table costs a 3-octet header element in every part.
+const GSM7_SINGLE: usize = 160;
+const GSM7_MULTI: usize = 153;
+const GSM7_TURKISH_SINGLE: usize = 155;
+const GSM7_TURKISH_MULTI: usize = 149;
+/// UTF-16 code units per message.
+const UCS2_SINGLE: usize = 70;
+const UCS2_MULTI: usize = 67;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmsEncoding {
+    /// GSM-7 default alphabet.
+    Gsm7,
+    /// GSM-7 with the Turkish single shift table.
+    Gsm7Turkish,
+    Ucs2,
+}
+
+impl SmsEncoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SmsEncoding::Gsm7 => "gsm7",
+            SmsEncoding::Gsm7Turkish => "gsm7_turkish",
+            SmsEncoding::Ucs2 => "ucs2",
+        }
+    }
+
+    /// Units `c` takes, or `None` if the encoding cannot carry it.
+    fn units(self, c: char) -> Option<usize> {
+        let shift = match self {
+            SmsEncoding::Gsm7 => GSM7_EXTENSION,
+            SmsEncoding::Gsm7Turkish => GSM7_TURKISH_SHIFT,
+            SmsEncoding::Ucs2 => return Some(c.len_utf16()),
+        };
+        if GSM7_BASIC.contains(c) {
+            Some(1)
+        } else if shift.contains(c) {
+            Some(2)
+        } else {
+            None
+        }
+    }
+
+    fn limits(self) -> (usize, usize) {
+        match self {
+            SmsEncoding::Gsm7 => (GSM7_SINGLE, GSM7_MULTI),
+            SmsEncoding::Gsm7Turkish => (GSM7_TURKISH_SINGLE, GSM7_TURKISH_MULTI),
+            SmsEncoding::Ucs2 => (UCS2_SINGLE, UCS2_MULTI),
+        }
+    }
+}
+
+/// How a body goes over the air.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SmsEncodingInfo {
+    pub encoding: SmsEncoding,
+    pub characters: usize,
+    /// Septets for GSM-7, UTF-16 code units for UCS-2.
+    pub units: usize,
+    pub segments: u32,
+}
+
+/// Smallest encoding that carries `text`, with its segment count.
+pub fn analyze(text: &str) -> SmsEncodingInfo {
+    let encoding = [SmsEncoding::Gsm7, SmsEncoding::Gsm7Turkish]
+        .into_iter()
+        .find(|encoding| text.chars().all(|c| encoding.units(c).is_some()))
+        .unwrap_or(SmsEncoding::Ucs2);
+
+    let units: Vec<usize> = text.chars().filter_map(|c| encoding.units(c)).collect();
+    let total: usize = units.iter().sum();
+    let (single, multi) = encoding.limits();
+
+    let segments = if total <= single {
+        1
+    } else {
+        // A character (escape pair, surrogate pair) is never split across parts
+        let mut segments = 1;
+        let mut used = 0;
+        for unit in units {
+            if used + unit > multi {
+                segments += 1;
+                used = 0;
+            }
+            used += unit;
+        }
+        segments
+    };
+
+    SmsEncodingInfo {
+        encoding,
+        characters: text.chars().count(),
+        units: total,
+        segments,
+    }
+}
+
+/// Replaces Turkish letters outside the GSM-7 default alphabet (and common
+/// typographic punctuation) with their plain counterparts.
+pub fn transliterate(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            'ı' => out.push('i'),
+            'İ' => out.push('I'),
+            'ş' => out.push('s'),
+            'Ş' => out.push('S'),
+            'ğ' => out.push('g'),
+            'Ğ' => out.push('G'),
+            'ç' => out.push('c'),
+            'â' => out.push('a'),
+            'Â' => out.push('A'),
+            'î' => out.push('i'),
+            'Î' => out.push('I'),
+            'û' => out.push('u'),
+            'Û' => out.push('U'),
+            '‘' | '’' | '`' => out.push('\''),
+            '“' | '”' => out.push('"'),
+            '–' | '—' => out.push('-'),
+            '…' => out.push_str("..."),
+            '\u{A0}' => out.push(' '),
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+/// A body ready to hand to a gateway.
+#[derive(Debug, Clone)]
+pub struct PreparedSms {
+    pub body: String,
+    pub info: SmsEncodingInfo,
+    pub transliterated: bool,
+}
+
+/// Analyzes `text`; with `allow_transliteration`, a multi-part message is
+/// transliterated when that makes it fit in a single segment.
+pub fn prepare(text: &str, allow_transliteration: bool) -> PreparedSms {
+    let info = analyze(text);
+    if allow_transliteration && info.segments > 1 {
+        let plain = transliterate(text);
+        let plain_info = analyze(&plain);
+        if plain_info.segments == 1 {
+            return PreparedSms {
+                body: plain,
+                info: plain_info,
+                transliterated: true,
+            };
+        }
+    }
+
+    PreparedSms {
+        body: text.to_string(),
+        info,
+        transliterated: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repeat(c: char, count: usize) -> String {
+        c.to_string().repeat(count)
+    }
+
+    #[test]
+    fn plain_text_uses_the_default_alphabet() {
+        let info = analyze(&repeat('a', 160));
+        assert_eq!(info.encoding, SmsEncoding::Gsm7);
+        assert_eq!(info.segments, 1);
+
+        let info = analyze(&repeat('a', 161));
+        assert_eq!(info.segments, 2);
+        assert_eq!(analyze(&repeat('a', 306)).segments, 2);
+        assert_eq!(analyze(&repeat('a', 307)).segments, 3);
+    }
+
+    #[test]
+    fn extension_characters_cost_two_septets() {
+        let info = analyze(&format!("{}€", repeat('a', 158)));
+        assert_eq!(info.encoding, SmsEncoding::Gsm7);
+        assert_eq!(info.characters, 159);
+        assert_eq!(info.units, 160);
+        assert_eq!(info.segments, 1);
+
+        assert_eq!(analyze(&format!("{}€", repeat('a', 159))).segments, 2);
+    }
+
+    #[test]
+    fn escape_pairs_are_not_split_across_parts() {
+        // 306 septets would fill two parts exactly, but the pair can't straddle the boundary
+        let text = format!("{}€{}", repeat('a', 152), repeat('a', 152));
+        let info = analyze(&text);
+        assert_eq!(info.units, 306);
+        assert_eq!(info.segments, 3);
+    }
+
+    #[test]
+    fn turkish_letters_use_the_shift_table_limits() {
+        let info = analyze(&format!("ş{}", repeat('a', 153)));
+        assert_eq!(info.encoding, SmsEncoding::Gsm7Turkish);
+        assert_eq!(info.units, 155);
+        assert_eq!(info.segments, 1);
+
+        let info = analyze(&format!("ş{}", repeat('a', 154)));
+        assert_eq!(info.units, 156);
+        assert_eq!(info.segments, 2);
+
+        assert_eq!(analyze(&format!("ı{}", repeat('a', 296))).segments, 2);
+        assert_eq!(analyze(&format!("ı{}", repeat('a', 297))).segments, 3);
+    }
+
+    #[test]
+    fn other_characters_fall_back_to_ucs2() {
+        let info = analyze(&repeat('ж', 70));
+        assert_eq!(info.encoding, SmsEncoding::Ucs2);
+        assert_eq!(info.segments, 1);
+        assert_eq!(analyze(&repeat('ж', 71)).segments, 2);
+
+        let info = analyze("😀");
+        assert_eq!(info.characters, 1);
+        assert_eq!(info.units, 2);
+    }
+
+    #[test]
+    fn transliteration_only_applies_when_it_saves_segments() {
+        let long = format!("ş{}", repeat('a', 155));
+        let prepared = prepare(&long, true);
+        assert!(prepared.transliterated);
+        assert_eq!(prepared.info.encoding, SmsEncoding::Gsm7);
+        assert_eq!(prepared.info.segments, 1);
+
+        let short = "Cihazınız hazır";
+        assert!(!prepare(short, true).transliterated);
+        assert!(!prepare(&long, false).transliterated);
+    }
+}
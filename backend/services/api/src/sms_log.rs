@@ -0,0 +1,418 @@
+use chrono::{DateTime, Utc};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    options::IndexOptions,
+    Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{derive_key, hmac_tag, verify_hmac_tag, FieldCipher};
+use crate::phone_index::telefon_index;
+use crate::sms_consent;
+use crate::sms_encoding;
+use crate::sms_provider::{SmsProvider, SmsResponse};
+
+pub const COLLECTION: &str = "sms_messages";
+
+const DELIVERY_REPORT_CONTEXT: &[u8] = b"sis-teknik/sms-delivery-report/v1";
+
+/// What a message was sent for.
+pub const TEMPLATE_INTAKE: &str = "intake";
+pub const TEMPLATE_MONTAJ: &str = "montaj";
+pub const TEMPLATE_RESEND: &str = "resend";
+pub const TEMPLATE_STATUS: &str = "status";
+pub const TEMPLATE_PICKUP_REMINDER: &str = "pickup_reminder";
+pub const TEMPLATE_SURVEY: &str = "survey";
+pub const TEMPLATE_OTP: &str = "otp";
+
+/// Accepted by the gateway, no delivery report yet.
+pub const STATE_SENT: &str = "sent";
+/// Every gateway refused the message.
+pub const STATE_FAILED: &str = "failed";
+/// A gateway may have taken the message but never confirmed it.
+pub const STATE_UNCERTAIN: &str = "uncertain";
+pub const STATE_DELIVERED: &str = "delivered";
+/// Accepted by the gateway but reported as not delivered to the handset.
+pub const STATE_UNDELIVERED: &str = "undelivered";
+/// Not sent: the number has not consented to this kind of message.
+pub const STATE_SUPPRESSED: &str = "suppressed";
+
+/// Why `send` did not get a message out.
+#[derive(Debug, Clone)]
+pub enum SendError {
+    /// Skipped for lack of consent; retrying will not help.
+    Suppressed,
+    /// Every gateway refused the message, or consent could not be checked.
+    /// Worth retrying.
+    Failed(String),
+    /// A gateway may have sent it (timeout, unreadable answer). Not retried
+    /// automatically: a retry could deliver it twice.
+    Uncertain(String),
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Suppressed => write!(f, "Numaranın SMS izni yok"),
+            SendError::Failed(e) | SendError::Uncertain(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// One outgoing SMS in `sms_messages`. The number itself is not stored, only
+/// its blind index (same as `musteri_kabul.telefon_index`).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmsMessage {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub recipient_hash: Option<String>,
+    pub template: String,
+    pub body: String,
+    pub provider: Option<String>,
+    pub message_id: Option<String>,
+    pub state: String,
+    pub error: Option<String>,
+    /// `sms_encoding::SmsEncoding` the message went out in.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    #[serde(default)]
+    pub segments: Option<i32>,
+    /// Price at the accepting gateway; `None` when nothing was sent.
+    #[serde(default)]
+    pub cost: Option<f64>,
+    /// Record the message is about (`musteri_kabul` or `montaj_kayitlari`).
+    pub record_collection: Option<String>,
+    pub record_id: Option<ObjectId>,
+    /// Raw status of the last delivery report.
+    #[serde(default)]
+    pub delivery_status: Option<String>,
+    #[serde(default)]
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Log entry as returned by the admin endpoint.
+#[derive(Debug, Serialize)]
+pub struct SmsMessageResponse {
+    pub id: String,
+    pub recipient_hash: Option<String>,
+    pub template: String,
+    pub body: String,
+    pub provider: Option<String>,
+    pub message_id: Option<String>,
+    pub state: String,
+    pub error: Option<String>,
+    pub encoding: Option<String>,
+    pub segments: Option<i32>,
+    pub cost: Option<f64>,
+    pub record_collection: Option<String>,
+    pub record_id: Option<String>,
+    pub delivery_status: Option<String>,
+    pub delivered_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<SmsMessage> for SmsMessageResponse {
+    fn from(message: SmsMessage) -> Self {
+        Self {
+            id: message.id.map(|id| id.to_hex()).unwrap_or_default(),
+            recipient_hash: message.recipient_hash,
+            template: message.template,
+            body: message.body,
+            provider: message.provider,
+            message_id: message.message_id,
+            state: message.state,
+            error: message.error,
+            encoding: message.encoding,
+            segments: message.segments,
+            cost: message.cost,
+            record_collection: message.record_collection,
+            record_id: message.record_id.map(|id| id.to_hex()),
+            delivery_status: message.delivery_status,
+            delivered_at: message.delivered_at.map(|at| at.to_rfc3339()),
+            created_at: message.created_at.to_rfc3339(),
+            updated_at: message.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+/// An SMS about to be sent through `send`.
+pub struct OutgoingSms<'a> {
+    phone: &'a str,
+    body: &'a str,
+    template: &'static str,
+    record: Option<(&'static str, ObjectId)>,
+    logged_body: Option<String>,
+    /// Whether the recipient's SMS consent is checked.
+    check_consent: bool,
+}
+
+impl<'a> OutgoingSms<'a> {
+    pub fn new(template: &'static str, phone: &'a str, body: &'a str) -> Self {
+        Self {
+            phone,
+            body,
+            template,
+            record: None,
+            logged_body: None,
+            check_consent: true,
+        }
+    }
+
+    /// For the shop's own numbers (e.g. OTPs to staff), which need no consent.
+    pub fn internal(mut self) -> Self {
+        self.check_consent = false;
+        self
+    }
+
+    pub fn record(mut self, collection: &'static str, id: ObjectId) -> Self {
+        self.record = Some((collection, id));
+        self
+    }
+
+    /// Text stored in the log instead of the real body (e.g. without an OTP code).
+    pub fn logged_as(mut self, body: impl Into<String>) -> Self {
+        self.logged_body = Some(body.into());
+        self
+    }
+}
+
+pub async fn ensure_indexes(db: &Database) -> Result<(), String> {
+    let index = |keys: Document, name: &str| {
+        IndexModel::builder()
+            .keys(keys)
+            .options(IndexOptions::builder().name(name.to_string()).build())
+            .build()
+    };
+
+    db.collection::<Document>(COLLECTION)
+        .create_indexes(vec![
+            index(doc! { "created_at": -1, "_id": -1 }, "created_at_-1__id_-1"),
+            index(doc! { "record_id": 1, "created_at": -1 }, "record_id_1_created_at_-1"),
+            index(doc! { "recipient_hash": 1, "created_at": -1 }, "recipient_hash_1_created_at_-1"),
+            index(doc! { "provider": 1, "message_id": 1 }, "provider_1_message_id_1"),
+        ])
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("SMS log index error: {}", e))
+}
+
+/// Sends through `sms` and records the outcome in `sms_messages`. Numbers
+/// that opted out are skipped and logged as suppressed. A failed log write
+/// is only logged: the message has gone out either way.
+pub async fn send(
+    db: &Database,
+    cipher: &FieldCipher,
+    sms: &dyn SmsProvider,
+    outgoing: OutgoingSms<'_>,
+) -> Result<SmsResponse, SendError> {
+    let recipient_hash = telefon_index(cipher, outgoing.phone);
+
+    if let (true, Some(hash)) = (outgoing.check_consent, &recipient_hash) {
+        let allowed = sms_consent::allows_transactional(db, hash).await.map_err(|e| {
+            tracing::error!("SMS consent lookup failed: {}", e);
+            SendError::Failed(format!("SMS izni kontrol edilemedi: {}", e))
+        })?;
+        if !allowed {
+            println!("🔕 SMS gönderilmedi, izin yok ({})", outgoing.template);
+            let info = sms_encoding::analyze(outgoing.body);
+            let now = Utc::now();
+            let message = SmsMessage {
+                id: None,
+                recipient_hash,
+                template: outgoing.template.to_string(),
+                body: outgoing.logged_body.unwrap_or_else(|| outgoing.body.to_string()),
+                provider: None,
+                message_id: None,
+                state: STATE_SUPPRESSED.to_string(),
+                error: Some(SendError::Suppressed.to_string()),
+                encoding: Some(info.encoding.as_str().to_string()),
+                segments: Some(info.segments as i32),
+                cost: None,
+                record_collection: outgoing.record.map(|(collection, _)| collection.to_string()),
+                record_id: outgoing.record.map(|(_, id)| id),
+                delivery_status: None,
+                delivered_at: None,
+                created_at: now,
+                updated_at: now,
+            };
+            insert_log(db, &message).await;
+            return Err(SendError::Suppressed);
+        }
+    }
+
+    let result = sms.send(outgoing.phone, outgoing.body).await;
+
+    let now = Utc::now();
+    let (provider, message_id, state, error) = match &result {
+        Ok(response) => (
+            Some(response.provider.to_string()),
+            response.message_id.clone(),
+            STATE_SENT,
+            None,
+        ),
+        Err(e) if e.is_uncertain() => (None, None, STATE_UNCERTAIN, Some(e.to_string())),
+        Err(e) => (None, None, STATE_FAILED, Some(e.to_string())),
+    };
+    let (encoding, segments, cost) = match &result {
+        Ok(response) => (response.encoding, response.segments, Some(response.cost)),
+        Err(_) => {
+            let info = sms_encoding::analyze(outgoing.body);
+            (info.encoding, info.segments, None)
+        }
+    };
+
+    let mut body = outgoing.logged_body.unwrap_or_else(|| outgoing.body.to_string());
+    if result.as_ref().is_ok_and(|response| response.transliterated) {
+        body = sms_encoding::transliterate(&body);
+    }
+
+    let message = SmsMessage {
+        id: None,
+        recipient_hash,
+        template: outgoing.template.to_string(),
+        body,
+        provider,
+        message_id,
+        state: state.to_string(),
+        error,
+        encoding: Some(encoding.as_str().to_string()),
+        segments: Some(segments as i32),
+        cost,
+        record_collection: outgoing.record.map(|(collection, _)| collection.to_string()),
+        record_id: outgoing.record.map(|(_, id)| id),
+        delivery_status: None,
+        delivered_at: None,
+        created_at: now,
+        updated_at: now,
+    };
+
+    insert_log(db, &message).await;
+
+    result.map_err(|e| {
+        if e.is_uncertain() {
+            SendError::Uncertain(e.to_string())
+        } else {
+            SendError::Failed(e.to_string())
+        }
+    })
+}
+
+async fn insert_log(db: &Database, message: &SmsMessage) {
+    if let Err(e) = db.collection::<SmsMessage>(COLLECTION).insert_one(message).await {
+        tracing::error!("SMS log write failed ({}): {}", message.template, e);
+    }
+}
+
+/// Signs the webhook URLs given to the gateways (delivery reports and
+/// inbound replies), one token per provider and hook, so callbacks cannot be
+/// forged without the server secret.
+#[derive(Clone)]
+pub struct DeliveryReports {
+    key: Vec<u8>,
+}
+
+impl DeliveryReports {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            key: derive_key(secret.as_bytes(), DELIVERY_REPORT_CONTEXT),
+        }
+    }
+
+    pub fn path(&self, provider: &str) -> String {
+        format!(
+            "/api/sms/delivery-report/{}?token={}",
+            provider,
+            hmac_tag(&self.key, provider.as_bytes())
+        )
+    }
+
+    pub fn verify(&self, provider: &str, token: &str) -> bool {
+        verify_hmac_tag(&self.key, provider.as_bytes(), token)
+    }
+
+    pub fn inbound_path(&self, provider: &str) -> String {
+        format!(
+            "/api/sms/inbound/{}?token={}",
+            provider,
+            hmac_tag(&self.key, format!("inbound:{}", provider).as_bytes())
+        )
+    }
+
+    pub fn verify_inbound(&self, provider: &str, token: &str) -> bool {
+        verify_hmac_tag(&self.key, format!("inbound:{}", provider).as_bytes(), token)
+    }
+}
+
+/// One status update pulled out of a gateway callback.
+#[derive(Debug)]
+pub struct DeliveryReport {
+    pub message_id: String,
+    pub status: String,
+}
+
+fn json_string(value: &serde_json::Value, keys: &[&str]) -> Option<String> {
+    keys.iter().find_map(|key| match value.get(key)? {
+        serde_json::Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+/// Reads a callback body: one report object or an array of them, with the
+/// id and status under the names the supported gateways use.
+pub fn parse_reports(body: &serde_json::Value) -> Vec<DeliveryReport> {
+    let items = match body {
+        serde_json::Value::Array(items) => items.iter().collect(),
+        other => vec![other],
+    };
+
+    items
+        .into_iter()
+        .filter_map(|item| {
+            Some(DeliveryReport {
+                message_id: json_string(item, &["message_id", "messageId", "jobid", "jobId", "msgid", "id"])?,
+                status: json_string(item, &["status", "state", "durum"])?,
+            })
+        })
+        .collect()
+}
+
+/// Final state for a gateway status; `None` while it is still pending.
+/// Numeric codes follow Netgsm's report codes.
+pub fn delivery_state(status: &str) -> Option<&'static str> {
+    match status.trim().to_lowercase().as_str() {
+        "1" | "delivered" | "delivrd" | "success" | "iletildi" => Some(STATE_DELIVERED),
+        "2" | "3" | "4" | "11" | "12" | "13" | "undelivered" | "undeliv" | "failed" | "expired" | "rejected"
+        | "error" | "iletilemedi" => Some(STATE_UNDELIVERED),
+        _ => None,
+    }
+}
+
+/// Applies a report to the logged message. Returns whether a message matched.
+pub async fn apply_report(db: &Database, provider: &str, report: &DeliveryReport) -> Result<bool, String> {
+    let now = Utc::now();
+    let mut set = doc! {
+        "delivery_status": &report.status,
+        "updated_at": now.to_rfc3339(),
+    };
+    if let Some(state) = delivery_state(&report.status) {
+        set.insert("state", state);
+        if state == STATE_DELIVERED {
+            set.insert("delivered_at", now.to_rfc3339());
+        }
+    }
+
+    let result = db
+        .collection::<Document>(COLLECTION)
+        .update_one(
+            doc! { "provider": provider, "message_id": &report.message_id },
+            doc! { "$set": set },
+        )
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok(result.matched_count > 0)
+}
@@ -0,0 +1,953 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{NetgsmConfig, SmsConfig};
+use crate::sms::normalize_phone;
+use crate::sms_encoding::{self, SmsEncoding};
+
+#[derive(Debug, Serialize)]
+struct VoiceTelekomRequest {
+    #[serde(rename = "type")]
+    msg_type: i32,
+    #[serde(rename = "sendingType")]
+    sending_type: i32,
+    title: String,
+    content: String,
+    number: String,
+    encoding: i32,
+    sender: String,
+    #[serde(rename = "periodicSettings")]
+    periodic_settings: Option<serde_json::Value>,
+    #[serde(rename = "sendingDate")]
+    sending_date: Option<serde_json::Value>,
+    validity: i32,
+    #[serde(rename = "pushSettings")]
+    push_settings: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VoiceTelekomError {
+    status: i32,
+    code: String,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VoiceTelekomResponse {
+    err: Option<VoiceTelekomError>,
+    status: Option<String>,
+    message: Option<String>,
+    message_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct NetgsmMessage<'a> {
+    msg: &'a str,
+    no: String,
+}
+
+#[derive(Debug, Serialize)]
+struct NetgsmRequest<'a> {
+    msgheader: &'a str,
+    messages: Vec<NetgsmMessage<'a>>,
+    /// "TR" allows Turkish characters in the text; plain GSM-7 omits it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NetgsmResponse {
+    code: String,
+    jobid: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SmsResponse {
+    /// Gateway that accepted the message.
+    pub provider: &'static str,
+    pub status: String,
+    pub message: String,
+    pub message_id: Option<String>,
+    pub encoding: SmsEncoding,
+    pub segments: u32,
+    /// `segments` at the accepting gateway's price per segment.
+    pub cost: f64,
+    /// Sent transliterated to fit a single segment, see `sms.transliterate`.
+    pub transliterated: bool,
+}
+
+/// Why a gateway did not take a message. Decides whether the failover chain
+/// may hand the message to the next gateway.
+#[derive(Debug, Clone)]
+pub enum ProviderError {
+    /// The request never reached the gateway (DNS, connect, TLS).
+    Unreachable(String),
+    /// The gateway answered and refused the message.
+    Rejected(String),
+    /// The outcome is unknown (timeout, unreadable response); the message may
+    /// have gone out, so it is not resent through another gateway.
+    Uncertain(String),
+}
+
+impl ProviderError {
+    /// Classifies a failed HTTP request: only connection failures are known
+    /// not to have reached the gateway.
+    fn from_request(context: &str, e: reqwest::Error) -> Self {
+        let message = format!("{}: {}", context, e);
+        if e.is_connect() {
+            ProviderError::Unreachable(message)
+        } else {
+            ProviderError::Uncertain(message)
+        }
+    }
+
+    /// Classifies a non-2xx answer: a 5xx (or a proxy's error page) means the
+    /// gateway is down, a 4xx that it refused the request. Neither took the message.
+    fn from_status(context: &str, status: reqwest::StatusCode, body: &str) -> Self {
+        let excerpt: String = body.chars().take(200).collect();
+        let message = format!("{} HTTP {}: {}", context, status, excerpt);
+        if status.is_server_error() {
+            ProviderError::Unreachable(message)
+        } else {
+            ProviderError::Rejected(message)
+        }
+    }
+
+    pub fn is_uncertain(&self) -> bool {
+        matches!(self, ProviderError::Uncertain(_))
+    }
+
+    fn can_fail_over(&self) -> bool {
+        matches!(self, ProviderError::Unreachable(_) | ProviderError::Rejected(_))
+    }
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProviderError::Unreachable(e) | ProviderError::Rejected(e) | ProviderError::Uncertain(e) => {
+                write!(f, "{}", e)
+            }
+        }
+    }
+}
+
+/// A message recorded by the mock provider.
+#[derive(Debug, Clone, Serialize)]
+pub struct SentSms {
+    pub phone: String,
+    pub message: String,
+    pub message_id: String,
+    pub sent_at: String,
+}
+
+/// Something that can deliver an SMS. Handlers and the queue worker only see
+/// this trait; which gateways sit behind it comes from `sms.providers`.
+#[async_trait]
+pub trait SmsProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn send(&self, phone: &str, message: &str) -> Result<SmsResponse, ProviderError>;
+
+    /// Messages sent so far, for providers that keep them (the mock).
+    fn outbox(&self) -> Option<Vec<SentSms>> {
+        None
+    }
+
+    /// Per-gateway health, for providers that track it (the failover chain).
+    fn health(&self) -> Vec<ProviderHealth> {
+        Vec::new()
+    }
+}
+
+/// Builds the configured gateways, in priority order, behind a failover chain.
+pub fn build_provider(config: &SmsConfig) -> Result<Arc<dyn SmsProvider>, String> {
+    let mut providers: Vec<Arc<dyn SmsProvider>> = Vec::new();
+    for name in &config.providers {
+        let provider: Arc<dyn SmsProvider> = match name.as_str() {
+            "mock" => Arc::new(MockSmsProvider::new(config.mock_file.clone())),
+            "voicetelekom" => Arc::new(VoiceTelekomProvider::new(config)?),
+            "netgsm" => Arc::new(NetgsmProvider::new(&config.netgsm)?),
+            other => return Err(format!("Bilinmeyen SMS sağlayıcısı: {}", other)),
+        };
+        providers.push(provider);
+    }
+
+    Ok(Arc::new(FailoverSmsProvider::new(
+        providers,
+        config.failure_threshold,
+        chrono::Duration::seconds(config.circuit_cooldown_seconds as i64),
+        config.transliterate,
+    )))
+}
+
+fn http_client() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().timeout(Duration::from_secs(30))
+}
+
+/// VoiceTelekom HTTP gateway. The client is built once, with certificate
+/// verification on; `sms.ca_cert` adds a trusted root for a private CA.
+pub struct VoiceTelekomProvider {
+    client: reqwest::Client,
+    username: String,
+    password: String,
+    sender: String,
+    api_url: String,
+    segment_cost: f64,
+}
+
+impl VoiceTelekomProvider {
+    pub fn new(config: &SmsConfig) -> Result<Self, String> {
+        let mut builder = http_client();
+
+        if let Some(path) = &config.ca_cert {
+            let pem = std::fs::read(path).map_err(|e| format!("SMS CA sertifikası okunamadı ({}): {}", path, e))?;
+            let cert = reqwest::Certificate::from_pem(&pem)
+                .map_err(|e| format!("SMS CA sertifikası geçersiz ({}): {}", path, e))?;
+            builder = builder.add_root_certificate(cert);
+        }
+
+        let client = builder
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+        Ok(Self {
+            client,
+            username: config.username.clone(),
+            password: config.password.clone(),
+            sender: config.sender.clone(),
+            api_url: config.api_url.clone(),
+            segment_cost: config.segment_cost,
+        })
+    }
+
+    /// Gateway `encoding` codes: 0 default alphabet, 1 Turkish, 2 Unicode.
+    fn encoding_code(encoding: SmsEncoding) -> i32 {
+        match encoding {
+            SmsEncoding::Gsm7 => 0,
+            SmsEncoding::Gsm7Turkish => 1,
+            SmsEncoding::Ucs2 => 2,
+        }
+    }
+}
+
+#[async_trait]
+impl SmsProvider for VoiceTelekomProvider {
+    fn name(&self) -> &'static str {
+        "voicetelekom"
+    }
+
+    async fn send(&self, phone: &str, message: &str) -> Result<SmsResponse, ProviderError> {
+        let normalized_phone = normalize_phone(phone);
+        let info = sms_encoding::analyze(message);
+        println!(
+            "📱 SMS API Request - Phone: {} → {} ({}, {} segment)",
+            phone,
+            normalized_phone,
+            info.encoding.as_str(),
+            info.segments
+        );
+
+        let payload = VoiceTelekomRequest {
+            msg_type: 1,
+            sending_type: 0,
+            title: "SIS Teknik SMS".to_string(),
+            content: message.to_string(),
+            number: normalized_phone,
+            encoding: Self::encoding_code(info.encoding),
+            sender: self.sender.clone(),
+            periodic_settings: None,
+            sending_date: None,
+            validity: 60,
+            push_settings: None,
+        };
+
+        let response = self
+            .client
+            .post(&self.api_url)
+            .basic_auth(&self.username, Some(&self.password))
+            .json(&payload)
+            .header("User-Agent", "SIS-Teknik/1.0")
+            .send()
+            .await
+            .map_err(|e| {
+                let error = ProviderError::from_request("SMS API request error", e);
+                println!("❌ {}", error);
+                error
+            })?;
+
+        let http_status = response.status();
+        println!("📱 SMS API Response Status: {}", http_status);
+        let response_text = response.text().await.unwrap_or_default();
+
+        if !http_status.is_success() {
+            let error = ProviderError::from_status("SMS API", http_status, &response_text);
+            println!("❌ {}", error);
+            return Err(error);
+        }
+
+        let api_response = serde_json::from_str::<VoiceTelekomResponse>(&response_text).map_err(|e| {
+            let error_msg = format!("SMS response parse error: {} | Body: {}", e, response_text);
+            println!("❌ {}", error_msg);
+            ProviderError::Uncertain(error_msg)
+        })?;
+
+        if let Some(err) = api_response.err {
+            let error_msg = format!("SMS API Error [{}]: {} - {}", err.status, err.code, err.message);
+            println!("❌ {}", error_msg);
+            return Err(ProviderError::Rejected(error_msg));
+        }
+
+        Ok(SmsResponse {
+            provider: self.name(),
+            status: api_response.status.unwrap_or_else(|| "success".to_string()),
+            message: api_response.message.unwrap_or_else(|| "SMS sent".to_string()),
+            message_id: api_response.message_id,
+            encoding: info.encoding,
+            segments: info.segments,
+            cost: info.segments as f64 * self.segment_cost,
+            transliterated: false,
+        })
+    }
+}
+
+/// Netgsm REST v2 gateway (`/sms/rest/v2/send`), basic auth with the
+/// subscriber code. Response code "00" means the message was queued.
+pub struct NetgsmProvider {
+    client: reqwest::Client,
+    usercode: String,
+    password: String,
+    header: String,
+    api_url: String,
+    segment_cost: f64,
+}
+
+impl NetgsmProvider {
+    pub fn new(config: &NetgsmConfig) -> Result<Self, String> {
+        let client = http_client()
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+        Ok(Self {
+            client,
+            usercode: config.usercode.clone(),
+            password: config.password.clone(),
+            header: config.header.clone(),
+            api_url: config.api_url.clone(),
+            segment_cost: config.segment_cost,
+        })
+    }
+
+    fn error_description(code: &str) -> &'static str {
+        match code {
+            "20" => "Mesaj metni hatalı veya çok uzun",
+            "30" => "Geçersiz kullanıcı adı/şifre veya API erişim izni yok",
+            "40" => "Mesaj başlığı (gönderici adı) sistemde tanımlı değil",
+            "50" => "İYS kontrollü gönderim yapılamıyor",
+            "70" => "Hatalı sorgulama, parametreler eksik veya hatalı",
+            "80" => "Gönderim sınırı aşıldı",
+            "85" => "Mükerrer gönderim sınırı aşıldı",
+            _ => "Bilinmeyen hata",
+        }
+    }
+}
+
+#[async_trait]
+impl SmsProvider for NetgsmProvider {
+    fn name(&self) -> &'static str {
+        "netgsm"
+    }
+
+    async fn send(&self, phone: &str, message: &str) -> Result<SmsResponse, ProviderError> {
+        let info = sms_encoding::analyze(message);
+        let payload = NetgsmRequest {
+            msgheader: &self.header,
+            messages: vec![NetgsmMessage {
+                msg: message,
+                no: normalize_phone(phone),
+            }],
+            encoding: (info.encoding != SmsEncoding::Gsm7).then_some("TR"),
+        };
+
+        let response = self
+            .client
+            .post(&self.api_url)
+            .basic_auth(&self.usercode, Some(&self.password))
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| ProviderError::from_request("Netgsm request error", e))?;
+
+        let http_status = response.status();
+        let response_text = response.text().await.unwrap_or_default();
+        println!("📱 Netgsm Response [{}]: {}", http_status, response_text);
+
+        if !http_status.is_success() {
+            return Err(ProviderError::from_status("Netgsm", http_status, &response_text));
+        }
+
+        let api_response = serde_json::from_str::<NetgsmResponse>(&response_text)
+            .map_err(|e| {
+                ProviderError::Uncertain(format!("Netgsm response parse error: {} | Body: {}", e, response_text))
+            })?;
+
+        if api_response.code != "00" {
+            return Err(ProviderError::Rejected(format!(
+                "Netgsm Error [{}]: {}",
+                api_response.code,
+                Self::error_description(&api_response.code)
+            )));
+        }
+
+        Ok(SmsResponse {
+            provider: self.name(),
+            status: "success".to_string(),
+            message: api_response.description.unwrap_or_else(|| "queued".to_string()),
+            message_id: api_response.jobid,
+            encoding: info.encoding,
+            segments: info.segments,
+            cost: info.segments as f64 * self.segment_cost,
+            transliterated: false,
+        })
+    }
+}
+
+/// Sends nothing: keeps every message in memory and, if `sms.mock_file` is
+/// set, appends it to that file as a JSON line. For development and tests.
+pub struct MockSmsProvider {
+    sent: Arc<Mutex<Vec<SentSms>>>,
+    file: Option<String>,
+}
+
+impl MockSmsProvider {
+    pub fn new(file: Option<String>) -> Self {
+        Self {
+            sent: Arc::new(Mutex::new(Vec::new())),
+            file,
+        }
+    }
+
+    fn append_to_file(path: &str, entry: &SentSms) -> Result<(), String> {
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl SmsProvider for MockSmsProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn send(&self, phone: &str, message: &str) -> Result<SmsResponse, ProviderError> {
+        let entry = {
+            let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+            let entry = SentSms {
+                phone: normalize_phone(phone),
+                message: message.to_string(),
+                message_id: format!("mock-{}", sent.len() + 1),
+                sent_at: Utc::now().to_rfc3339(),
+            };
+            sent.push(entry.clone());
+            entry
+        };
+
+        // The text may carry link tokens; it is only kept in the outbox and `mock_file`
+        let info = sms_encoding::analyze(message);
+        println!(
+            "📱 Mock SMS → {} ({}, {} segment, {})",
+            entry.phone,
+            info.encoding.as_str(),
+            info.segments,
+            entry.message_id
+        );
+        if let Some(path) = &self.file {
+            if let Err(e) = Self::append_to_file(path, &entry) {
+                tracing::error!("Mock SMS file write error ({}): {}", path, e);
+            }
+        }
+
+        Ok(SmsResponse {
+            provider: self.name(),
+            status: "success".to_string(),
+            message: "SMS recorded by mock provider".to_string(),
+            message_id: Some(entry.message_id),
+            encoding: info.encoding,
+            segments: info.segments,
+            cost: 0.0,
+            transliterated: false,
+        })
+    }
+
+    fn outbox(&self) -> Option<Vec<SentSms>> {
+        Some(self.sent.lock().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+}
+
+/// Health of one gateway in the failover chain.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealth {
+    pub provider: &'static str,
+    /// "closed" (in use), "open" (skipped until `open_until`) or
+    /// "half_open" (cooled down, next send is a trial).
+    pub circuit: &'static str,
+    pub consecutive_failures: u32,
+    pub sent: u64,
+    pub failed: u64,
+    pub last_error: Option<String>,
+    pub last_success_at: Option<String>,
+    pub last_failure_at: Option<String>,
+    pub open_until: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    sent: u64,
+    failed: u64,
+    last_error: Option<String>,
+    last_success_at: Option<DateTime<Utc>>,
+    last_failure_at: Option<DateTime<Utc>>,
+    open_until: Option<DateTime<Utc>>,
+    /// When the running half-open trial started, if one is running.
+    trial_started_at: Option<DateTime<Utc>>,
+}
+
+impl CircuitState {
+    fn is_open(&self, now: DateTime<Utc>) -> bool {
+        self.open_until.is_some_and(|until| until > now)
+    }
+
+    /// Cooldown over but the last send still failed: only a trial may go.
+    fn is_half_open(&self, now: DateTime<Utc>) -> bool {
+        self.open_until.is_some_and(|until| until <= now)
+    }
+
+    fn label(&self, now: DateTime<Utc>) -> &'static str {
+        if self.is_open(now) {
+            "open"
+        } else if self.is_half_open(now) {
+            "half_open"
+        } else {
+            "closed"
+        }
+    }
+}
+
+/// Tries the gateways in priority order until one accepts the message.
+///
+/// A gateway that fails `failure_threshold` times in a row is skipped for
+/// `cooldown`; after that a single trial send is let through (concurrent
+/// sends keep skipping it), which either closes the circuit again or reopens
+/// it. When no gateway can be tried all of them are tried anyway rather than
+/// dropping the message.
+///
+/// Only failures known not to have sent the message (`Unreachable`,
+/// `Rejected`, including non-2xx answers) move on to the next gateway. After
+/// an `Uncertain` one the message is not resent elsewhere, and callers do not
+/// retry it either (see `sms_log::SendError::Uncertain`).
+///
+/// With `transliterate`, a multi-part message that fits in a single segment
+/// once transliterated goes out transliterated.
+pub struct FailoverSmsProvider {
+    providers: Vec<Arc<dyn SmsProvider>>,
+    circuits: Vec<Mutex<CircuitState>>,
+    failure_threshold: u32,
+    cooldown: chrono::Duration,
+    transliterate: bool,
+}
+
+impl FailoverSmsProvider {
+    pub fn new(
+        providers: Vec<Arc<dyn SmsProvider>>,
+        failure_threshold: u32,
+        cooldown: chrono::Duration,
+        transliterate: bool,
+    ) -> Self {
+        let circuits = providers.iter().map(|_| Mutex::new(CircuitState::default())).collect();
+        Self {
+            providers,
+            circuits,
+            failure_threshold,
+            cooldown,
+            transliterate,
+        }
+    }
+
+    fn circuit(&self, index: usize) -> MutexGuard<'_, CircuitState> {
+        self.circuits[index].lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether gateway `index` may be tried now; claims the trial of a
+    /// half-open circuit. A trial older than `cooldown` is treated as lost.
+    fn admit(&self, index: usize, now: DateTime<Utc>) -> bool {
+        let mut circuit = self.circuit(index);
+        if circuit.is_open(now) {
+            return false;
+        }
+        if circuit.is_half_open(now) {
+            if circuit.trial_started_at.is_some_and(|started| started + self.cooldown > now) {
+                return false;
+            }
+            circuit.trial_started_at = Some(now);
+        }
+        true
+    }
+
+    fn record_success(&self, index: usize) {
+        let mut circuit = self.circuit(index);
+        circuit.consecutive_failures = 0;
+        circuit.open_until = None;
+        circuit.trial_started_at = None;
+        circuit.sent += 1;
+        circuit.last_success_at = Some(Utc::now());
+    }
+
+    fn record_failure(&self, index: usize, error: &ProviderError) {
+        let now = Utc::now();
+        let mut circuit = self.circuit(index);
+        circuit.consecutive_failures += 1;
+        circuit.failed += 1;
+        circuit.last_error = Some(error.to_string());
+        circuit.last_failure_at = Some(now);
+        circuit.trial_started_at = None;
+
+        if circuit.consecutive_failures >= self.failure_threshold {
+            circuit.open_until = Some(now + self.cooldown);
+            println!(
+                "⚠️ SMS sağlayıcısı devre dışı: {} ({} ardışık hata)",
+                self.providers[index].name(),
+                circuit.consecutive_failures
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl SmsProvider for FailoverSmsProvider {
+    fn name(&self) -> &'static str {
+        "failover"
+    }
+
+    async fn send(&self, phone: &str, message: &str) -> Result<SmsResponse, ProviderError> {
+        let prepared = sms_encoding::prepare(message, self.transliterate);
+        let mut errors = Vec::new();
+        let mut attempted = false;
+
+        // Second pass only when every circuit was open on the first
+        for ignore_circuits in [false, true] {
+            if ignore_circuits && attempted {
+                break;
+            }
+            for index in 0..self.providers.len() {
+                if !ignore_circuits && !self.admit(index, Utc::now()) {
+                    continue;
+                }
+                attempted = true;
+
+                let provider = &self.providers[index];
+                match provider.send(phone, &prepared.body).await {
+                    Ok(response) => {
+                        self.record_success(index);
+                        return Ok(SmsResponse {
+                            transliterated: prepared.transliterated,
+                            ..response
+                        });
+                    }
+                    Err(e) => {
+                        tracing::error!("SMS provider {} failed: {}", provider.name(), e);
+                        self.record_failure(index, &e);
+                        errors.push(format!("{}: {}", provider.name(), e));
+                        if !e.can_fail_over() {
+                            return Err(ProviderError::Uncertain(errors.join(" | ")));
+                        }
+                    }
+                }
+            }
+        }
+
+        Err(ProviderError::Rejected(errors.join(" | ")))
+    }
+
+    fn outbox(&self) -> Option<Vec<SentSms>> {
+        self.providers.iter().find_map(|provider| provider.outbox())
+    }
+
+    fn health(&self) -> Vec<ProviderHealth> {
+        let now = Utc::now();
+        self.providers
+            .iter()
+            .enumerate()
+            .map(|(index, provider)| {
+                let circuit = self.circuit(index);
+                ProviderHealth {
+                    provider: provider.name(),
+                    circuit: circuit.label(now),
+                    consecutive_failures: circuit.consecutive_failures,
+                    sent: circuit.sent,
+                    failed: circuit.failed,
+                    last_error: circuit.last_error.clone(),
+                    last_success_at: circuit.last_success_at.map(|at| at.to_rfc3339()),
+                    last_failure_at: circuit.last_failure_at.map(|at| at.to_rfc3339()),
+                    open_until: circuit.open_until.filter(|until| *until > now).map(|at| at.to_rfc3339()),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Gateway that fails with the queued errors in turn, then sends like the mock.
+    struct ScriptedProvider {
+        name: &'static str,
+        failures: Mutex<VecDeque<ProviderError>>,
+        calls: Mutex<u32>,
+        mock: MockSmsProvider,
+    }
+
+    impl ScriptedProvider {
+        fn new(name: &'static str, failures: Vec<ProviderError>) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                failures: Mutex::new(failures.into()),
+                calls: Mutex::new(0),
+                mock: MockSmsProvider::new(None),
+            })
+        }
+
+        fn calls(&self) -> u32 {
+            *self.calls.lock().unwrap()
+        }
+
+        fn delivered(&self) -> usize {
+            self.mock.outbox().unwrap().len()
+        }
+    }
+
+    #[async_trait]
+    impl SmsProvider for ScriptedProvider {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn send(&self, phone: &str, message: &str) -> Result<SmsResponse, ProviderError> {
+            *self.calls.lock().unwrap() += 1;
+            if let Some(error) = self.failures.lock().unwrap().pop_front() {
+                return Err(error);
+            }
+            let response = self.mock.send(phone, message).await?;
+            Ok(SmsResponse {
+                provider: self.name,
+                ..response
+            })
+        }
+    }
+
+    fn chain(providers: &[Arc<ScriptedProvider>], failure_threshold: u32) -> FailoverSmsProvider {
+        FailoverSmsProvider::new(
+            providers.iter().map(|p| p.clone() as Arc<dyn SmsProvider>).collect(),
+            failure_threshold,
+            chrono::Duration::seconds(60),
+            false,
+        )
+    }
+
+    fn unreachable() -> ProviderError {
+        ProviderError::Unreachable("connect refused".to_string())
+    }
+
+    #[tokio::test]
+    async fn fails_over_in_priority_order() {
+        let primary = ScriptedProvider::new("primary", vec![unreachable()]);
+        let backup = ScriptedProvider::new("backup", vec![ProviderError::Rejected("kota".to_string())]);
+        let last = ScriptedProvider::new("last", vec![]);
+        let failover = chain(&[primary.clone(), backup.clone(), last.clone()], 3);
+
+        let response = failover.send("05321234567", "Merhaba").await.unwrap();
+        assert_eq!(response.provider, "last");
+        assert_eq!((primary.calls(), backup.calls(), last.calls()), (1, 1, 1));
+
+        // Next message starts at the top again
+        let response = failover.send("05321234567", "Merhaba").await.unwrap();
+        assert_eq!(response.provider, "primary");
+        assert_eq!(last.calls(), 1);
+
+        let health = failover.health();
+        assert_eq!(health[0].consecutive_failures, 0);
+        assert_eq!(health[1].consecutive_failures, 1);
+        assert!(health.iter().all(|h| h.circuit == "closed"));
+    }
+
+    #[tokio::test]
+    async fn uncertain_errors_do_not_fail_over() {
+        let primary = ScriptedProvider::new("primary", vec![ProviderError::Uncertain("timeout".to_string())]);
+        let backup = ScriptedProvider::new("backup", vec![]);
+        let failover = chain(&[primary.clone(), backup.clone()], 3);
+
+        let error = failover.send("05321234567", "Merhaba").await.unwrap_err();
+        assert!(error.is_uncertain());
+        assert_eq!(backup.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn all_gateways_failing_is_rejected() {
+        let primary = ScriptedProvider::new("primary", vec![unreachable()]);
+        let backup = ScriptedProvider::new("backup", vec![unreachable()]);
+        let failover = chain(&[primary, backup], 3);
+
+        let error = failover.send("05321234567", "Merhaba").await.unwrap_err();
+        assert!(!error.is_uncertain());
+        assert!(error.to_string().contains("primary") && error.to_string().contains("backup"));
+    }
+
+    #[tokio::test]
+    async fn circuit_opens_after_consecutive_failures() {
+        let primary = ScriptedProvider::new("primary", vec![unreachable(), unreachable()]);
+        let backup = ScriptedProvider::new("backup", vec![]);
+        let failover = chain(&[primary.clone(), backup.clone()], 2);
+
+        failover.send("05321234567", "Bir").await.unwrap();
+        assert_eq!(failover.health()[0].circuit, "closed");
+        failover.send("05321234567", "İki").await.unwrap();
+        assert_eq!(failover.health()[0].circuit, "open");
+        assert!(failover.health()[0].open_until.is_some());
+
+        // Skipped while open, even though it would succeed now
+        let response = failover.send("05321234567", "Üç").await.unwrap();
+        assert_eq!(response.provider, "backup");
+        assert_eq!(primary.calls(), 2);
+        assert_eq!(backup.delivered(), 3);
+    }
+
+    #[tokio::test]
+    async fn open_circuits_are_tried_when_nothing_else_is_left() {
+        let primary = ScriptedProvider::new("primary", vec![unreachable()]);
+        let failover = chain(std::slice::from_ref(&primary), 1);
+
+        assert!(failover.send("05321234567", "Bir").await.is_err());
+        assert_eq!(failover.health()[0].circuit, "open");
+
+        let response = failover.send("05321234567", "İki").await.unwrap();
+        assert_eq!(response.provider, "primary");
+        assert_eq!(primary.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn half_open_circuit_lets_one_trial_through() {
+        let primary = ScriptedProvider::new("primary", vec![unreachable()]);
+        let backup = ScriptedProvider::new("backup", vec![]);
+        let failover = chain(&[primary.clone(), backup.clone()], 1);
+
+        failover.send("05321234567", "Bir").await.unwrap();
+        failover.circuit(0).open_until = Some(Utc::now() - chrono::Duration::seconds(1));
+        assert_eq!(failover.health()[0].circuit, "half_open");
+
+        // Only one caller gets the trial
+        let now = Utc::now();
+        assert!(failover.admit(0, now));
+        assert!(!failover.admit(0, now));
+        failover.circuit(0).trial_started_at = None;
+
+        let response = failover.send("05321234567", "İki").await.unwrap();
+        assert_eq!(response.provider, "primary");
+        let health = &failover.health()[0];
+        assert_eq!(health.circuit, "closed");
+        assert_eq!(health.consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn failed_trial_reopens_the_circuit() {
+        let primary = ScriptedProvider::new("primary", vec![unreachable(), unreachable()]);
+        let backup = ScriptedProvider::new("backup", vec![]);
+        let failover = chain(&[primary.clone(), backup.clone()], 1);
+
+        failover.send("05321234567", "Bir").await.unwrap();
+        failover.circuit(0).open_until = Some(Utc::now() - chrono::Duration::seconds(1));
+
+        let response = failover.send("05321234567", "İki").await.unwrap();
+        assert_eq!(response.provider, "backup");
+        assert_eq!(primary.calls(), 2);
+        assert_eq!(failover.health()[0].circuit, "open");
+    }
+
+    #[test]
+    fn http_errors_can_fail_over() {
+        let down = ProviderError::from_status("SMS API", reqwest::StatusCode::BAD_GATEWAY, "<html>Bad Gateway</html>");
+        assert!(matches!(down, ProviderError::Unreachable(_)));
+        assert!(down.can_fail_over());
+
+        let refused = ProviderError::from_status("Netgsm", reqwest::StatusCode::UNAUTHORIZED, &"x".repeat(1000));
+        assert!(matches!(refused, ProviderError::Rejected(_)));
+        assert!(refused.can_fail_over());
+        assert!(refused.to_string().len() < 300);
+    }
+
+    #[tokio::test]
+    async fn mock_records_messages_in_order() {
+        let mock = MockSmsProvider::new(None);
+
+        let first = mock.send("0532 123 45 67", "Merhaba").await.unwrap();
+        let second = mock.send("905551112233", "İkinci mesaj").await.unwrap();
+
+        assert_eq!(first.provider, "mock");
+        assert_eq!(first.message_id.as_deref(), Some("mock-1"));
+        assert_eq!(second.message_id.as_deref(), Some("mock-2"));
+        assert_eq!(first.cost, 0.0);
+
+        let outbox = mock.outbox().unwrap();
+        assert_eq!(outbox.len(), 2);
+        assert_eq!(outbox[0].phone, "905321234567");
+        assert_eq!(outbox[0].message, "Merhaba");
+        assert_eq!(outbox[1].phone, "905551112233");
+        assert_eq!(outbox[1].message, "İkinci mesaj");
+    }
+
+    #[tokio::test]
+    async fn mock_reports_encoding_and_segments() {
+        let mock = MockSmsProvider::new(None);
+
+        let plain = mock.send("05321234567", "Cihaziniz hazir").await.unwrap();
+        assert_eq!(plain.encoding, SmsEncoding::Gsm7);
+        assert_eq!(plain.segments, 1);
+
+        let turkish = mock.send("05321234567", "Cihazınız hazır").await.unwrap();
+        assert_eq!(turkish.encoding, SmsEncoding::Gsm7Turkish);
+        assert!(!turkish.transliterated);
+    }
+
+    #[tokio::test]
+    async fn mock_appends_json_lines_to_file() {
+        let path = std::env::temp_dir().join(format!("mock-sms-{}.jsonl", uuid::Uuid::new_v4()));
+        let mock = MockSmsProvider::new(Some(path.to_string_lossy().into_owned()));
+
+        mock.send("05321234567", "Bir").await.unwrap();
+        mock.send("05321234567", "İki").await.unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["message"], "Bir");
+        assert_eq!(lines[1]["message_id"], "mock-2");
+        assert_eq!(lines[1]["phone"], "905321234567");
+    }
+}
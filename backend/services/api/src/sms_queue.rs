@@ -1,12 +1,54 @@
 use std::time::Duration;
+use std::sync::Arc;
 
 use mongodb::{
-    bson::{doc, oid::ObjectId, Bson, DateTime},
-    Database,
+    bson::{doc, oid::ObjectId, Bson, DateTime, Document},
+    options::{IndexOptions, ReturnDocument},
+    Database, IndexModel,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::sms::send_sms;
+use crate::crypto::FieldCipher;
+use crate::models::status_id_from_any;
+use crate::sms_log::{self, OutgoingSms, SendError};
+use crate::sms_provider::SmsProvider;
+use crate::sms_schedule::SendWindow;
+use crate::survey;
+use crate::trash;
+
+const QUEUE_COLLECTION: &str = "sms_queue";
+
+/// Items claimed per worker tick.
+const BATCH_SIZE: usize = 25;
+
+/// How long a claimed item is reserved for its worker. Longer than a send
+/// through every gateway can take, so a live worker never loses its lease.
+const LEASE_SECONDS: i64 = 5 * 60;
+
+/// Status SMS wait this long after the change, so a quick correction
+/// cancels nothing the customer has already seen.
+const STATUS_SMS_DELAY_SECONDS: i64 = 60 * 60;
+
+/// Retry delays double from `RETRY_BASE_SECONDS` up to `RETRY_MAX_SECONDS`.
+const RETRY_BASE_SECONDS: i64 = 60;
+const RETRY_MAX_SECONDS: i64 = 6 * 60 * 60;
+
+/// What a queued message is for.
+pub const KIND_STATUS: &str = "status";
+/// Only sent while the record is still in `status_id`.
+pub const KIND_PICKUP_REMINDER: &str = "pickup_reminder";
+/// Satisfaction survey after delivery, see `survey`.
+pub const KIND_SURVEY: &str = "survey";
+
+fn default_kind() -> String {
+    KIND_STATUS.to_string()
+}
+
+pub const STATE_PENDING: &str = "pending";
+pub const STATE_SENT: &str = "sent";
+pub const STATE_CANCELLED: &str = "cancelled";
+/// Gave up after `max_attempts` failures; only an admin retry revives it.
+pub const STATE_DEAD: &str = "dead";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SmsQueueItem {
@@ -14,6 +56,8 @@ pub struct SmsQueueItem {
     pub id: Option<ObjectId>,
     pub customer_id: ObjectId,
     pub status_id: i32,
+    #[serde(default = "default_kind")]
+    pub kind: String,
     pub phone: String,
     pub message: String,
     pub due_at: DateTime,
@@ -22,33 +66,167 @@ pub struct SmsQueueItem {
     pub sent_at: Option<DateTime>,
     pub attempts: i32,
     pub last_error: Option<String>,
+    #[serde(default)]
+    pub cancelled: bool,
+    #[serde(default)]
+    pub dead: bool,
+    #[serde(default)]
+    pub dead_at: Option<DateTime>,
+    /// Worker holding the item and until when, see `claim_next`.
+    #[serde(default)]
+    pub lease_owner: Option<String>,
+    #[serde(default)]
+    pub lease_until: Option<DateTime>,
+    /// Sent as soon as due, ignoring the sending window.
+    #[serde(default)]
+    pub urgent: bool,
 }
 
-pub async fn enqueue_status_sms(
-    db: &Database,
+impl SmsQueueItem {
+    pub fn state(&self) -> &'static str {
+        if self.sent {
+            STATE_SENT
+        } else if self.cancelled {
+            STATE_CANCELLED
+        } else if self.dead {
+            STATE_DEAD
+        } else {
+            STATE_PENDING
+        }
+    }
+}
+
+/// Queue item as shown to admins; the phone number is left out.
+#[derive(Debug, Serialize)]
+pub struct SmsQueueItemResponse {
+    pub id: String,
+    pub customer_id: String,
+    pub status_id: i32,
+    pub kind: String,
+    pub message: String,
+    pub state: &'static str,
+    pub urgent: bool,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub due_at: String,
+    pub created_at: String,
+    pub sent_at: Option<String>,
+    pub dead_at: Option<String>,
+}
+
+fn rfc3339(value: DateTime) -> String {
+    value.try_to_rfc3339_string().unwrap_or_default()
+}
+
+impl From<SmsQueueItem> for SmsQueueItemResponse {
+    fn from(item: SmsQueueItem) -> Self {
+        Self {
+            id: item.id.map(|id| id.to_hex()).unwrap_or_default(),
+            customer_id: item.customer_id.to_hex(),
+            status_id: item.status_id,
+            state: item.state(),
+            kind: item.kind,
+            urgent: item.urgent,
+            message: item.message,
+            attempts: item.attempts,
+            last_error: item.last_error,
+            due_at: rfc3339(item.due_at),
+            created_at: rfc3339(item.created_at),
+            sent_at: item.sent_at.map(rfc3339),
+            dead_at: item.dead_at.map(rfc3339),
+        }
+    }
+}
+
+/// Filter for items still waiting to be sent.
+fn pending_filter() -> Document {
+    doc! {
+        "sent": false,
+        "cancelled": { "$ne": true },
+        "dead": { "$ne": true },
+    }
+}
+
+/// Filter for items in `state` (pending, sent, cancelled or dead).
+pub fn state_filter(state: &str) -> Result<Document, String> {
+    match state {
+        STATE_PENDING => Ok(pending_filter()),
+        STATE_SENT => Ok(doc! { "sent": true }),
+        STATE_CANCELLED => Ok(doc! { "sent": false, "cancelled": true }),
+        STATE_DEAD => Ok(doc! { "sent": false, "cancelled": { "$ne": true }, "dead": true }),
+        other => Err(format!("Geçersiz durum: {}", other)),
+    }
+}
+
+fn millis_from_now(seconds: i64) -> DateTime {
+    DateTime::from_millis((chrono::Utc::now() + chrono::Duration::seconds(seconds)).timestamp_millis())
+}
+
+/// `seconds` from now, moved into the sending window unless `urgent`.
+fn scheduled_from_now(window: &SendWindow, seconds: i64, urgent: bool) -> DateTime {
+    let at = window.schedule(chrono::Utc::now() + chrono::Duration::seconds(seconds), urgent);
+    DateTime::from_millis(at.timestamp_millis())
+}
+
+/// Delay before the next try after `attempts` failed sends.
+fn retry_delay_seconds(attempts: i32) -> i64 {
+    let exponent = attempts.clamp(1, 20) as u32 - 1;
+    RETRY_BASE_SECONDS
+        .saturating_mul(2_i64.saturating_pow(exponent))
+        .min(RETRY_MAX_SECONDS)
+}
+
+pub async fn ensure_indexes(db: &Database) -> Result<(), String> {
+    db.collection::<Document>(QUEUE_COLLECTION)
+        .create_indexes(vec![
+            IndexModel::builder()
+                .keys(doc! { "sent": 1, "due_at": 1 })
+                .options(IndexOptions::builder().name("sent_1_due_at_1".to_string()).build())
+                .build(),
+            IndexModel::builder()
+                .keys(doc! { "created_at": -1 })
+                .options(IndexOptions::builder().name("created_at_-1".to_string()).build())
+                .build(),
+        ])
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("SMS queue index error: {}", e))
+}
+
+/// A message to put in the queue.
+struct NewItem {
+    kind: &'static str,
     customer_id: ObjectId,
+    status_id: i32,
     phone: String,
     message: String,
-    status_id: i32,
-) -> Result<(), String> {
-    let collection = db.collection::<SmsQueueItem>("sms_queue");
-    let due_millis = (chrono::Utc::now() + chrono::Duration::hours(1)).timestamp_millis();
+    due_at: DateTime,
+    urgent: bool,
+}
 
+async fn insert_item(db: &Database, new: NewItem) -> Result<(), String> {
     let item = SmsQueueItem {
         id: None,
-        customer_id,
-        status_id,
-        phone,
-        message,
-        due_at: DateTime::from_millis(due_millis),
+        customer_id: new.customer_id,
+        status_id: new.status_id,
+        kind: new.kind.to_string(),
+        phone: new.phone,
+        message: new.message,
+        due_at: new.due_at,
         created_at: DateTime::now(),
         sent: false,
         sent_at: None,
         attempts: 0,
         last_error: None,
+        cancelled: false,
+        dead: false,
+        dead_at: None,
+        lease_owner: None,
+        lease_until: None,
+        urgent: new.urgent,
     };
 
-    collection
+    db.collection::<SmsQueueItem>(QUEUE_COLLECTION)
         .insert_one(item)
         .await
         .map_err(|e| format!("Queue insert error: {}", e))?;
@@ -56,10 +234,99 @@ pub async fn enqueue_status_sms(
     Ok(())
 }
 
-pub fn start_sms_queue_worker(db: Database) {
+/// Queues a status SMS an hour from now, deferred to the next sending
+/// window unless `urgent`.
+pub async fn enqueue_status_sms(
+    db: &Database,
+    window: &SendWindow,
+    customer_id: ObjectId,
+    phone: String,
+    message: String,
+    status_id: i32,
+    urgent: bool,
+) -> Result<(), String> {
+    insert_item(
+        db,
+        NewItem {
+            kind: KIND_STATUS,
+            customer_id,
+            status_id,
+            phone,
+            message,
+            due_at: scheduled_from_now(window, STATUS_SMS_DELAY_SECONDS, urgent),
+            urgent,
+        },
+    )
+    .await
+}
+
+/// Queues a pickup reminder for the next sending window. It is cancelled if
+/// the record has left `status_id` by then.
+pub async fn enqueue_pickup_reminder(
+    db: &Database,
+    window: &SendWindow,
+    customer_id: ObjectId,
+    phone: String,
+    message: String,
+    status_id: i32,
+) -> Result<(), String> {
+    insert_item(
+        db,
+        NewItem {
+            kind: KIND_PICKUP_REMINDER,
+            customer_id,
+            status_id,
+            phone,
+            message,
+            due_at: scheduled_from_now(window, 0, false),
+            urgent: false,
+        },
+    )
+    .await
+}
+
+/// Queues the satisfaction survey `delay_hours` after delivery, deferred to
+/// the next sending window.
+pub async fn enqueue_survey_sms(
+    db: &Database,
+    window: &SendWindow,
+    customer_id: ObjectId,
+    phone: String,
+    message: String,
+    delay_hours: i64,
+) -> Result<(), String> {
+    insert_item(
+        db,
+        NewItem {
+            kind: KIND_SURVEY,
+            customer_id,
+            status_id: 8,
+            phone,
+            message,
+            due_at: scheduled_from_now(window, delay_hours * 60 * 60, false),
+            urgent: false,
+        },
+    )
+    .await
+}
+
+/// Worker settings.
+#[derive(Clone)]
+pub struct QueueWorker {
+    pub db: Database,
+    pub cipher: FieldCipher,
+    pub sms: Arc<dyn SmsProvider>,
+    /// Failed sends before an item is dead-lettered.
+    pub max_attempts: i32,
+    pub window: SendWindow,
+}
+
+pub fn start_sms_queue_worker(worker: QueueWorker) {
+    // Unique per process, so several API instances can share the queue
+    let owner = ObjectId::new().to_hex();
     tokio::spawn(async move {
         loop {
-            if let Err(e) = process_due_sms_queue(&db).await {
+            if let Err(e) = process_due_sms_queue(&worker, &owner).await {
                 tracing::error!("SMS queue worker error: {}", e);
             }
             tokio::time::sleep(Duration::from_secs(30)).await;
@@ -67,37 +334,105 @@ pub fn start_sms_queue_worker(db: Database) {
     });
 }
 
-async fn process_due_sms_queue(db: &Database) -> Result<(), String> {
-    let queue_collection = db.collection::<SmsQueueItem>("sms_queue");
-    let customer_collection = db.collection::<mongodb::bson::Document>("musteri_kabul");
-
+/// Atomically reserves the next due item for `owner`. Items whose lease ran
+/// out (their worker died mid-send) can be claimed again.
+async fn claim_next(db: &Database, owner: &str) -> Result<Option<SmsQueueItem>, String> {
     let now = DateTime::now();
-    let mut cursor = queue_collection
-        .find(doc! {
-            "sent": false,
-            "due_at": { "$lte": now }
-        })
+    let mut filter = pending_filter();
+    filter.insert("due_at", doc! { "$lte": now });
+    filter.insert(
+        "$or",
+        vec![
+            Bson::Document(doc! { "lease_until": Bson::Null }),
+            Bson::Document(doc! { "lease_until": { "$lte": now } }),
+        ],
+    );
+
+    db.collection::<SmsQueueItem>(QUEUE_COLLECTION)
+        .find_one_and_update(
+            filter,
+            doc! { "$set": { "lease_owner": owner, "lease_until": millis_from_now(LEASE_SECONDS) } },
+        )
         .sort(doc! { "due_at": 1 })
-        .limit(25)
+        .return_document(ReturnDocument::After)
         .await
-        .map_err(|e| format!("Queue find error: {}", e))?;
+        .map_err(|e| format!("Queue claim error: {}", e))
+}
 
-    while cursor
-        .advance()
-        .await
-        .map_err(|e| format!("Queue cursor error: {}", e))?
-    {
-        let item: SmsQueueItem = cursor
-            .deserialize_current()
-            .map_err(|e| format!("Queue deserialize error: {}", e))?;
+async fn process_due_sms_queue(worker: &QueueWorker, owner: &str) -> Result<(), String> {
+    let queue_collection = worker.db.collection::<SmsQueueItem>(QUEUE_COLLECTION);
+    let customer_collection = worker.db.collection::<Document>("musteri_kabul");
+
+    for _ in 0..BATCH_SIZE {
+        let Some(item) = claim_next(&worker.db, owner).await? else {
+            break;
+        };
 
         let Some(queue_id) = item.id else {
             continue;
         };
 
-        let queue_filter = doc! { "_id": queue_id };
+        // Updates only apply while this worker still holds the lease
+        let queue_filter = doc! { "_id": queue_id, "lease_owner": owner };
+        let release = doc! { "lease_owner": "", "lease_until": "" };
+
+        // Due outside the sending window (e.g. retried by an admin at night)
+        if !item.urgent && !worker.window.is_open(chrono::Utc::now()) {
+            queue_collection
+                .update_one(
+                    queue_filter,
+                    doc! {
+                        "$set": { "due_at": scheduled_from_now(&worker.window, 0, false) },
+                        "$unset": release,
+                    },
+                )
+                .await
+                .map_err(|e| format!("Queue defer update error: {}", e))?;
+            continue;
+        }
+
+        // Customer moved to the trash after the SMS was queued, or left the
+        // status a reminder or survey was queued for
+        let customer = customer_collection
+            .find_one(trash::active(doc! { "_id": item.customer_id }))
+            .await
+            .map_err(|e| format!("Queue customer lookup error: {}", e))?;
+        let cancel_reason = match &customer {
+            None => Some("Müşteri kaydı silinmiş"),
+            Some(customer)
+                if item.kind != KIND_STATUS
+                    && customer.get_str("status").ok().and_then(status_id_from_any) != Some(item.status_id) =>
+            {
+                Some(if item.kind == KIND_PICKUP_REMINDER {
+                    "Cihaz teslim alınmış"
+                } else {
+                    "Kayıt durumu değişmiş"
+                })
+            }
+            Some(_) => None,
+        };
+
+        if let Some(reason) = cancel_reason {
+            queue_collection
+                .update_one(
+                    queue_filter,
+                    doc! {
+                        "$set": { "cancelled": true, "last_error": reason },
+                        "$unset": release,
+                    },
+                )
+                .await
+                .map_err(|e| format!("Queue cancel update error: {}", e))?;
+            continue;
+        }
 
-        match send_sms(&item.phone, &item.message).await {
+        let template = match item.kind.as_str() {
+            KIND_PICKUP_REMINDER => sms_log::TEMPLATE_PICKUP_REMINDER,
+            KIND_SURVEY => sms_log::TEMPLATE_SURVEY,
+            _ => sms_log::TEMPLATE_STATUS,
+        };
+        let outgoing = OutgoingSms::new(template, &item.phone, &item.message).record("musteri_kabul", item.customer_id);
+        match sms_log::send(&worker.db, &worker.cipher, worker.sms.as_ref(), outgoing).await {
             Ok(response) => {
                 queue_collection
                     .update_one(
@@ -107,16 +442,26 @@ async fn process_due_sms_queue(db: &Database) -> Result<(), String> {
                                 "sent": true,
                                 "sent_at": DateTime::now(),
                                 "last_error": Bson::Null,
+                                "provider": response.provider,
                                 "provider_message": response.message,
                             },
                             "$inc": {
                                 "attempts": 1,
-                            }
+                            },
+                            "$unset": release,
                         },
                     )
                     .await
                     .map_err(|e| format!("Queue success update error: {}", e))?;
 
+                if item.kind == KIND_SURVEY {
+                    if let Err(e) = survey::mark_sent(&worker.db, item.customer_id).await {
+                        tracing::error!("{}", e);
+                    }
+                }
+                if item.kind != KIND_STATUS {
+                    continue;
+                }
                 let _ = customer_collection
                     .update_one(
                         doc! { "_id": item.customer_id },
@@ -132,19 +477,60 @@ async fn process_due_sms_queue(db: &Database) -> Result<(), String> {
                     )
                     .await;
             }
-            Err(err) => {
-                let retry_millis = (chrono::Utc::now() + chrono::Duration::minutes(5)).timestamp_millis();
+            Err(SendError::Suppressed) => {
+                queue_collection
+                    .update_one(
+                        queue_filter,
+                        doc! {
+                            "$set": { "cancelled": true, "last_error": SendError::Suppressed.to_string() },
+                            "$unset": release,
+                        },
+                    )
+                    .await
+                    .map_err(|e| format!("Queue cancel update error: {}", e))?;
+            }
+            Err(SendError::Uncertain(err)) => {
+                // May have gone out; leave it to an admin retry instead of risking a duplicate
+                println!("❓ SMS kuyruğu: {} gönderim sonucu belirsiz, tekrar denenmeyecek: {}", queue_id, err);
                 queue_collection
                     .update_one(
                         queue_filter,
                         doc! {
                             "$set": {
-                                "last_error": err,
-                                "due_at": DateTime::from_millis(retry_millis),
+                                "last_error": format!("Gönderim sonucu belirsiz: {}", err),
+                                "dead": true,
+                                "dead_at": DateTime::now(),
                             },
-                            "$inc": {
-                                "attempts": 1,
-                            }
+                            "$inc": { "attempts": 1 },
+                            "$unset": release,
+                        },
+                    )
+                    .await
+                    .map_err(|e| format!("Queue failure update error: {}", e))?;
+            }
+            Err(SendError::Failed(err)) => {
+                let attempts = item.attempts + 1;
+                let set = if attempts >= worker.max_attempts {
+                    println!("☠️ SMS kuyruğu: {} {} denemeden sonra bırakıldı: {}", queue_id, attempts, err);
+                    doc! {
+                        "last_error": err,
+                        "dead": true,
+                        "dead_at": DateTime::now(),
+                    }
+                } else {
+                    doc! {
+                        "last_error": err,
+                        "due_at": scheduled_from_now(&worker.window, retry_delay_seconds(attempts), item.urgent),
+                    }
+                };
+
+                queue_collection
+                    .update_one(
+                        queue_filter,
+                        doc! {
+                            "$set": set,
+                            "$inc": { "attempts": 1 },
+                            "$unset": release,
                         },
                     )
                     .await
@@ -155,3 +541,111 @@ async fn process_due_sms_queue(db: &Database) -> Result<(), String> {
 
     Ok(())
 }
+
+/// Newest items first.
+pub async fn list_items(db: &Database, filter: Document, limit: i64) -> Result<Vec<SmsQueueItem>, String> {
+    let mut cursor = db
+        .collection::<SmsQueueItem>(QUEUE_COLLECTION)
+        .find(filter)
+        .sort(doc! { "created_at": -1 })
+        .limit(limit)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let mut items = Vec::new();
+    while cursor
+        .advance()
+        .await
+        .map_err(|e| format!("Cursor error: {}", e))?
+    {
+        items.push(
+            cursor
+                .deserialize_current()
+                .map_err(|e| format!("Deserialize error: {}", e))?,
+        );
+    }
+
+    Ok(items)
+}
+
+/// Why an admin retry or cancel did not apply.
+#[derive(Debug)]
+pub enum ItemActionError {
+    /// No unsent item with that id.
+    NotFound,
+    /// A worker is sending the item right now.
+    Leased,
+    Database(String),
+}
+
+/// Filter for an unsent item no worker currently holds a lease on.
+fn unleased_unsent(id: ObjectId) -> Document {
+    doc! {
+        "_id": id,
+        "sent": false,
+        "$or": [
+            { "lease_until": Bson::Null },
+            { "lease_until": { "$lte": DateTime::now() } },
+        ],
+    }
+}
+
+/// Applies `update` to an unsent, unleased item and tells a missing item
+/// apart from one that is being sent.
+async fn update_unleased(db: &Database, id: ObjectId, update: Document) -> Result<SmsQueueItem, ItemActionError> {
+    let collection = db.collection::<SmsQueueItem>(QUEUE_COLLECTION);
+    let database_error = |e: mongodb::error::Error| ItemActionError::Database(format!("Database error: {}", e));
+
+    if let Some(item) = collection
+        .find_one_and_update(unleased_unsent(id), update)
+        .return_document(ReturnDocument::After)
+        .await
+        .map_err(database_error)?
+    {
+        return Ok(item);
+    }
+
+    match collection
+        .find_one(doc! { "_id": id, "sent": false })
+        .await
+        .map_err(database_error)?
+    {
+        Some(_) => Err(ItemActionError::Leased),
+        None => Err(ItemActionError::NotFound),
+    }
+}
+
+/// Puts an unsent (pending, dead or cancelled) item back in line for an
+/// immediate send with a fresh attempt budget.
+pub async fn retry_item(db: &Database, id: ObjectId) -> Result<SmsQueueItem, ItemActionError> {
+    update_unleased(
+        db,
+        id,
+        doc! {
+            "$set": {
+                "cancelled": false,
+                "dead": false,
+                "attempts": 0,
+                "due_at": DateTime::now(),
+            },
+            "$unset": { "dead_at": "", "lease_owner": "", "lease_until": "" },
+        },
+    )
+    .await
+}
+
+/// Cancels an unsent item.
+pub async fn cancel_item(db: &Database, id: ObjectId, actor: &str) -> Result<SmsQueueItem, ItemActionError> {
+    update_unleased(
+        db,
+        id,
+        doc! {
+            "$set": {
+                "cancelled": true,
+                "cancelled_by": actor,
+                "cancelled_at": DateTime::now(),
+            },
+        },
+    )
+    .await
+}
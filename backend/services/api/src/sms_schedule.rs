@@ -0,0 +1,197 @@
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use serde::Serialize;
+
+use crate::config::SmsScheduleConfig;
+
+/// Turkey has been on UTC+3 all year since 2016.
+const TURKEY_UTC_OFFSET_SECONDS: i32 = 3 * 60 * 60;
+
+/// National holidays on the same date every year (month, day).
+const FIXED_HOLIDAYS: &[(u32, u32)] = &[
+    (1, 1),   // Yılbaşı
+    (4, 23),  // Ulusal Egemenlik ve Çocuk Bayramı
+    (5, 1),   // Emek ve Dayanışma Günü
+    (5, 19),  // Atatürk'ü Anma, Gençlik ve Spor Bayramı
+    (7, 15),  // Demokrasi ve Milli Birlik Günü
+    (8, 30),  // Zafer Bayramı
+    (10, 29), // Cumhuriyet Bayramı
+];
+
+/// Ramazan and Kurban Bayramı days, which move with the lunar calendar.
+/// Later years go in `sms.schedule.holidays` until they are added here.
+const RELIGIOUS_HOLIDAYS: &[(i32, u32, u32)] = &[
+    (2025, 3, 30),
+    (2025, 3, 31),
+    (2025, 4, 1),
+    (2025, 6, 6),
+    (2025, 6, 7),
+    (2025, 6, 8),
+    (2025, 6, 9),
+    (2026, 3, 20),
+    (2026, 3, 21),
+    (2026, 3, 22),
+    (2026, 5, 27),
+    (2026, 5, 28),
+    (2026, 5, 29),
+    (2026, 5, 30),
+    (2027, 3, 9),
+    (2027, 3, 10),
+    (2027, 3, 11),
+    (2027, 5, 16),
+    (2027, 5, 17),
+    (2027, 5, 18),
+    (2027, 5, 19),
+];
+
+/// Upper bound on the days searched for the next slot.
+const MAX_LOOKAHEAD_DAYS: u32 = 30;
+
+/// When non-urgent SMS may go out: between `start` and `end` Turkish time,
+/// never on public holidays and only on Sundays if allowed.
+#[derive(Debug, Clone, Serialize)]
+pub struct SendWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub sundays: bool,
+    /// Extra days off on top of the built-in calendar.
+    pub holidays: Vec<NaiveDate>,
+}
+
+impl SendWindow {
+    pub fn new(config: &SmsScheduleConfig) -> Self {
+        Self {
+            start: config.window_start,
+            end: config.window_end,
+            sundays: config.sundays,
+            holidays: config.holidays.clone(),
+        }
+    }
+
+    fn timezone() -> FixedOffset {
+        FixedOffset::east_opt(TURKEY_UTC_OFFSET_SECONDS).expect("valid offset")
+    }
+
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        FIXED_HOLIDAYS.contains(&(date.month(), date.day()))
+            || RELIGIOUS_HOLIDAYS.contains(&(date.year(), date.month(), date.day()))
+            || self.holidays.contains(&date)
+    }
+
+    fn is_sending_day(&self, date: NaiveDate) -> bool {
+        (self.sundays || date.weekday() != Weekday::Sun) && !self.is_holiday(date)
+    }
+
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&Self::timezone());
+        let time = local.time();
+        self.is_sending_day(local.date_naive()) && time >= self.start && time < self.end
+    }
+
+    /// `at` if the window is open then, otherwise the start of the next
+    /// sending day's window.
+    pub fn next_slot(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        let tz = Self::timezone();
+        let local = at.with_timezone(&tz);
+        let mut date = local.date_naive();
+
+        for day in 0..MAX_LOOKAHEAD_DAYS {
+            if self.is_sending_day(date) {
+                if day > 0 || local.time() < self.start {
+                    if let Some(slot) = tz.from_local_datetime(&date.and_time(self.start)).single() {
+                        return slot.with_timezone(&Utc);
+                    }
+                } else if local.time() < self.end {
+                    return at;
+                }
+            }
+            date += Duration::days(1);
+        }
+
+        at
+    }
+
+    /// Send time for a message ready at `at`; urgent messages skip the window.
+    pub fn schedule(&self, at: DateTime<Utc>, urgent: bool) -> DateTime<Utc> {
+        if urgent {
+            at
+        } else {
+            self.next_slot(at)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(sundays: bool, holidays: Vec<NaiveDate>) -> SendWindow {
+        SendWindow {
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            sundays,
+            holidays,
+        }
+    }
+
+    /// Turkish local time as UTC.
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        SendWindow::timezone()
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn inside_the_window_sends_now() {
+        let at = local(2026, 10, 12, 10, 30);
+        assert_eq!(window(false, vec![]).next_slot(at), at);
+        assert!(window(false, vec![]).is_open(at));
+    }
+
+    #[test]
+    fn before_the_window_waits_for_the_start() {
+        let window = window(false, vec![]);
+        assert_eq!(window.next_slot(local(2026, 10, 12, 6, 15)), local(2026, 10, 12, 9, 0));
+        assert_eq!(window.next_slot(local(2026, 10, 12, 9, 0)), local(2026, 10, 12, 9, 0));
+    }
+
+    #[test]
+    fn window_end_moves_to_the_next_day() {
+        let window = window(false, vec![]);
+        assert_eq!(window.next_slot(local(2026, 10, 12, 21, 0)), local(2026, 10, 13, 9, 0));
+        assert_eq!(window.next_slot(local(2026, 10, 12, 23, 59)), local(2026, 10, 13, 9, 0));
+    }
+
+    #[test]
+    fn sundays_are_skipped_unless_allowed() {
+        // Saturday night
+        let at = local(2026, 10, 17, 22, 0);
+        assert_eq!(window(false, vec![]).next_slot(at), local(2026, 10, 19, 9, 0));
+        assert_eq!(window(true, vec![]).next_slot(at), local(2026, 10, 18, 9, 0));
+        assert!(!window(false, vec![]).is_open(local(2026, 10, 18, 12, 0)));
+    }
+
+    #[test]
+    fn holidays_are_skipped() {
+        let window = window(false, vec![]);
+        // Cumhuriyet Bayramı
+        assert_eq!(window.next_slot(local(2026, 10, 28, 22, 0)), local(2026, 10, 30, 9, 0));
+        // Kurban Bayramı (27–30 May) runs into a Sunday
+        assert_eq!(window.next_slot(local(2026, 5, 26, 22, 0)), local(2026, 6, 1, 9, 0));
+    }
+
+    #[test]
+    fn configured_holidays_are_skipped() {
+        let friday = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let window = window(false, vec![friday]);
+        assert!(window.is_holiday(friday));
+        assert_eq!(window.next_slot(local(2026, 10, 15, 21, 30)), local(2026, 10, 17, 9, 0));
+    }
+
+    #[test]
+    fn urgent_messages_skip_the_window() {
+        let at = local(2026, 10, 18, 3, 0);
+        assert_eq!(window(false, vec![]).schedule(at, true), at);
+        assert_eq!(window(false, vec![]).schedule(at, false), local(2026, 10, 19, 9, 0));
+    }
+}
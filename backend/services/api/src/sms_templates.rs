@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use mongodb::{
+    bson::{doc, DateTime, Document},
+    options::ReplaceOptions,
+    Database,
+};
+use serde::{Deserialize, Serialize};
+
+const COLLECTION: &str = "sms_templates";
+
+/// Placeholder values are uppercased for these, as the messages always were.
+const UPPERCASE_PLACEHOLDERS: &[&str] = &["ad_soyad", "model"];
+
+/// Values used by the preview endpoint when none are given.
+const SAMPLE_VALUES: &[(&str, &str)] = &[
+    ("ad_soyad", "Ahmet Yılmaz"),
+    ("model", "Samsung UE55"),
+    ("link", "https://teknik.example.com/takip/Ab12Cd34Ef56"),
+    ("kod", "123456"),
+    ("son_tarih", "24.11.2026"),
+];
+
+/// An SMS the system sends, with its built-in text.
+pub struct TemplateDef {
+    pub key: &'static str,
+    pub description: &'static str,
+    pub placeholders: &'static [&'static str],
+    /// Placeholders an edited body must keep, e.g. the link or the code.
+    pub required: &'static [&'static str],
+    pub body: &'static str,
+}
+
+const CUSTOMER: &[&str] = &["ad_soyad", "model", "servis_telefonu"];
+const CUSTOMER_LINK: &[&str] = &["ad_soyad", "model", "link", "servis_telefonu"];
+const CUSTOMER_DEADLINE: &[&str] = &["ad_soyad", "model", "son_tarih", "servis_telefonu"];
+const LINK: &[&str] = &["link"];
+
+pub const TEMPLATES: &[TemplateDef] = &[
+    TemplateDef {
+        key: "intake",
+        description: "Cihaz kabulü (link: takip sayfası)",
+        placeholders: CUSTOMER_LINK,
+        required: LINK,
+        body: "SN : {ad_soyad}\n{model} CİHAZININ İNCELENMEK ÜZERE ATÖLYEMIZE KABUL EDİLMİŞTİR.\nCİHAZ DURUMU: {link}\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "intake_fatura",
+        description: "Kabul SMS'ine eklenen fatura yükleme bölümü (link: fatura yükleme)",
+        placeholders: CUSTOMER_LINK,
+        required: LINK,
+        body: "Fatura Yükleme:\n{link}",
+    },
+    TemplateDef {
+        key: "intake_tv_kurulum",
+        description: "TV kurulum kaydı (link: fatura yükleme)",
+        placeholders: CUSTOMER_LINK,
+        required: LINK,
+        body: "SN : {ad_soyad}\nTV KURULUM KAYDI ALINDI.\nFATURA: {link}\nFATURA YUKLENMEDEN HIZMET VERILMEZ.",
+    },
+    TemplateDef {
+        key: "intake_robot_kurulum",
+        description: "Robot kurulum kaydı (link: fatura yükleme)",
+        placeholders: CUSTOMER_LINK,
+        required: LINK,
+        body: "SN : {ad_soyad}\nROBOT KURULUM KAYDI ALINDI.\nFATURA: {link}\nFATURA YUKLENMEDEN HIZMET VERILMEZ.",
+    },
+    TemplateDef {
+        key: "intake_ariza",
+        description: "TV/robot arıza kaydı",
+        placeholders: CUSTOMER,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CIHAZININ INCELENMEK UZERE ATOLYEMIZE KABUL EDILMISTIR.\nTEKNIK ELEKTRONIK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "status_2",
+        description: "Durum: teknisyene verildi",
+        placeholders: CUSTOMER,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CIHAZINIZ İNCELENMEK ÜZERE TEKNİSYENE VERİLMİŞTİR.\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "status_3",
+        description: "Durum: işlem bekliyor",
+        placeholders: CUSTOMER,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CIHAZINIZ İŞLEM BEKLEMEKTEDİR. SİSTEMSEL KAYITLAR TAMAMLANDIĞINDA BİLGİ VERİLECEKTİR.\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "status_4",
+        description: "Durum: parça bekleniyor",
+        placeholders: CUSTOMER,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CIHAZINIZ PARÇA BEKLEMEKTEDİR. EN KISA SÜREDE İŞLEMLERİ TAMAMLANACAKTIR.\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "status_5",
+        description: "Durum: merkeze sevk edildi",
+        placeholders: CUSTOMER,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CIHAZINIZ MERKEZE SEVK EDİLMİŞTİR. EN KISA SÜREDE ULAŞINCA BİLGİ VERİLECEKTİR.\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "status_6",
+        description: "Durum: değişim",
+        placeholders: CUSTOMER,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CIHAZINIZIN DEĞİŞİM İŞLEMLERİ YAPILACAKTIR. EN KISA SÜREDE TARAFINIZA BİLGİ SAĞLANACAKTIR.\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "status_7",
+        description: "Durum: tamir tamamlandı",
+        placeholders: CUSTOMER,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CIHAZINIZIN TAMİRİ TAMAMLANDI EN KISA SÜREDE TESLİM ALMANIZI RİCA EDERİZ. 20 İŞ GÜNÜ İÇERİSİNDE ALINMAYAN ÜRÜNLER İÇİN SORUMLULUK KABUL EDİLMEYECEKTİR.\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "status_8",
+        description: "Durum: teslim edildi",
+        placeholders: CUSTOMER,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CIHAZINIZ TESLİM EDİLDİ GÜLE GÜLE KULLANMANIZ DİLEĞİYLE\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "status_9",
+        description: "Durum: iadeye hazır",
+        placeholders: CUSTOMER,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CIHAZINIZ İADE EDİLMEK ÜZERE İADE KÖŞESİNE ALINMIŞTIR EN KISA SÜREDE İADE ALINIZ 20 GÜNÜ GEÇEN ÜRÜNLERİN KAYBOLMASI DURUMUNDA SORUMLULUK KABUL EDİLMEYECEKTİR.\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "pickup_reminder_7",
+        description: "Teslim hatırlatması: tamiri tamamlanan cihaz (son_tarih: son teslim günü)",
+        placeholders: CUSTOMER_DEADLINE,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CİHAZINIZIN TAMİRİ TAMAMLANMIŞ OLUP TESLİM ALINMAYI BEKLEMEKTEDİR. SON TESLİM TARİHİ: {son_tarih}\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "pickup_reminder_9",
+        description: "Teslim hatırlatması: iadeye hazır cihaz (son_tarih: son teslim günü)",
+        placeholders: CUSTOMER_DEADLINE,
+        required: &[],
+        body: "SN : {ad_soyad}\n{model} CİHAZINIZ İADE KÖŞESİNDE BEKLEMEKTEDİR. SON TESLİM TARİHİ: {son_tarih}. BU TARİHTEN SONRA SORUMLULUK KABUL EDİLMEYECEKTİR.\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "survey",
+        description: "Teslimden sonra memnuniyet anketi (link: anket sayfası)",
+        placeholders: CUSTOMER_LINK,
+        required: LINK,
+        body: "SN : {ad_soyad}\n{model} CİHAZINIZ İÇİN HİZMETİMİZİ 1-5 ARASI PUANLAR MISINIZ?\n{link}\nTEKNİK ELEKTRONİK {servis_telefonu}",
+    },
+    TemplateDef {
+        key: "montaj_tv_kurulum",
+        description: "Montaj kaydı: TV kurulum (link: fatura yükleme)",
+        placeholders: CUSTOMER_LINK,
+        required: LINK,
+        body: "SN : {ad_soyad}\nTV KURULUM KAYDI ALINDI.\nFATURA: {link}\nFATURA YUKLENMEDEN HIZMET VERILMEZ.",
+    },
+    TemplateDef {
+        key: "montaj_robot_kurulum",
+        description: "Montaj kaydı: robot kurulum (link: fatura yükleme)",
+        placeholders: CUSTOMER_LINK,
+        required: LINK,
+        body: "SN : {ad_soyad}\nROBOT KURULUM KAYDI ALINDI.\nFATURA: {link}\nFATURA YUKLENMEDEN HIZMET VERILMEZ.",
+    },
+    TemplateDef {
+        key: "montaj_diger",
+        description: "Montaj kaydı: diğer servis tipleri (link: fatura yükleme)",
+        placeholders: CUSTOMER_LINK,
+        required: LINK,
+        body: "SN : {ad_soyad}\nMONTAJ KAYDI ALINDI.\nCİHAZ: {model}\nFATURA: {link}\nFATURA YUKLENMEDEN HIZMET VERILMEZ.",
+    },
+    TemplateDef {
+        key: "otp",
+        description: "Silme onay kodu",
+        placeholders: &["kod", "servis_telefonu"],
+        required: &["kod"],
+        body: "SIS Teknik silme onay kodunuz: {kod}. Bu kod 10 dakika geçerlidir.",
+    },
+];
+
+pub fn definition(key: &str) -> Option<&'static TemplateDef> {
+    TEMPLATES.iter().find(|def| def.key == key)
+}
+
+/// Template of the status SMS for `status_id`, if that status sends one.
+pub fn status_key(status_id: i32) -> Option<&'static str> {
+    definition(&format!("status_{}", status_id)).map(|def| def.key)
+}
+
+/// Names written as `{name}` in a template body.
+fn placeholders_in(body: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = body;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) => {
+                names.push(&after[..end]);
+                rest = &after[end + 1..];
+            }
+            None => break,
+        }
+    }
+    names
+}
+
+/// Rejects empty bodies, placeholders the template does not provide and
+/// bodies that drop a required one.
+pub fn validate(def: &TemplateDef, body: &str) -> Result<(), String> {
+    if body.trim().is_empty() {
+        return Err("Şablon metni boş olamaz".to_string());
+    }
+    let used = placeholders_in(body);
+    for name in def.required {
+        if !used.contains(name) {
+            return Err(format!("Şablon {{{}}} yer tutucusunu içermeli", name));
+        }
+    }
+    for name in used {
+        if !def.placeholders.contains(&name) {
+            return Err(format!(
+                "Bilinmeyen yer tutucu: {{{}}} (kullanılabilir: {})",
+                name,
+                def.placeholders.iter().map(|p| format!("{{{}}}", p)).collect::<Vec<_>>().join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredTemplate {
+    #[serde(rename = "_id")]
+    key: String,
+    body: String,
+    updated_at: DateTime,
+    updated_by: String,
+}
+
+/// Template as listed to admins.
+#[derive(Debug, Serialize)]
+pub struct TemplateInfo {
+    pub key: &'static str,
+    pub description: &'static str,
+    pub placeholders: Vec<String>,
+    pub required: Vec<String>,
+    pub body: String,
+    pub default_body: &'static str,
+    pub customized: bool,
+    pub updated_at: Option<String>,
+    pub updated_by: Option<String>,
+}
+
+/// SMS texts: an admin-edited version from `sms_templates` when there is one,
+/// the built-in text otherwise.
+#[derive(Clone)]
+pub struct SmsTemplates {
+    db: Database,
+    shop_phone: String,
+}
+
+impl SmsTemplates {
+    pub fn new(db: Database, shop_phone: &str) -> Self {
+        Self {
+            db,
+            shop_phone: shop_phone.to_string(),
+        }
+    }
+
+    async fn stored(&self, key: &str) -> Result<Option<StoredTemplate>, String> {
+        self.db
+            .collection::<StoredTemplate>(COLLECTION)
+            .find_one(doc! { "_id": key })
+            .await
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Fills `{name}` placeholders in `body`; `servis_telefonu` is always set.
+    pub fn fill(&self, body: &str, values: &[(&str, &str)]) -> String {
+        let mut text = body.replace("{servis_telefonu}", &self.shop_phone);
+        for (name, value) in values {
+            let value = if UPPERCASE_PLACEHOLDERS.contains(name) {
+                value.to_uppercase()
+            } else {
+                value.to_string()
+            };
+            text = text.replace(&format!("{{{}}}", name), &value);
+        }
+        text
+    }
+
+    /// Text of template `key`. Falls back to the built-in text if the stored
+    /// one cannot be read or no longer validates, so a database hiccup never
+    /// blocks an SMS.
+    pub async fn render(&self, key: &str, values: &[(&str, &str)]) -> String {
+        let Some(def) = definition(key) else {
+            tracing::error!("Unknown SMS template: {}", key);
+            return String::new();
+        };
+        let body = match self.stored(key).await {
+            Ok(Some(stored)) => match validate(def, &stored.body) {
+                Ok(()) => stored.body,
+                Err(e) => {
+                    tracing::error!("SMS template {} is invalid, using the default: {}", key, e);
+                    def.body.to_string()
+                }
+            },
+            Ok(None) => def.body.to_string(),
+            Err(e) => {
+                tracing::error!("SMS template {} could not be loaded: {}", key, e);
+                def.body.to_string()
+            }
+        };
+        self.fill(&body, values)
+    }
+
+    /// Fills `body` with sample values, overridden by `values`.
+    pub fn preview(&self, body: &str, values: &HashMap<String, String>) -> String {
+        let mut merged: Vec<(&str, &str)> = SAMPLE_VALUES
+            .iter()
+            .filter(|(name, _)| !values.contains_key(*name))
+            .copied()
+            .collect();
+        merged.extend(values.iter().map(|(name, value)| (name.as_str(), value.as_str())));
+        self.fill(body, &merged)
+    }
+
+    pub async fn list(&self) -> Result<Vec<TemplateInfo>, String> {
+        let mut cursor = self
+            .db
+            .collection::<StoredTemplate>(COLLECTION)
+            .find(doc! {})
+            .await
+            .map_err(|e| format!("Database error: {}", e))?;
+
+        let mut stored = HashMap::new();
+        while cursor
+            .advance()
+            .await
+            .map_err(|e| format!("Cursor error: {}", e))?
+        {
+            let template: StoredTemplate = cursor
+                .deserialize_current()
+                .map_err(|e| format!("Deserialize error: {}", e))?;
+            stored.insert(template.key.clone(), template);
+        }
+
+        Ok(TEMPLATES
+            .iter()
+            .map(|def| {
+                let custom = stored.remove(def.key);
+                TemplateInfo {
+                    key: def.key,
+                    description: def.description,
+                    placeholders: def.placeholders.iter().map(|p| format!("{{{}}}", p)).collect(),
+                    required: def.required.iter().map(|p| format!("{{{}}}", p)).collect(),
+                    body: custom.as_ref().map(|t| t.body.clone()).unwrap_or_else(|| def.body.to_string()),
+                    default_body: def.body,
+                    customized: custom.is_some(),
+                    updated_at: custom.as_ref().and_then(|t| t.updated_at.try_to_rfc3339_string().ok()),
+                    updated_by: custom.map(|t| t.updated_by),
+                }
+            })
+            .collect())
+    }
+
+    /// Current body of `key` (stored or built-in), for the audit diff.
+    pub async fn current_body(&self, def: &TemplateDef) -> Result<String, String> {
+        Ok(self
+            .stored(def.key)
+            .await?
+            .map(|t| t.body)
+            .unwrap_or_else(|| def.body.to_string()))
+    }
+
+    pub async fn save(&self, def: &TemplateDef, body: &str, actor: &str) -> Result<(), String> {
+        let template = StoredTemplate {
+            key: def.key.to_string(),
+            body: body.to_string(),
+            updated_at: DateTime::now(),
+            updated_by: actor.to_string(),
+        };
+        self.db
+            .collection::<StoredTemplate>(COLLECTION)
+            .replace_one(doc! { "_id": def.key }, &template)
+            .with_options(ReplaceOptions::builder().upsert(true).build())
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Database error: {}", e))
+    }
+
+    /// Drops the edited version; returns whether there was one.
+    pub async fn reset(&self, def: &TemplateDef) -> Result<bool, String> {
+        self.db
+            .collection::<Document>(COLLECTION)
+            .delete_one(doc! { "_id": def.key })
+            .await
+            .map(|result| result.deleted_count > 0)
+            .map_err(|e| format!("Database error: {}", e))
+    }
+}
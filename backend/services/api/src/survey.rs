@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+use mongodb::{
+    bson::{doc, oid::ObjectId, Bson, Document},
+    options::IndexOptions,
+    Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{status_id_from_any, transition_requirements, MusteriKabul, TransitionRequirement};
+use crate::tracking;
+
+pub const COLLECTION: &str = "surveys";
+
+/// Days a survey link accepts an answer after it was created.
+const RESPONSE_DAYS: i64 = 30;
+
+pub const MIN_RATING: i32 = 1;
+pub const MAX_RATING: i32 = 5;
+pub const MAX_COMMENT_CHARS: usize = 1000;
+
+/// Report group for records no technician can be attributed to.
+const UNKNOWN_GROUP: &str = "BİLİNMİYOR";
+
+/// Satisfaction survey for one delivered record, keyed by its public token.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Survey {
+    #[serde(rename = "_id")]
+    pub token: String,
+    pub musteri_id: ObjectId,
+    /// Who finished the repair, see `technician`.
+    pub teknisyen: Option<String>,
+    pub servis_tipi: Option<String>,
+    pub delivered_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Set once the survey SMS went out.
+    pub sent_at: Option<DateTime<Utc>>,
+    pub puan: Option<i32>,
+    pub yorum: Option<String>,
+    pub answered_at: Option<DateTime<Utc>>,
+}
+
+pub fn survey_url(frontend_url: &str, token: &str) -> String {
+    format!("{}/anket/{}", frontend_url.trim_end_matches('/'), token)
+}
+
+pub async fn ensure_indexes(db: &Database) -> Result<(), String> {
+    db.collection::<Document>(COLLECTION)
+        .create_indexes(vec![
+            IndexModel::builder()
+                .keys(doc! { "musteri_id": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .name("musteri_id_1".to_string())
+                        .unique(true)
+                        .build(),
+                )
+                .build(),
+            IndexModel::builder()
+                .keys(doc! { "delivered_at": -1 })
+                .options(IndexOptions::builder().name("delivered_at_-1".to_string()).build())
+                .build(),
+        ])
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Survey index error: {}", e))
+}
+
+/// The user who last moved the record into a status that needs a technician
+/// note (değişim, tamir tamamlandı, iade), i.e. who finished the work.
+pub fn technician(musteri: &MusteriKabul) -> Option<String> {
+    musteri
+        .status_history
+        .iter()
+        .rev()
+        .find(|step| {
+            status_id_from_any(&step.to).is_some_and(|id| {
+                transition_requirements(id).contains(&TransitionRequirement::TechnicianNote)
+            })
+        })
+        .map(|step| step.actor.clone())
+}
+
+/// Creates the survey for a record that was just delivered. Returns `None`
+/// if the record already has one.
+pub async fn create(db: &Database, musteri: &MusteriKabul, musteri_id: ObjectId) -> Result<Option<Survey>, String> {
+    let now = Utc::now();
+    let survey = Survey {
+        token: tracking::generate_token(),
+        musteri_id,
+        teknisyen: technician(musteri),
+        servis_tipi: musteri.servis_tipi.clone(),
+        delivered_at: now,
+        expires_at: now + Duration::days(RESPONSE_DAYS),
+        sent_at: None,
+        puan: None,
+        yorum: None,
+        answered_at: None,
+    };
+
+    let document = mongodb::bson::to_document(&survey).map_err(|e| format!("Serialize error: {}", e))?;
+
+    // Upserted on the record id, so a repeated delivery never sends a second survey
+    let result = db
+        .collection::<Document>(COLLECTION)
+        .update_one(doc! { "musteri_id": musteri_id }, doc! { "$setOnInsert": document })
+        .upsert(true)
+        .await
+        .map_err(|e| format!("Survey insert error: {}", e))?;
+
+    Ok(result.upserted_id.map(|_| survey))
+}
+
+pub async fn mark_sent(db: &Database, musteri_id: ObjectId) -> Result<(), String> {
+    db.collection::<Document>(COLLECTION)
+        .update_one(
+            doc! { "musteri_id": musteri_id },
+            doc! { "$set": { "sent_at": Utc::now().to_rfc3339() } },
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Survey update error: {}", e))
+}
+
+pub async fn find(db: &Database, token: &str) -> Result<Option<Survey>, String> {
+    db.collection::<Survey>(COLLECTION)
+        .find_one(doc! { "_id": token })
+        .await
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Why an answer was not stored.
+#[derive(Debug)]
+pub enum AnswerError {
+    Expired,
+    AlreadyAnswered,
+    Database(String),
+}
+
+/// Stores the answer; a survey can be answered once.
+pub async fn answer(db: &Database, survey: &Survey, puan: i32, yorum: Option<String>) -> Result<(), AnswerError> {
+    if survey.answered_at.is_some() {
+        return Err(AnswerError::AlreadyAnswered);
+    }
+    if survey.expires_at < Utc::now() {
+        return Err(AnswerError::Expired);
+    }
+
+    let result = db
+        .collection::<Document>(COLLECTION)
+        .update_one(
+            doc! { "_id": &survey.token, "answered_at": Bson::Null },
+            doc! {
+                "$set": {
+                    "puan": puan,
+                    "yorum": yorum,
+                    "answered_at": Utc::now().to_rfc3339(),
+                }
+            },
+        )
+        .await
+        .map_err(|e| AnswerError::Database(format!("Database error: {}", e)))?;
+
+    if result.modified_count == 0 {
+        return Err(AnswerError::AlreadyAnswered);
+    }
+    Ok(())
+}
+
+/// Satisfaction of one technician or servis_tipi.
+#[derive(Debug, Default, Serialize)]
+pub struct SurveyGroup {
+    pub key: String,
+    pub sent: u64,
+    pub answered: u64,
+    /// Mean rating of the answered surveys.
+    pub average: Option<f64>,
+    /// Answers per rating, 1 to 5.
+    pub distribution: [u64; 5],
+}
+
+impl SurveyGroup {
+    fn add(&mut self, survey: &Survey) {
+        self.sent += 1;
+        if let Some(puan) = survey.puan.filter(|puan| (MIN_RATING..=MAX_RATING).contains(puan)) {
+            self.answered += 1;
+            self.distribution[(puan - MIN_RATING) as usize] += 1;
+        }
+    }
+
+    fn finish(mut self) -> Self {
+        let total: u64 = self
+            .distribution
+            .iter()
+            .enumerate()
+            .map(|(index, count)| (index as u64 + MIN_RATING as u64) * count)
+            .sum();
+        self.average = (self.answered > 0).then(|| total as f64 / self.answered as f64);
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SurveyReport {
+    pub overall: SurveyGroup,
+    pub by_teknisyen: Vec<SurveyGroup>,
+    pub by_servis_tipi: Vec<SurveyGroup>,
+}
+
+fn group_key(value: Option<&String>) -> String {
+    value
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| UNKNOWN_GROUP.to_string())
+}
+
+/// Report over the surveys sent for records delivered between `from` and
+/// `to` (RFC3339 bounds, either may be open).
+pub async fn report(db: &Database, from: Option<String>, to: Option<String>) -> Result<SurveyReport, String> {
+    let mut filter = doc! { "sent_at": { "$type": "string" } };
+    let mut delivered = Document::new();
+    if let Some(from) = from {
+        delivered.insert("$gte", from);
+    }
+    if let Some(to) = to {
+        delivered.insert("$lt", to);
+    }
+    if !delivered.is_empty() {
+        filter.insert("delivered_at", delivered);
+    }
+
+    let mut cursor = db
+        .collection::<Survey>(COLLECTION)
+        .find(filter)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    let mut overall = SurveyGroup {
+        key: "TOPLAM".to_string(),
+        ..Default::default()
+    };
+    let mut by_teknisyen: BTreeMap<String, SurveyGroup> = BTreeMap::new();
+    let mut by_servis_tipi: BTreeMap<String, SurveyGroup> = BTreeMap::new();
+
+    while cursor
+        .advance()
+        .await
+        .map_err(|e| format!("Cursor error: {}", e))?
+    {
+        let survey = cursor
+            .deserialize_current()
+            .map_err(|e| format!("Deserialize error: {}", e))?;
+
+        overall.add(&survey);
+        for (groups, key) in [
+            (&mut by_teknisyen, group_key(survey.teknisyen.as_ref())),
+            (&mut by_servis_tipi, group_key(survey.servis_tipi.as_ref())),
+        ] {
+            groups
+                .entry(key.clone())
+                .or_insert_with(|| SurveyGroup {
+                    key,
+                    ..Default::default()
+                })
+                .add(&survey);
+        }
+    }
+
+    let finish = |groups: BTreeMap<String, SurveyGroup>| groups.into_values().map(SurveyGroup::finish).collect();
+    Ok(SurveyReport {
+        overall: overall.finish(),
+        by_teknisyen: finish(by_teknisyen),
+        by_servis_tipi: finish(by_servis_tipi),
+    })
+}
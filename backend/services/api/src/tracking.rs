@@ -0,0 +1,235 @@
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use mongodb::{
+    bson::{doc, Document},
+    options::IndexOptions,
+    Database, IndexModel,
+};
+use rand::Rng;
+use serde::Serialize;
+
+use crate::models::{status_id_from_any, MusteriKabul};
+
+/// Record field holding the public tracking token.
+pub const TOKEN_FIELD: &str = "takip_token";
+
+/// Random base62 characters per token (~71 bits); short enough for an SMS.
+const TOKEN_LENGTH: usize = 12;
+const TOKEN_ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Pickup windows stated in the status SMS texts.
+const REPAIRED_PICKUP_BUSINESS_DAYS: u32 = 20;
+const RETURNED_PICKUP_DAYS: i64 = 20;
+
+pub fn generate_token() -> String {
+    let mut rng = rand::thread_rng();
+    (0..TOKEN_LENGTH)
+        .map(|_| TOKEN_ALPHABET[rng.gen_range(0..TOKEN_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Tokens are plain base62; anything else is rejected before hitting the database.
+pub fn is_valid_token(token: &str) -> bool {
+    token.len() == TOKEN_LENGTH && token.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+pub fn tracking_url(frontend_url: &str, token: &str) -> String {
+    format!("{}/takip/{}", frontend_url.trim_end_matches('/'), token)
+}
+
+pub async fn ensure_index(db: &Database) -> Result<(), String> {
+    db.collection::<Document>("musteri_kabul")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { TOKEN_FIELD: 1 })
+                .options(
+                    IndexOptions::builder()
+                        .name(format!("{}_1", TOKEN_FIELD))
+                        .unique(true)
+                        .partial_filter_expression(doc! { TOKEN_FIELD: { "$type": "string" } })
+                        .build(),
+                )
+                .build(),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Takip index error: {}", e))
+}
+
+/// Status wording for customers, without the internal workflow jargon.
+pub fn customer_status_label(status_id: i32) -> &'static str {
+    match status_id {
+        1 => "Cihazınız teslim alındı",
+        2 => "Teknisyen incelemesinde",
+        3 => "İşlem bekliyor",
+        4 => "Parça bekleniyor",
+        5 => "Yetkili servise gönderildi",
+        6 => "Değişim işlemi yapılıyor",
+        7 => "Tamir tamamlandı, teslim almaya hazır",
+        8 => "Teslim edildi",
+        9 => "İade için hazır",
+        _ => "Bilinmiyor",
+    }
+}
+
+fn add_business_days(start: DateTime<Utc>, days: u32) -> DateTime<Utc> {
+    let mut current = start;
+    let mut remaining = days;
+    while remaining > 0 {
+        current += Duration::days(1);
+        if !matches!(current.weekday(), Weekday::Sat | Weekday::Sun) {
+            remaining -= 1;
+        }
+    }
+    current
+}
+
+/// Status changes of a record, oldest first. Records created before the
+/// history existed only know their current status; it is dated by the last
+/// update unless the record is still at intake.
+fn status_timeline(musteri: &MusteriKabul) -> Vec<(i32, DateTime<Utc>)> {
+    let mut timeline: Vec<(i32, DateTime<Utc>)> = musteri
+        .status_history
+        .iter()
+        .filter_map(|step| {
+            let at = DateTime::<Utc>::from_timestamp_millis(step.at.timestamp_millis())?;
+            Some((status_id_from_any(&step.to)?, at))
+        })
+        .collect();
+    if timeline.is_empty() {
+        let status_id = status_id_from_any(&musteri.status).unwrap_or(0);
+        let at = if status_id == 1 { musteri.created_at } else { musteri.updated_at };
+        timeline.push((status_id, at));
+    }
+    timeline
+}
+
+/// A device ready to be picked up (status 7 or 9).
+#[derive(Debug, Clone, Copy)]
+pub struct PickupWindow {
+    pub status_id: i32,
+    /// When the record entered its current status.
+    pub ready_since: DateTime<Utc>,
+    /// Last day stated in the status SMS.
+    pub deadline: DateTime<Utc>,
+}
+
+fn pickup_window_in(
+    musteri: &MusteriKabul,
+    status_id: i32,
+    timeline: &[(i32, DateTime<Utc>)],
+) -> Option<PickupWindow> {
+    let ready_since = timeline
+        .iter()
+        .rev()
+        .find(|(id, _)| *id == status_id)
+        .map(|(_, at)| *at)
+        .unwrap_or(musteri.updated_at);
+    let deadline = match status_id {
+        7 => add_business_days(ready_since, REPAIRED_PICKUP_BUSINESS_DAYS),
+        9 => ready_since + Duration::days(RETURNED_PICKUP_DAYS),
+        _ => return None,
+    };
+    Some(PickupWindow {
+        status_id,
+        ready_since,
+        deadline,
+    })
+}
+
+pub fn pickup_window(musteri: &MusteriKabul) -> Option<PickupWindow> {
+    let status_id = status_id_from_any(&musteri.status)?;
+    pickup_window_in(musteri, status_id, &status_timeline(musteri))
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackingStep {
+    pub status: &'static str,
+    pub at: String,
+}
+
+/// Public view of a record: nothing that identifies the customer beyond a
+/// masked name, no phone and no documents.
+#[derive(Debug, Serialize)]
+pub struct TrackingResponse {
+    pub ad_soyad: String,
+    pub cihaz: String,
+    pub status: &'static str,
+    pub timeline: Vec<TrackingStep>,
+    /// Last day to pick the device up, once it is ready (status 7 or 9).
+    pub teslim_son_tarih: Option<String>,
+    pub created_at: String,
+}
+
+impl TrackingResponse {
+    pub fn from_musteri(musteri: &MusteriKabul) -> Self {
+        let status_id = status_id_from_any(&musteri.status).unwrap_or(0);
+
+        let timeline = status_timeline(musteri);
+        let deadline = pickup_window_in(musteri, status_id, &timeline).map(|window| window.deadline);
+
+        Self {
+            ad_soyad: crate::fatura_link::mask_name(&musteri.ad_soyad),
+            cihaz: musteri.marka_model.clone(),
+            status: customer_status_label(status_id),
+            timeline: timeline
+                .into_iter()
+                .map(|(id, at)| TrackingStep {
+                    status: customer_status_label(id),
+                    at: at.to_rfc3339(),
+                })
+                .collect(),
+            teslim_son_tarih: deadline.map(|at| at.date_naive().to_string()),
+            created_at: musteri.created_at.to_rfc3339(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Record written before `status_history` existed.
+    fn legacy_record(status: &str) -> MusteriKabul {
+        mongodb::bson::from_document(doc! {
+            "ad_soyad": "AHMET YILMAZ",
+            "telefon": "05551234567",
+            "marka_model": "SAMSUNG TV",
+            "aksesuarlar": "",
+            "musteri_sikayeti": "GÖRÜNTÜ YOK",
+            "status": status,
+            "sms_gonderildi": true,
+            "created_at": "2026-09-01T08:00:00+00:00",
+            "updated_at": "2026-10-12T08:00:00+00:00",
+        })
+        .unwrap()
+    }
+
+    fn at(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn legacy_ready_record_counts_from_last_update() {
+        let window = pickup_window(&legacy_record("TAMIR_TAMAMLANDI")).unwrap();
+        assert_eq!(window.status_id, 7);
+        assert_eq!(window.ready_since, at("2026-10-12T08:00:00+00:00"));
+        assert_eq!(window.deadline, at("2026-11-09T08:00:00+00:00"));
+
+        let tracking = TrackingResponse::from_musteri(&legacy_record("TAMIR_TAMAMLANDI"));
+        assert_eq!(tracking.teslim_son_tarih.as_deref(), Some("2026-11-09"));
+    }
+
+    #[test]
+    fn legacy_returned_record_counts_from_last_update() {
+        let window = pickup_window(&legacy_record("IADE")).unwrap();
+        assert_eq!(window.ready_since, at("2026-10-12T08:00:00+00:00"));
+        assert_eq!(window.deadline, at("2026-11-01T08:00:00+00:00"));
+    }
+
+    #[test]
+    fn legacy_intake_record_keeps_its_intake_date() {
+        let record = legacy_record("MÜŞTERI_KABUL");
+        assert_eq!(status_timeline(&record), vec![(1, record.created_at)]);
+        assert!(pickup_window(&record).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use mongodb::{
+    bson::{doc, Bson, DateTime, Document},
+    Database,
+};
+
+use crate::attachments::{self, AttachmentStore};
+
+/// Collections that use soft delete (`deleted`, `deleted_at`, `deleted_by`).
+pub const SOFT_DELETE_COLLECTIONS: [&str; 2] = ["musteri_kabul", "montaj_kayitlari"];
+
+/// Adds the "not in trash" condition to a query filter.
+pub fn active(mut filter: Document) -> Document {
+    filter.insert("deleted", doc! { "$ne": true });
+    filter
+}
+
+/// `$set` body that moves a record to the trash.
+pub fn soft_delete_update(username: &str) -> Document {
+    doc! {
+        "$set": {
+            "deleted": true,
+            "deleted_at": DateTime::now(),
+            "deleted_by": username,
+            "updated_at": chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Update body that takes a record back out of the trash.
+pub fn restore_update(username: &str) -> Document {
+    doc! {
+        "$set": {
+            "restored_at": DateTime::now(),
+            "restored_by": username,
+            "updated_at": chrono::Utc::now().to_rfc3339(),
+        },
+        "$unset": {
+            "deleted": "",
+            "deleted_at": "",
+            "deleted_by": "",
+        }
+    }
+}
+
+/// Hourly cleanup: records past their trash retention, then attachments that
+/// were uploaded but never linked to a record within `orphan_hours`.
+pub fn start_trash_purge_worker(db: Database, store: AttachmentStore, retention_days: i64, orphan_hours: i64) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = purge_expired_trash(&db, &store, retention_days).await {
+                tracing::error!("Trash purge worker error: {}", e);
+            }
+            match store.sweep_unlinked(&db, orphan_hours).await {
+                Ok(removed) if removed > 0 => println!("🗑️ Hiçbir kayda bağlanmamış ekler silindi: {}", removed),
+                Ok(_) => {}
+                Err(e) => tracing::error!("Unlinked attachment sweep error: {}", e),
+            }
+            tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+        }
+    });
+}
+
+/// Deletes records that have been in the trash longer than `retention_days`,
+/// then the attachments only they used.
+async fn purge_expired_trash(db: &Database, store: &AttachmentStore, retention_days: i64) -> Result<(), String> {
+    let cutoff = DateTime::from_millis(
+        (chrono::Utc::now() - chrono::Duration::days(retention_days)).timestamp_millis(),
+    );
+    let expired = doc! {
+        "deleted": true,
+        "deleted_at": { "$lt": cutoff },
+    };
+
+    for name in SOFT_DELETE_COLLECTIONS {
+        let collection = db.collection::<Document>(name);
+        let mut cursor = collection
+            .find(expired.clone())
+            .await
+            .map_err(|e| format!("Purge error ({}): {}", name, e))?;
+
+        let mut record_ids: Vec<Bson> = Vec::new();
+        let mut attachment_ids: Vec<String> = Vec::new();
+        while cursor
+            .advance()
+            .await
+            .map_err(|e| format!("Cursor error: {}", e))?
+        {
+            let record: Document = cursor
+                .deserialize_current()
+                .map_err(|e| format!("Deserialize error: {}", e))?;
+            if let Some(id) = record.get("_id") {
+                record_ids.push(id.clone());
+            }
+            attachment_ids.extend(attachments::record_attachment_ids(&record));
+        }
+        if record_ids.is_empty() {
+            continue;
+        }
+
+        // Still filtered on the trash state, in case a record was restored meanwhile
+        let mut filter = expired.clone();
+        filter.insert("_id", doc! { "$in": record_ids });
+        let result = collection
+            .delete_many(filter)
+            .await
+            .map_err(|e| format!("Purge error ({}): {}", name, e))?;
+
+        if result.deleted_count > 0 {
+            println!("🗑️ Çöp kutusundan kalıcı silindi: {} kayıt ({})", result.deleted_count, name);
+        }
+
+        let removed = store.delete_unreferenced(db, &attachment_ids).await?;
+        if removed > 0 {
+            println!("🗑️ Kullanılmayan ekler silindi: {} ({})", removed, name);
+        }
+    }
+
+    Ok(())
+}
@@ -75,6 +75,25 @@ log "Backend build"
 cd "$APP_DIR/backend"
 cargo build --release --bin api
 
+ENV_FILE="/etc/sis-teknik/api.env"
+if [[ ! -f "$ENV_FILE" ]]; then
+  log "Environment file creating: $ENV_FILE"
+  $SUDO install -d -m 755 /etc/sis-teknik
+  sed -e "s|^API_PORT=.*|API_PORT=$API_PORT|" \
+    -e "s|^MONGODB_URI=.*|MONGODB_URI=$MONGODB_URI|" \
+    -e "s|^MONGODB_DATABASE=.*|MONGODB_DATABASE=$MONGODB_DATABASE|" \
+    -e "s|^FRONTEND_URL=.*|FRONTEND_URL=$FRONTEND_URL|" \
+    "$APP_DIR/deploy/almalinux/api.env.example" | $SUDO tee "$ENV_FILE" >/dev/null
+  $SUDO chmod 600 "$ENV_FILE"
+fi
+
+missing=()
+for key in JWT_SECRET ADMIN_PASSWORD ENCRYPTION_KEY DELETE_OTP_PHONE; do
+  if ! $SUDO grep -qE "^$key=.+" "$ENV_FILE"; then
+    missing+=("$key")
+  fi
+done
+
 log "Systemd service writing"
 $SUDO tee /etc/systemd/system/sis-teknik-api.service >/dev/null <<EOF
 [Unit]
@@ -89,18 +108,20 @@ WorkingDirectory=$APP_DIR
 ExecStart=$APP_DIR/backend/target/release/api
 Restart=always
 RestartSec=3
-Environment=API_PORT=$API_PORT
-Environment=MONGODB_URI=$MONGODB_URI
-Environment=MONGODB_DATABASE=$MONGODB_DATABASE
-Environment=FRONTEND_URL=$FRONTEND_URL
+EnvironmentFile=$ENV_FILE
 
 [Install]
 WantedBy=multi-user.target
 EOF
 
 $SUDO systemctl daemon-reload
-$SUDO systemctl enable --now sis-teknik-api
-$SUDO systemctl restart sis-teknik-api
+if (( ${#missing[@]} > 0 )); then
+  log "UYARI: $ENV_FILE içinde eksik değerler: ${missing[*]} — doldurup 'systemctl restart sis-teknik-api' çalıştırın"
+  $SUDO systemctl enable sis-teknik-api
+else
+  $SUDO systemctl enable --now sis-teknik-api
+  $SUDO systemctl restart sis-teknik-api
+fi
 $SUDO systemctl --no-pager --full status sis-teknik-api | sed -n '1,20p'
 
 log "OpenLiteSpeed installing and configuring"
@@ -22,6 +22,69 @@ const isSameData = (a, b) => {
   }
 };
 
+const LIST_PAGE_SIZE = 50;
+const LIST_MAX_LIMIT = 200;
+
+// Sayfalı liste endpoint'inden tek sayfa çek: { items, total, next_cursor }.
+// Hata olursa null döner.
+const fetchListPage = async (url, { cursor = null, limit = LIST_PAGE_SIZE } = {}) => {
+  const separator = url.includes('?') ? '&' : '?';
+  const pageUrl = `${url}${separator}limit=${limit}${cursor ? `&cursor=${encodeURIComponent(cursor)}` : ''}`;
+  const response = await fetch(pageUrl);
+  if (!response.ok) return null;
+  const page = await response.json();
+  return {
+    items: Array.isArray(page?.items) ? page.items : [],
+    total: Number(page?.total) || 0,
+    next_cursor: page?.next_cursor || null,
+  };
+};
+
+// Yenilemede ilk sayfa, o ana kadar yüklenen kayıt sayısı kadar (en fazla 200) tekrar çekilir;
+// böylece "Daha fazla" ile açılan kayıtlar her yenilemede kaybolmaz.
+const refreshLimit = (loadedCount) => Math.min(LIST_MAX_LIMIT, Math.max(LIST_PAGE_SIZE, loadedCount));
+
+// Dosyayı base64'e çevirmeden ek deposuna yükle, { id, url } döner.
+const uploadAttachment = async (file) => {
+  const response = await fetch(`/api/attachments?filename=${encodeURIComponent(file.name || '')}`, {
+    method: 'POST',
+    headers: { 'Content-Type': file.type || 'application/octet-stream' },
+    body: file,
+  });
+  if (!response.ok) {
+    throw new Error((await response.text()) || 'Dosya yüklenemedi');
+  }
+  return response.json();
+};
+
+// Detay endpoint'leri belge alanlarında önizleme (thumbnail) URL'si döner;
+// aynı imzalı sorgu tam boy dosya için de geçerlidir.
+const fullAttachmentUrl = (url) => (url ? url.replace('/thumbnail?', '?') : url);
+
+// Silme işlemleri için SMS OTP iste, kodu kullanıcıdan al ve istek header'larını döndür.
+// Kullanıcı vazgeçerse veya kod gönderilemezse null döner.
+const requestDeleteOtpHeaders = async (action, resourceId) => {
+  const response = await fetch('/api/delete-otp/request', {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ action, resource_id: resourceId }),
+  });
+
+  if (!response.ok) {
+    alert((await response.text()) || 'Onay kodu gönderilemedi.');
+    return null;
+  }
+
+  const { request_id: requestId } = await response.json();
+  const code = window.prompt('Silme onay kodunu girin (SMS ile gönderildi):');
+  if (!code || !code.trim()) return null;
+
+  return {
+    'x-delete-otp-id': requestId,
+    'x-delete-otp-code-1': code.trim(),
+  };
+};
+
 function useDataRefreshListener(callback, deps = []) {
   const callbackRef = useRef(callback);
 
@@ -39,6 +102,52 @@ function useDataRefreshListener(callback, deps = []) {
   }, deps);
 }
 
+// Sayfalı listelerin altındaki sayaç ve "Daha fazla" düğmesi.
+function LoadMoreButton({ shown, total, hasMore, loading, onClick }) {
+  return (
+    <div className="flex flex-col items-center gap-2 mt-6">
+      <p className="text-sm text-gray-500">{total} kayıttan {shown} tanesi gösteriliyor</p>
+      {hasMore && (
+        <button
+          type="button"
+          onClick={onClick}
+          disabled={loading}
+          className="px-6 py-2 rounded-full border border-gray-300 text-sm font-medium text-gray-700 hover:bg-gray-50 disabled:opacity-50"
+        >
+          {loading ? 'Yükleniyor...' : 'Daha fazla'}
+        </button>
+      )}
+    </div>
+  );
+}
+
+// Kabul ve montaj formlarındaki SMS izinleri. Bilgilendirme SMS'i (kabul, durum, montaj)
+// varsayılan olarak açık; kampanya SMS'i yalnızca müşteri açıkça onaylarsa işaretlenir.
+function ConsentFields({ smsIzni, pazarlamaIzni, onChange }) {
+  return (
+    <div className="space-y-2">
+      <label className="flex items-start gap-2 text-sm text-gray-700 cursor-pointer">
+        <input
+          type="checkbox"
+          checked={smsIzni}
+          onChange={(e) => onChange('smsIzni', e.target.checked)}
+          className="mt-0.5 w-4 h-4 rounded border-gray-300"
+        />
+        <span>Müşteri cihazıyla ilgili bilgilendirme SMS'lerini almayı kabul ediyor</span>
+      </label>
+      <label className="flex items-start gap-2 text-sm text-gray-700 cursor-pointer">
+        <input
+          type="checkbox"
+          checked={pazarlamaIzni}
+          onChange={(e) => onChange('pazarlamaIzni', e.target.checked)}
+          className="mt-0.5 w-4 h-4 rounded border-gray-300"
+        />
+        <span>Müşteri kampanya ve duyuru SMS'lerini almayı kabul ediyor</span>
+      </label>
+    </div>
+  );
+}
+
 // Menu Item Component - MD3 Style
 function MenuItem({ to, iconName, label, children, themeColor, isLogout, horizontal }) {
   const location = useLocation();
@@ -167,9 +276,8 @@ function Home() {
   const [stats, setStats] = useState({});
   const [searchText, setSearchText] = useState('');
   const [searchPlaceholder, setSearchPlaceholder] = useState('Hoş Geldin Usta');
-  const [allCustomers, setAllCustomers] = useState([]);
   const [searchResults, setSearchResults] = useState([]);
-  const homeCacheRef = useRef({ stats: null, customers: null });
+  const homeCacheRef = useRef({ stats: null });
 
   const formatDisplayName = (value) => {
     return String(value || '')
@@ -217,10 +325,7 @@ function Home() {
   const fetchHomeData = async () => {
     let hasChanged = false;
     try {
-      const [statsResponse, customersResponse] = await Promise.all([
-        fetch('/api/musteri-kabul/stats'),
-        fetch('/api/musteri-kabul')
-      ]);
+      const statsResponse = await fetch('/api/musteri-kabul/stats');
 
       if (statsResponse.ok) {
         const statsData = await statsResponse.json();
@@ -230,16 +335,6 @@ function Home() {
           hasChanged = true;
         }
       }
-
-      if (customersResponse.ok) {
-        const customersData = await customersResponse.json();
-        const normalizedCustomers = Array.isArray(customersData) ? customersData : [];
-        if (!isSameData(homeCacheRef.current.customers, normalizedCustomers)) {
-          homeCacheRef.current.customers = normalizedCustomers;
-          setAllCustomers(normalizedCustomers);
-          hasChanged = true;
-        }
-      }
     } catch (err) {
       console.error('Home live data fetch error:', err);
     }
@@ -251,20 +346,35 @@ function Home() {
   }, []);
   useDataRefreshListener(fetchHomeData, []);
 
-  // Handle search
+  // Handle search: telefon numarası gibi görünen aramalar telefon indeksine,
+  // diğerleri sunucu taraflı metin aramasına gider
   useEffect(() => {
-    if (searchText.trim() === '') {
+    const query = searchText.trim();
+    if (query === '') {
       setSearchResults([]);
-    } else {
-      const query = searchText.toLowerCase();
-      const filtered = allCustomers.filter(customer =>
-        customer.ad_soyad?.toLowerCase().includes(query) ||
-        customer.telefon?.toLowerCase().includes(query) ||
-        customer.marka_model?.toLowerCase().includes(query)
-      );
-      setSearchResults(filtered);
+      return;
     }
-  }, [searchText, allCustomers]);
+
+    let cancelled = false;
+    const timer = setTimeout(async () => {
+      const digits = query.replace(/\D/g, '');
+      const isPhone = digits.length >= 10 && /^[\d\s()+-]+$/.test(query);
+      const params = new URLSearchParams(isPhone ? { telefon: query } : { q: query });
+      try {
+        const response = await fetch(`/api/musteri-kabul/search?${params}`);
+        const data = response.ok ? await response.json() : [];
+        if (!cancelled) setSearchResults(Array.isArray(data) ? data : []);
+      } catch (err) {
+        console.error('Search error:', err);
+        if (!cancelled) setSearchResults([]);
+      }
+    }, 300);
+
+    return () => {
+      cancelled = true;
+      clearTimeout(timer);
+    };
+  }, [searchText]);
   
   const handleCardClick = (statusId) => {
     navigate(`/status/${statusId}`);
@@ -465,6 +575,9 @@ function StatusList({showBelgeModal, setShowBelgeModal, selectedBelgeData, setSe
   const [showBulkActions, setShowBulkActions] = useState(false);
   const [showEditModal, setShowEditModal] = useState(false);
   const [editingItem, setEditingItem] = useState(null);
+  const [listTotal, setListTotal] = useState(0);
+  const [nextCursor, setNextCursor] = useState(null);
+  const [isLoadingMore, setIsLoadingMore] = useState(false);
   const statusListCacheRef = useRef(null);
   const hasLoadedOnceRef = useRef(false);
   const [editForm, setEditForm] = useState({
@@ -583,8 +696,15 @@ function StatusList({showBelgeModal, setShowBelgeModal, selectedBelgeData, setSe
     try {
       for (const id of selectedIds) {
         try {
+          const otpHeaders = await requestDeleteOtpHeaders('musteri', id);
+          if (!otpHeaders) {
+            failedCount += 1;
+            continue;
+          }
+
           const response = await fetch(`/api/musteri-kabul/${id}`, {
             method: 'DELETE',
+            headers: otpHeaders,
           });
 
           if (response.ok || response.status === 204) {
@@ -636,9 +756,12 @@ function StatusList({showBelgeModal, setShowBelgeModal, selectedBelgeData, setSe
 
     let hasChanged = false;
     try {
-      const response = await fetch(`/api/musteri-kabul/by-status/${status}`);
-      const data = await response.json();
-      const normalizedData = Array.isArray(data) ? data : [];
+      const page = await fetchListPage(`/api/musteri-kabul/by-status/${status}`, {
+        limit: refreshLimit(statusListCacheRef.current?.length || 0),
+      });
+      const normalizedData = page?.items || [];
+      setListTotal(page?.total || 0);
+      setNextCursor(page?.next_cursor || null);
       if (!isSameData(statusListCacheRef.current, normalizedData)) {
         statusListCacheRef.current = normalizedData;
         setStatusList(normalizedData);
@@ -653,6 +776,25 @@ function StatusList({showBelgeModal, setShowBelgeModal, selectedBelgeData, setSe
     return hasChanged;
   };
 
+  const loadMoreStatusList = async () => {
+    if (!nextCursor || isLoadingMore) return;
+    setIsLoadingMore(true);
+    try {
+      const page = await fetchListPage(`/api/musteri-kabul/by-status/${status}`, { cursor: nextCursor });
+      if (page) {
+        const merged = [...(statusListCacheRef.current || []), ...page.items];
+        statusListCacheRef.current = merged;
+        setStatusList(merged);
+        setListTotal(page.total);
+        setNextCursor(page.next_cursor);
+      }
+    } catch (err) {
+      console.error('List fetch error:', err);
+    } finally {
+      setIsLoadingMore(false);
+    }
+  };
+
   const handleEditSubmit = async (e) => {
     e.preventDefault();
     if (!editForm.ad_soyad.trim()) {
@@ -700,9 +842,13 @@ function StatusList({showBelgeModal, setShowBelgeModal, selectedBelgeData, setSe
     }
 
     try {
+      const otpHeaders = await requestDeleteOtpHeaders('musteri', id);
+      if (!otpHeaders) return;
+
       console.log('DELETE isteği gönderiliyor:', id);
       const response = await fetch(`/api/musteri-kabul/${id}`, {
         method: 'DELETE',
+        headers: otpHeaders,
       });
 
       console.log('DELETE response status:', response.status, response.statusText);
@@ -751,6 +897,7 @@ function StatusList({showBelgeModal, setShowBelgeModal, selectedBelgeData, setSe
   };
   
   useEffect(() => {
+    statusListCacheRef.current = null;
     fetchStatusList();
   }, [status]);
 
@@ -777,7 +924,7 @@ function StatusList({showBelgeModal, setShowBelgeModal, selectedBelgeData, setSe
           <span className="material-icons">arrow_back</span>
         </button>
         <div className="flex flex-col">
-          <span className="text-sm font-medium text-gray-500">Durum Listesi</span>
+          <span className="text-sm font-medium text-gray-500">Durum Listesi{!isLoading && ` · ${listTotal} kayıt`}</span>
           <h2 className="text-2xl font-bold text-gray-900">{getStatusLabel(status)}</h2>
         </div>
       </div>
@@ -1048,6 +1195,14 @@ function StatusList({showBelgeModal, setShowBelgeModal, selectedBelgeData, setSe
               </div>
             ))}
           </div>
+
+          <LoadMoreButton
+            shown={statusList.length}
+            total={listTotal}
+            hasMore={Boolean(nextCursor)}
+            loading={isLoadingMore}
+            onClick={loadMoreStatusList}
+          />
         </>
       )}
 
@@ -1067,6 +1222,8 @@ function MusteriKabul({showBelgeModal, setShowBelgeModal, selectedBelgeData, set
     aksesuarlar: '',
     museriSikayeti: '',
     not: '',
+    smsIzni: true,
+    pazarlamaIzni: false,
     belge_f: null,
     belge_g: null,
     belge_u: null,
@@ -1201,6 +1358,8 @@ function MusteriKabul({showBelgeModal, setShowBelgeModal, selectedBelgeData, set
         aksesuarlar: formData.aksesuarlar,
         musteri_sikayeti: formData.museriSikayeti,
         not: formData.not || null,
+        sms_izni: formData.smsIzni,
+        pazarlama_izni: formData.pazarlamaIzni,
         belge_f: belge_f,
         belge_g: belge_g,
         belge_u: belge_u,
@@ -1377,6 +1536,16 @@ function MusteriKabul({showBelgeModal, setShowBelgeModal, selectedBelgeData, set
                    <label className="block text-xs font-bold text-gray-500 uppercase tracking-wider mb-2 group-focus-within:text-primary transition-colors">Not (Varsa)</label>
                    <textarea name="not" value={formData.not} onChange={handleChange} placeholder="Ek bilgi veya hatırlatmalar." rows="2" className="w-full px-4 py-3 bg-gray-50 border-gray-200 rounded-xl focus:bg-white focus:ring-2 focus:ring-primary/20 focus:border-primary transition-all font-medium text-gray-900 placeholder:text-gray-400 resize-none outline-none" />
                  </div>
+
+                 {/* SMS İzinleri */}
+                 <div>
+                   <label className="block text-xs font-bold text-gray-500 uppercase tracking-wider mb-2">SMS İzinleri</label>
+                   <ConsentFields
+                     smsIzni={formData.smsIzni}
+                     pazarlamaIzni={formData.pazarlamaIzni}
+                     onChange={(field, checked) => setFormData((prev) => ({ ...prev, [field]: checked }))}
+                   />
+                 </div>
              </div>
 
              {/* Submit Button */}
@@ -1468,6 +1637,9 @@ function MontajEkle() {
   const [montajList, setMontajList] = useState([]);
   const [level3Users, setLevel3Users] = useState([]);
   const [montajLoading, setMontajLoading] = useState(true);
+  const [montajTotal, setMontajTotal] = useState(0);
+  const [montajCursor, setMontajCursor] = useState(null);
+  const [montajLoadingMore, setMontajLoadingMore] = useState(false);
   const [actionMenu, setActionMenu] = useState(null);
   const [showEditModal, setShowEditModal] = useState(false);
   const [editMontajId, setEditMontajId] = useState('');
@@ -1549,8 +1721,12 @@ function MontajEkle() {
     if (!confirmed) return;
 
     try {
+      const otpHeaders = await requestDeleteOtpHeaders('montaj', id);
+      if (!otpHeaders) return;
+
       const response = await fetch(`/api/montaj/${id}`, {
         method: 'DELETE',
+        headers: otpHeaders,
       });
 
       if (!response.ok) {
@@ -1565,9 +1741,27 @@ function MontajEkle() {
     }
   };
 
-  const handleDownloadMontajZip = (id) => {
+  const handleDownloadMontajZip = async (id) => {
     if (!id) return;
-    window.open(`/api/montaj/${id}/download-zip`, '_blank');
+    try {
+      const response = await fetch(`/api/montaj/${id}/download-zip`);
+      if (!response.ok) {
+        alert((await response.text()) || 'Dosyalar indirilemedi.');
+        return;
+      }
+
+      const disposition = response.headers.get('Content-Disposition') || '';
+      const filename = disposition.match(/filename="([^"]+)"/)?.[1] || 'montaj.zip';
+      const blobUrl = URL.createObjectURL(await response.blob());
+      const link = document.createElement('a');
+      link.href = blobUrl;
+      link.download = filename;
+      link.click();
+      URL.revokeObjectURL(blobUrl);
+    } catch (error) {
+      console.error('Montaj zip download error:', error);
+      alert('Dosyalar indirilemedi.');
+    }
   };
 
   const closeActionMenu = () => {
@@ -1594,17 +1788,37 @@ function MontajEkle() {
   const fetchMontajList = async () => {
     setMontajLoading(true);
     try {
-      const response = await fetch('/api/montaj');
-      const data = await response.json();
-      setMontajList(Array.isArray(data) ? data : []);
+      const page = await fetchListPage('/api/montaj', { limit: refreshLimit(montajList.length) });
+      setMontajList(page?.items || []);
+      setMontajTotal(page?.total || 0);
+      setMontajCursor(page?.next_cursor || null);
     } catch (error) {
       console.error('Montaj liste fetch error:', error);
       setMontajList([]);
+      setMontajTotal(0);
+      setMontajCursor(null);
     } finally {
       setMontajLoading(false);
     }
   };
 
+  const loadMoreMontaj = async () => {
+    if (!montajCursor || montajLoadingMore) return;
+    setMontajLoadingMore(true);
+    try {
+      const page = await fetchListPage('/api/montaj', { cursor: montajCursor });
+      if (page) {
+        setMontajList((prev) => [...prev, ...page.items]);
+        setMontajTotal(page.total);
+        setMontajCursor(page.next_cursor);
+      }
+    } catch (error) {
+      console.error('Montaj liste fetch error:', error);
+    } finally {
+      setMontajLoadingMore(false);
+    }
+  };
+
   const fetchLevel3Users = async () => {
     try {
       const response = await fetch('/api/users');
@@ -1649,7 +1863,9 @@ function MontajEkle() {
        </div>
 
        <div>
-         <h3 className="text-lg font-semibold text-gray-900 mb-4">Montaj Kayıtları</h3>
+         <h3 className="text-lg font-semibold text-gray-900 mb-4">
+           Montaj Kayıtları{!montajLoading && <span className="ml-2 text-sm font-normal text-gray-500">{montajTotal} kayıt</span>}
+         </h3>
 
          {montajLoading ? (
            <p className="text-center text-gray-500">Yükleniyor...</p>
@@ -1758,6 +1974,14 @@ function MontajEkle() {
                ))}
              </div>
 
+             <LoadMoreButton
+               shown={montajList.length}
+               total={montajTotal}
+               hasMore={Boolean(montajCursor)}
+               loading={montajLoadingMore}
+               onClick={loadMoreMontaj}
+             />
+
              {actionMenu && (
                (() => {
                  const selectedItem = montajList.find((entry) => entry.id === actionMenu.id);
@@ -1961,7 +2185,9 @@ function MontaEklePage() {
     model: '',
     telefon: '',
     adres: '',
-    servisTipi: ''
+    servisTipi: '',
+    smsIzni: true,
+    pazarlamaIzni: false
   });
 
   const ensureLeadingZero = (value) => {
@@ -2000,6 +2226,8 @@ function MontaEklePage() {
       telefon: normalizedPhone,
       adres: montajForm.adres?.trim() || null,
       servis_tipi: montajForm.servisTipi,
+      sms_izni: montajForm.smsIzni,
+      pazarlama_izni: montajForm.pazarlamaIzni,
     };
 
     setIsSaving(true);
@@ -2128,6 +2356,15 @@ function MontaEklePage() {
             </div>
           </div>
 
+          <div className="md:col-span-2">
+            <label className="block text-sm font-medium text-gray-700 mb-2">SMS İzinleri</label>
+            <ConsentFields
+              smsIzni={montajForm.smsIzni}
+              pazarlamaIzni={montajForm.pazarlamaIzni}
+              onChange={handleInputChange}
+            />
+          </div>
+
           <div className="pt-2 flex justify-end gap-2 md:col-span-2">
             <button
               type="button"
@@ -2191,30 +2428,50 @@ function CihazKurulum() {
   const [closeProgressText, setCloseProgressText] = useState('');
   const [showFaturaModal, setShowFaturaModal] = useState(false);
   const [selectedFatura, setSelectedFatura] = useState(null);
+  const [total, setTotal] = useState(0);
+  const [nextCursor, setNextCursor] = useState(null);
+  const [loadingMore, setLoadingMore] = useState(false);
   const currentUsername = (localStorage.getItem('username') || '').trim().toLowerCase();
+  // Yalnızca bu kullanıcıya atanmış, kapatılmamış montajlar
+  const assignedUrl = `/api/montaj?atanan=${encodeURIComponent(currentUsername)}&acik=true`;
 
   const fetchMontaj = async () => {
     setLoading(true);
     try {
-      const response = await fetch('/api/montaj');
-      if (!response.ok) {
+      const page = await fetchListPage(assignedUrl, { limit: refreshLimit(items.length) });
+      if (!page) {
         setMessage({ type: 'error', text: 'Montaj listesi alınamadı.' });
         setLoading(false);
         return;
       }
 
-      const data = await response.json();
-      const normalizedData = Array.isArray(data) ? data : [];
-      setItems(normalizedData.filter((item) => {
-        const assigned = String(item?.atanan_kullanici_username || '').trim().toLowerCase();
-        return !item?.kapatildi && assigned === currentUsername;
-      }));
+      setItems(page.items);
+      setTotal(page.total);
+      setNextCursor(page.next_cursor);
     } catch (error) {
       setMessage({ type: 'error', text: 'Sunucu bağlantısı başarısız.' });
     }
     setLoading(false);
   };
 
+  const loadMoreMontaj = async () => {
+    if (!nextCursor || loadingMore) return;
+    setLoadingMore(true);
+    try {
+      const page = await fetchListPage(assignedUrl, { cursor: nextCursor });
+      if (page) {
+        setItems((prev) => [...prev, ...page.items]);
+        setTotal(page.total);
+        setNextCursor(page.next_cursor);
+      } else {
+        setMessage({ type: 'error', text: 'Montaj listesi alınamadı.' });
+      }
+    } catch (error) {
+      setMessage({ type: 'error', text: 'Sunucu bağlantısı başarısız.' });
+    }
+    setLoadingMore(false);
+  };
+
   useEffect(() => {
     fetchMontaj();
   }, []);
@@ -2276,6 +2533,7 @@ function CihazKurulum() {
       }
 
       setItems((prev) => prev.filter((row) => row.id !== item.id));
+      setTotal((prev) => Math.max(0, prev - 1));
       setMessage({ type: 'success', text: 'Montaj kapatıldı.' });
       setShowCloseModal(false);
       setSelectedMontaj(null);
@@ -2344,7 +2602,7 @@ function CihazKurulum() {
                     {item.belge_f && (
                        <button
                          onClick={async () => {
-                            const response = await fetch(`/api/montaj/${item.id}`);
+                            const response = await fetch(`/api/montaj/${item.id}?full=true`);
                             if (response.ok) {
                               const fullItem = await response.json();
                               setSelectedFatura(fullItem);
@@ -2371,6 +2629,14 @@ function CihazKurulum() {
               </button>
             </div>
           ))}
+
+          <LoadMoreButton
+            shown={items.length}
+            total={total}
+            hasMore={Boolean(nextCursor)}
+            loading={loadingMore}
+            onClick={loadMoreMontaj}
+          />
         </div>
       )}
 
@@ -2515,7 +2781,9 @@ function IrsaliyeListesi() {
 
 // Fatura Yükleme Sayfası - Müşteriler linkle erişir
 function FaturaYukle() {
-  const { customerId } = useParams();
+  const { token } = useParams();
+  const [linkInfo, setLinkInfo] = useState(null);
+  const [linkError, setLinkError] = useState('');
   const [loading, setLoading] = useState(false);
   const [message, setMessage] = useState(null);
   const [selectedFile, setSelectedFile] = useState(null);
@@ -2525,6 +2793,22 @@ function FaturaYukle() {
   const turnstileRef = useRef(null);
   const turnstileWidgetIdRef = useRef(null);
 
+  useEffect(() => {
+    const loadLinkInfo = async () => {
+      try {
+        const response = await fetch(`/api/fatura-upload/${token}`);
+        if (response.ok) {
+          setLinkInfo(await response.json());
+        } else {
+          setLinkError((await response.text()) || 'Fatura bağlantısı geçersiz');
+        }
+      } catch (error) {
+        setLinkError('Bağlantı bilgisi alınamadı');
+      }
+    };
+    loadLinkInfo();
+  }, [token]);
+
   useEffect(() => {
     if (!turnstileSiteKey || !turnstileRef.current) return;
 
@@ -2594,7 +2878,7 @@ function FaturaYukle() {
       reader.onload = async (event) => {
         const base64 = event.target.result;
 
-        const response = await fetch(`/api/fatura-upload/${customerId}`, {
+        const response = await fetch(`/api/fatura-upload/${token}`, {
           method: 'PUT',
           headers: { 'Content-Type': 'application/json' },
           body: JSON.stringify({
@@ -2641,6 +2925,22 @@ function FaturaYukle() {
 
         {/* Content */}
         <div className="p-8">
+          {linkError && (
+            <div className="mb-6 p-4 rounded-lg bg-red-50 border border-red-200">
+              <p className="text-sm font-medium text-red-800">❌ {linkError}</p>
+            </div>
+          )}
+
+          {linkInfo && (
+            <div className="mb-6 p-4 rounded-lg bg-blue-50 border border-blue-200 text-sm text-gray-700">
+              <p><span className="font-semibold">Müşteri:</span> {linkInfo.ad_soyad}</p>
+              {linkInfo.cihaz && <p><span className="font-semibold">Cihaz:</span> {linkInfo.cihaz}</p>}
+              {linkInfo.fatura_yuklendi && (
+                <p className="mt-2 text-green-700">Bu kayıt için fatura daha önce yüklendi; yeni yükleme öncekinin yerine geçer.</p>
+              )}
+            </div>
+          )}
+
           {/* File Upload Area */}
           <div className="mb-6">
             <label htmlFor="file-upload" className="block">
@@ -2695,7 +2995,7 @@ function FaturaYukle() {
           {/* Upload Button */}
           <button
             onClick={handleUpload}
-            disabled={loading || !selectedFile || !turnstileToken}
+            disabled={loading || !selectedFile || !turnstileToken || !linkInfo}
             className="w-full py-3 px-4 bg-gradient-to-r from-blue-600 to-indigo-600 text-white font-semibold rounded-lg hover:opacity-90 transition disabled:opacity-50 disabled:cursor-not-allowed"
           >
             {loading ? '⏳ Yükleniyor...' : '📤 Yükle'}
@@ -2713,6 +3013,222 @@ function FaturaYukle() {
   );
 }
 
+// Cihaz Durum Takip Sayfası - Müşteriler SMS'teki linkle erişir
+function Takip() {
+  const { token } = useParams();
+  const [tracking, setTracking] = useState(null);
+  const [error, setError] = useState('');
+
+  useEffect(() => {
+    const loadTracking = async () => {
+      try {
+        const response = await fetch(`/api/track/${token}`);
+        if (response.ok) {
+          setTracking(await response.json());
+        } else {
+          setError((await response.text()) || 'Kayıt bulunamadı');
+        }
+      } catch (err) {
+        setError('Durum bilgisi alınamadı');
+      }
+    };
+    loadTracking();
+  }, [token]);
+
+  const formatDate = (value) => (value ? new Date(value).toLocaleString('tr-TR') : '');
+
+  return (
+    <div className="min-h-screen bg-gradient-to-br from-blue-50 to-indigo-100 py-8 px-4">
+      <div className="max-w-md mx-auto bg-white rounded-2xl shadow-2xl overflow-hidden">
+        <div className="bg-gradient-to-r from-blue-600 to-indigo-600 px-6 py-8 text-center">
+          <div className="text-5xl mb-3">🔧</div>
+          <h1 className="text-2xl font-bold text-white">Cihaz Durumu</h1>
+          <p className="text-blue-100 mt-2 text-sm">Teknik Elektronik servis takibi</p>
+        </div>
+
+        <div className="p-8">
+          {error && (
+            <div className="p-4 rounded-lg bg-red-50 border border-red-200">
+              <p className="text-sm font-medium text-red-800">❌ {error}</p>
+            </div>
+          )}
+
+          {!error && !tracking && <p className="text-center text-gray-500">⏳ Yükleniyor...</p>}
+
+          {tracking && (
+            <>
+              <div className="mb-6 text-sm text-gray-700">
+                <p><span className="font-semibold">Müşteri:</span> {tracking.ad_soyad}</p>
+                <p><span className="font-semibold">Cihaz:</span> {tracking.cihaz}</p>
+              </div>
+
+              <div className="mb-6 p-4 rounded-lg bg-blue-50 border border-blue-200 text-center">
+                <p className="text-lg font-semibold text-blue-800">{tracking.status}</p>
+                {tracking.teslim_son_tarih && (
+                  <p className="text-sm text-gray-700 mt-2">
+                    Son teslim alma tarihi: <strong>{new Date(tracking.teslim_son_tarih).toLocaleDateString('tr-TR')}</strong>
+                  </p>
+                )}
+              </div>
+
+              <ol className="relative border-l border-gray-200 ml-2">
+                {tracking.timeline.map((step, index) => (
+                  <li key={`${step.at}-${index}`} className="mb-4 ml-4">
+                    <div className="absolute w-3 h-3 bg-blue-500 rounded-full -left-1.5 mt-1.5 border border-white" />
+                    <p className="text-sm font-medium text-gray-800">{step.status}</p>
+                    <time className="text-xs text-gray-500">{formatDate(step.at)}</time>
+                  </li>
+                ))}
+              </ol>
+
+              <p className="mt-6 text-xs text-gray-500 text-center">Sorularınız için: 0416 216 12 62</p>
+            </>
+          )}
+        </div>
+      </div>
+    </div>
+  );
+}
+
+function Anket() {
+  const { token } = useParams();
+  const [survey, setSurvey] = useState(null);
+  const [error, setError] = useState('');
+  const [puan, setPuan] = useState(0);
+  const [yorum, setYorum] = useState('');
+  const [isSubmitting, setIsSubmitting] = useState(false);
+  const [submitError, setSubmitError] = useState('');
+  const [submitted, setSubmitted] = useState(false);
+
+  useEffect(() => {
+    const loadSurvey = async () => {
+      try {
+        const response = await fetch(`/api/survey/${token}`);
+        if (response.ok) {
+          setSurvey(await response.json());
+        } else {
+          setError((await response.text()) || 'Anket bulunamadı');
+        }
+      } catch (err) {
+        setError('Anket bilgisi alınamadı');
+      }
+    };
+    loadSurvey();
+  }, [token]);
+
+  const handleSubmit = async (e) => {
+    e.preventDefault();
+    if (!puan) {
+      setSubmitError('Lütfen 1 ile 5 arasında bir puan seçin');
+      return;
+    }
+
+    setIsSubmitting(true);
+    setSubmitError('');
+    try {
+      const response = await fetch(`/api/survey/${token}`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ puan, yorum: yorum.trim() || null })
+      });
+      if (response.ok) {
+        setSubmitted(true);
+      } else {
+        setSubmitError((await response.text()) || 'Yanıtınız kaydedilemedi');
+      }
+    } catch (err) {
+      setSubmitError('Yanıtınız gönderilemedi');
+    } finally {
+      setIsSubmitting(false);
+    }
+  };
+
+  const isExpired = survey && new Date(survey.expires_at) < new Date();
+
+  return (
+    <div className="min-h-screen bg-gradient-to-br from-blue-50 to-indigo-100 py-8 px-4">
+      <div className="max-w-md mx-auto bg-white rounded-2xl shadow-2xl overflow-hidden">
+        <div className="bg-gradient-to-r from-blue-600 to-indigo-600 px-6 py-8 text-center">
+          <div className="text-5xl mb-3">⭐</div>
+          <h1 className="text-2xl font-bold text-white">Memnuniyet Anketi</h1>
+          <p className="text-blue-100 mt-2 text-sm">Teknik Elektronik servis değerlendirmesi</p>
+        </div>
+
+        <div className="p-8">
+          {error && (
+            <div className="p-4 rounded-lg bg-red-50 border border-red-200">
+              <p className="text-sm font-medium text-red-800">❌ {error}</p>
+            </div>
+          )}
+
+          {!error && !survey && <p className="text-center text-gray-500">⏳ Yükleniyor...</p>}
+
+          {survey && (
+            <>
+              <div className="mb-6 text-sm text-gray-700">
+                <p><span className="font-semibold">Müşteri:</span> {survey.ad_soyad}</p>
+                <p><span className="font-semibold">Cihaz:</span> {survey.cihaz}</p>
+              </div>
+
+              {submitted || survey.yanitlandi ? (
+                <div className="p-4 rounded-lg bg-green-50 border border-green-200 text-center">
+                  <p className="text-sm font-medium text-green-800">✅ Değerlendirmeniz için teşekkür ederiz</p>
+                </div>
+              ) : isExpired ? (
+                <div className="p-4 rounded-lg bg-yellow-50 border border-yellow-200 text-center">
+                  <p className="text-sm font-medium text-yellow-800">Bu anketin süresi dolmuş</p>
+                </div>
+              ) : (
+                <form onSubmit={handleSubmit}>
+                  <p className="text-sm font-medium text-gray-800 mb-3 text-center">Hizmetimizi nasıl değerlendirirsiniz?</p>
+                  <div className="flex justify-center gap-2 mb-6">
+                    {[1, 2, 3, 4, 5].map((value) => (
+                      <button
+                        key={value}
+                        type="button"
+                        onClick={() => setPuan(value)}
+                        className={`text-4xl transition-transform hover:scale-110 ${value <= puan ? 'text-yellow-400' : 'text-gray-300'}`}
+                        aria-label={`${value} puan`}
+                      >
+                        ★
+                      </button>
+                    ))}
+                  </div>
+
+                  <textarea
+                    value={yorum}
+                    onChange={(e) => setYorum(e.target.value)}
+                    maxLength={1000}
+                    rows={4}
+                    placeholder="Yorumunuz (isteğe bağlı)"
+                    className="w-full p-3 mb-4 text-sm border border-gray-300 rounded-lg focus:ring-blue-500 focus:border-blue-500"
+                  />
+
+                  {submitError && (
+                    <div className="mb-4 p-3 rounded-lg bg-red-50 border border-red-200">
+                      <p className="text-sm font-medium text-red-800">❌ {submitError}</p>
+                    </div>
+                  )}
+
+                  <button
+                    type="submit"
+                    disabled={isSubmitting}
+                    className="w-full py-3 text-white font-semibold rounded-lg bg-gradient-to-r from-blue-600 to-indigo-600 hover:from-blue-700 hover:to-indigo-700 disabled:opacity-50"
+                  >
+                    {isSubmitting ? '⏳ Gönderiliyor...' : 'Gönder'}
+                  </button>
+                </form>
+              )}
+
+              <p className="mt-6 text-xs text-gray-500 text-center">Sorularınız için: 0416 216 12 62</p>
+            </>
+          )}
+        </div>
+      </div>
+    </div>
+  );
+}
+
 function Edit() {
   const { customerId } = useParams();
   const navigate = useNavigate();
@@ -2730,6 +3246,7 @@ function Edit() {
     musteri_sikayeti: '',
     not: '',
     teknisyen_aciklamasi: '',
+    parca_bilgisi: '',
     tamir_fisi_no: '',
     status: '',
     belge_f: null,
@@ -2756,9 +3273,8 @@ function Edit() {
   const fetchCustomer = async () => {
     let hasChanged = false;
     try {
-      const response = await fetch(`/api/musteri-kabul`);
-      const data = await response.json();
-      const found = data.find(c => c.id === customerId);
+      const response = await fetch(`/api/musteri-kabul/${customerId}`);
+      const found = response.ok ? await response.json() : null;
       if (found) {
         const nextForm = {
           ad_soyad: found.ad_soyad || '',
@@ -2768,6 +3284,7 @@ function Edit() {
           musteri_sikayeti: found.musteri_sikayeti || '',
           not: found.not || '',
           teknisyen_aciklamasi: found.teknisyen_aciklamasi || '',
+          parca_bilgisi: found.parca_bilgisi || '',
           tamir_fisi_no: found.tamir_fisi_no || '',
           status: statusStringToId(found.status) || '',
           belge_f: found.belge_f || null,
@@ -2821,7 +3338,7 @@ function Edit() {
         navigate(-1);
       } else {
         const errorText = await response.text();
-        alert('❌ Güncelleme başarısız: ' + response.statusText);
+        alert('❌ Güncelleme başarısız: ' + (errorText || response.statusText));
       }
     } catch (error) {
       console.error('Edit error:', error);
@@ -2853,6 +3370,25 @@ function Edit() {
     }
   };
 
+  // Eski fatura bağlantılarını geçersiz kılar ve yenisini gösterir
+  const handleReissueFaturaLink = async () => {
+    if (!customerId) return;
+    if (!window.confirm('Müşteriye gönderilmiş eski fatura bağlantıları geçersiz olacak. Devam edilsin mi?')) return;
+
+    try {
+      const response = await fetch(`/api/musteri-kabul/${customerId}/fatura-link`, { method: 'POST' });
+      if (!response.ok) {
+        alert(`❌ Bağlantı oluşturulamadı: ${(await response.text()) || response.statusText}`);
+        return;
+      }
+      const data = await response.json();
+      window.prompt('Yeni fatura yükleme bağlantısı:', data.link);
+    } catch (error) {
+      console.error('Fatura link reissue error:', error);
+      alert('❌ Bağlantı oluşturulurken hata oluştu');
+    }
+  };
+
   if (isLoading) {
     return (
       <div className="flex items-center justify-center p-6">
@@ -2902,6 +3438,13 @@ function Edit() {
             >
               {isResendingSms ? 'SMS Gönderiliyor...' : 'SMS Tekrar Gönder'}
             </button>
+            <button
+              type="button"
+              onClick={handleReissueFaturaLink}
+              className="px-3 py-2 rounded-lg border border-gray-300 text-sm text-gray-700 hover:bg-gray-50"
+            >
+              Fatura Linki Yenile
+            </button>
           <div className="flex gap-1.5 md:ml-auto">
             {[['F', 'belge_f'], ['G', 'belge_g'], ['Ü', 'belge_u'], ['A', 'belge_a']].map(([label, fieldName]) => (
               <div
@@ -2911,7 +3454,7 @@ function Edit() {
                 title={label}
                 onClick={() => {
                   if (editForm[fieldName]) {
-                    setSelectedBelgeData({ imageData: editForm[fieldName], type: label.charAt(0), adi: customer?.ad_soyad, customerId });
+                    setSelectedBelgeData({ imageData: fullAttachmentUrl(editForm[fieldName]), type: label.charAt(0), adi: customer?.ad_soyad, customerId });
                     setShowBelgeModal(true);
                   }
                 }}
@@ -3046,6 +3589,16 @@ function Edit() {
                 rows="5"
               />
             </div>
+            <div className="col-span-2">
+              <label className="block text-sm font-medium mb-2" style={{ color: themeColor }}>Parça Bilgisi</label>
+              <textarea
+                value={editForm.parca_bilgisi}
+                onChange={(e) => setEditForm({ ...editForm, parca_bilgisi: e.target.value })}
+                className="w-full px-3 py-2 border border-gray-300 rounded-lg focus:outline-none focus:ring-2 resize-none"
+                style={{ '--tw-ring-color': themeColor }}
+                rows="2"
+              />
+            </div>
             <div className="col-span-2">
               <label className="block text-sm font-medium mb-2" style={{ color: themeColor }}>Durum</label>
               <select
@@ -3414,8 +3967,15 @@ function Ayarlar({ themeColor, setThemeColor, onlyThemeModal = false }) {
     setUserMessage(null);
 
     try {
+      const otpHeaders = await requestDeleteOtpHeaders('user', user.id);
+      if (!otpHeaders) {
+        setUserSaving(false);
+        return;
+      }
+
       const response = await fetch(`/api/users/${user.id}`, {
         method: 'DELETE',
+        headers: otpHeaders,
       });
 
       if (!response.ok) {
@@ -3736,7 +4296,9 @@ function Layout({ themeColor, setThemeColor }) {
   // Hide navigation on specific pages
   const isLoginPage = location.pathname === '/login';
   const isInvoiceUploadPage = location.pathname.startsWith('/fatura/');
-  const hideNavigation = isLoginPage || isInvoiceUploadPage;
+  const isTrackingPage = location.pathname.startsWith('/takip/');
+  const isSurveyPage = location.pathname.startsWith('/anket/');
+  const hideNavigation = isLoginPage || isInvoiceUploadPage || isTrackingPage || isSurveyPage;
 
   useEffect(() => {
     initFlowbite();
@@ -3895,7 +4457,9 @@ function Layout({ themeColor, setThemeColor }) {
            <Route path="/irsaliye/listesi" element={<PrivateRoute><IrsaliyeListesi /></PrivateRoute>} />
            <Route path="/ayarlar" element={<PrivateRoute><Ayarlar themeColor={themeColor} setThemeColor={setThemeColor} /></PrivateRoute>} />
            <Route path="/tema" element={<PrivateRoute><Ayarlar themeColor={themeColor} setThemeColor={setThemeColor} onlyThemeModal={true} /></PrivateRoute>} />
-           <Route path="/fatura/:customerId" element={<FaturaYukle />} />
+           <Route path="/fatura/:token" element={<FaturaYukle />} />
+           <Route path="/takip/:token" element={<Takip />} />
+           <Route path="/anket/:token" element={<Anket />} />
          </Routes>
 
         {/* Belge Modal */}
@@ -3966,25 +4530,21 @@ function Layout({ themeColor, setThemeColor }) {
                           if (!file || !selectedBelgeData) return;
                           
                           try {
-                            const reader = new FileReader();
-                            reader.onload = async (event) => {
-                              const base64Data = event.target?.result;
-                              const response = await fetch(`/api/musteri-kabul/${selectedBelgeData.customerId}`, {
-                                method: 'PUT',
-                                headers: { 'Content-Type': 'application/json' },
-                                body: JSON.stringify({ [field]: base64Data })
-                              });
-                              
-                              if (response.ok || response.status === 204) {
-                                setSelectedBelgeData((prev) => prev ? { ...prev, imageData: base64Data, type: letter } : prev);
-                                triggerDataRefresh();
-                                alert(`✅ ${letter} belgesi yüklendi!`);
-                                setShowBelgeModal(false);
-                              } else {
-                                alert('❌ Yükleme başarısız');
-                              }
-                            };
-                            reader.readAsDataURL(file);
+                            const attachment = await uploadAttachment(file);
+                            const response = await fetch(`/api/musteri-kabul/${selectedBelgeData.customerId}`, {
+                              method: 'PUT',
+                              headers: { 'Content-Type': 'application/json' },
+                              body: JSON.stringify({ [field]: attachment.id })
+                            });
+
+                            if (response.ok || response.status === 204) {
+                              setSelectedBelgeData((prev) => prev ? { ...prev, imageData: attachment.url, type: letter } : prev);
+                              triggerDataRefresh();
+                              alert(`✅ ${letter} belgesi yüklendi!`);
+                              setShowBelgeModal(false);
+                            } else {
+                              alert('❌ Yükleme başarısız');
+                            }
                           } catch (error) {
                             console.error('Upload error:', error);
                             alert('❌ Yükleme hatası');
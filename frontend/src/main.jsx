@@ -3,6 +3,32 @@ import { createRoot } from 'react-dom/client'
 import './index.css'
 import App from './App.jsx'
 
+// Tüm /api isteklerine oturum token'ını ekle, süresi dolmuş oturumda login'e dön
+const nativeFetch = window.fetch.bind(window);
+window.fetch = async (input, init = {}) => {
+  const url = typeof input === 'string' ? input : input?.url || '';
+  const token = localStorage.getItem('token');
+  const isApiRequest = url.startsWith('/api/') || url.startsWith(`${window.location.origin}/api/`);
+
+  if (!isApiRequest || !token) {
+    return nativeFetch(input, init);
+  }
+
+  const headers = new Headers(init.headers || (input instanceof Request ? input.headers : undefined));
+  if (!headers.has('Authorization')) {
+    headers.set('Authorization', `Bearer ${token}`);
+  }
+
+  const response = await nativeFetch(input, { ...init, headers });
+  if (response.status === 401 && !url.includes('/api/login')) {
+    localStorage.removeItem('token');
+    localStorage.removeItem('username');
+    localStorage.removeItem('level');
+    window.location.assign('/login');
+  }
+  return response;
+};
+
 createRoot(document.getElementById('root')).render(
   <StrictMode>
     <App />